@@ -0,0 +1,25 @@
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+use datablaze_btree::check::check_file;
+
+// Usage: datablaze_check <tree file>, for example <data dir>/<database>/data.db while the engine
+// is stopped
+fn main() -> ExitCode {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("Usage: datablaze_check <tree file>");
+        return ExitCode::from(2);
+    };
+    let report = match check_file(Path::new(&path)) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Failed to check {}: {}", path, e);
+            return ExitCode::from(2);
+        }
+    };
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    println!("Checked {} pages of {}, found {} problems", report.page_count, path, report.problems.len());
+    if report.is_ok() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
@@ -0,0 +1,432 @@
+﻿use std::mem;
+use std::ops::Bound;
+use std::path::Path;
+use crate::exceptions::BTreeError;
+use crate::iterator::{prefix_end, BIter, BTreeRange, SeekComparison};
+use crate::node::{BNode, BNODE_INTERNAL, BNODE_LEAF, BNODE_HEADER, BNODE_OVERFLOW, BTREE_MAX_INLINE_VAL_SIZE, BTREE_MAX_KEY_SIZE, BTREE_MAX_VAL_SIZE, BTREE_PAGE_SIZE, OVERFLOW_CAPACITY};
+use crate::pager::{FilePager, MemoryPager, Pager};
+
+// Leaf values start with a tag. An inline value follows the tag directly, an overflow value is
+// stored in a chain of overflow pages and the tag is followed by [length: u64][first page: u64].
+pub(crate) const VALUE_INLINE: u8 = 0;
+pub(crate) const VALUE_OVERFLOW: u8 = 1;
+const OVERFLOW_REFERENCE_SIZE: usize = 1 + 8 + 8;
+
+/// Copy-on-write B+tree. Nodes are never modified in place: every change writes new nodes from
+/// the leaf up to the root and releases the nodes they replace.
+///
+/// Changes are only durable once they are committed. Until then the pager keeps the last
+/// committed version of the tree intact.
+pub struct BTree {
+    root: u64,
+    pager: Box<dyn Pager + Send + Sync>,
+    // Pages replaced by the change in progress, released once the change is done
+    replaced: Vec<u64>,
+    // Pages written by the change in progress, overflow pages included, released if it fails
+    written: Vec<u64>,
+}
+
+impl Default for BTree {
+    fn default() -> Self {
+        BTree::new(Box::new(MemoryPager::default()))
+    }
+}
+
+impl BTree {
+
+    pub fn new(pager: Box<dyn Pager + Send + Sync>) -> BTree {
+        BTree { root: pager.root(), pager, replaced: Vec::new(), written: Vec::new() }
+    }
+
+    /// Open the tree stored in a file, creating the file if it does not exist. At most the given
+    /// number of pages is cached in memory.
+    pub fn open(path: &Path, cache_pages: usize) -> Result<BTree, BTreeError> {
+        Ok(BTree::new(Box::new(FilePager::open(path, cache_pages)?)))
+    }
+
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// Number of pages currently used by the tree
+    pub fn page_count(&self) -> usize {
+        self.pager.page_count()
+    }
+
+    /// Make every change since the last commit durable
+    pub fn commit(&mut self) -> Result<(), BTreeError> {
+        self.pager.commit(self.root)
+    }
+
+    /// Drop every change since the last commit, going back to the committed tree
+    pub fn rollback(&mut self) -> Result<(), BTreeError> {
+        self.pager.rollback()?;
+        self.root = self.pager.root();
+        Ok(())
+    }
+
+    /// Change the number of pages kept in memory at most
+    pub fn set_cache_pages(&mut self, pages: usize) -> Result<(), BTreeError> {
+        self.pager.set_cache_pages(pages)
+    }
+
+    pub fn lookup(&self, key: &[u8]) -> Result<Option<Vec<u8>>, BTreeError> {
+        if self.root == 0 {
+            return Ok(None);
+        }
+        let mut node = self.get_node(self.root)?;
+        loop {
+            let index = node.node_lookup_less_than_or_equal(key);
+            if node.b_type() == BNODE_LEAF {
+                if node.get_key(index) != key {
+                    return Ok(None);
+                }
+                return self.read_value(node.get_value(index)).map(Some);
+            }
+            node = self.get_node(node.get_pointer(index))?;
+        }
+    }
+
+    /// Insert a key, or update its value if the key is already in the tree
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), BTreeError> {
+
+        // Check limit
+        check_key(key)?;
+        if value.len() > BTREE_MAX_VAL_SIZE {
+            return Err(BTreeError::ValueTooLarge(value.len()));
+        }
+        let root = self.insert_root(key, value);
+        self.root = self.finish_change(root)?;
+        Ok(())
+    }
+
+    /// Remove a key, returning whether it was in the tree
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool, BTreeError> {
+        check_key(key)?;
+        if self.root == 0 {
+            return Ok(false);
+        }
+        let root = self.delete_root(key);
+        match self.finish_change(root)? {
+            Some(root) => {
+                self.root = root;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Cursor on the key closest to the given key in the direction of the comparison
+    pub fn seek(&self, key: &[u8], comparison: SeekComparison) -> Result<BIter<'_>, BTreeError> {
+        BIter::seek(self, key, comparison)
+    }
+
+    /// Keys and values between the bounds, in ascending order
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<BTreeRange<'_>, BTreeError> {
+        BTreeRange::ascending(self, start, end)
+    }
+
+    /// Keys and values between the bounds, in descending order
+    pub fn range_rev(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<BTreeRange<'_>, BTreeError> {
+        BTreeRange::descending(self, start, end)
+    }
+
+    /// Keys and values of every key starting with the prefix, in ascending order
+    pub fn prefix(&self, prefix: &[u8]) -> Result<BTreeRange<'_>, BTreeError> {
+        match prefix_end(prefix) {
+            Some(end) => BTreeRange::ascending(self, Bound::Included(prefix), Bound::Excluded(end.as_slice())),
+            None => BTreeRange::ascending(self, Bound::Included(prefix), Bound::Unbounded),
+        }
+    }
+
+    /// Remove every key between the bounds, returning how many were removed
+    pub fn delete_range(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<usize, BTreeError> {
+        let keys = self.range(start, end)?.map(|item| item.map(|(key, _)| key)).collect::<Result<Vec<_>, _>>()?;
+        for key in keys.iter() {
+            self.delete(key)?;
+        }
+        Ok(keys.len())
+    }
+
+    pub(crate) fn get_node(&self, pointer: u64) -> Result<BNode, BTreeError> {
+        self.pager.get_page(pointer)
+    }
+
+    fn new_node(&mut self, node: BNode) -> Result<u64, BTreeError> {
+        assert!(node.size() as usize <= BTREE_PAGE_SIZE);
+        self.new_page(node.into_page())
+    }
+
+    fn new_page(&mut self, node: BNode) -> Result<u64, BTreeError> {
+        let pointer = self.pager.new_page(node)?;
+        self.written.push(pointer);
+        Ok(pointer)
+    }
+
+    // Replaced pages are only released once the whole change succeeded, so a failed change does
+    // not leave the current tree pointing at released pages
+    fn del_node(&mut self, pointer: u64) {
+        self.replaced.push(pointer);
+    }
+
+    // Returns the value as it is stored in a leaf, writing it to overflow pages if it is too large
+    fn write_value(&mut self, value: &[u8]) -> Result<Vec<u8>, BTreeError> {
+        if value.len() <= BTREE_MAX_INLINE_VAL_SIZE {
+            return Ok([&[VALUE_INLINE], value].concat());
+        }
+        // Write the chain back to front, so every page knows the page that follows it
+        let mut next = 0;
+        for piece in value.rchunks(OVERFLOW_CAPACITY) {
+            next = self.new_page(BNode::new_overflow(piece, next))?;
+        }
+        let mut stored = vec![VALUE_OVERFLOW];
+        stored.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        stored.extend_from_slice(&next.to_le_bytes());
+        Ok(stored)
+    }
+
+    /// Read back a value as it is stored in a leaf
+    pub(crate) fn read_value(&self, stored: &[u8]) -> Result<Vec<u8>, BTreeError> {
+        match stored.first() {
+            Some(&VALUE_INLINE) => Ok(stored[1..].to_vec()),
+            Some(&VALUE_OVERFLOW) => {
+                let (length, mut page) = decode_overflow_reference(stored)?;
+                let mut value = Vec::with_capacity(length.min(BTREE_MAX_VAL_SIZE));
+                while value.len() < length {
+                    let node = self.get_overflow_node(page)?;
+                    value.extend_from_slice(node.overflow_data());
+                    page = node.overflow_next();
+                }
+                if value.len() != length || page != 0 {
+                    return Err(BTreeError::Corrupt(format!("Overflow chain does not hold {} bytes", length)));
+                }
+                Ok(value)
+            }
+            // Only the sentinel key has an empty value
+            None => Ok(Vec::new()),
+            Some(tag) => Err(BTreeError::Corrupt(format!("Unknown value tag {}", tag))),
+        }
+    }
+
+    // Release the overflow pages of a value that is replaced or deleted
+    fn release_value(&mut self, stored: &[u8]) -> Result<(), BTreeError> {
+        if stored.first() != Some(&VALUE_OVERFLOW) {
+            return Ok(());
+        }
+        let (_, mut page) = decode_overflow_reference(stored)?;
+        while page != 0 {
+            let node = self.get_overflow_node(page)?;
+            self.del_node(page);
+            page = node.overflow_next();
+        }
+        Ok(())
+    }
+
+    fn get_overflow_node(&self, pointer: u64) -> Result<BNode, BTreeError> {
+        if pointer == 0 {
+            return Err(BTreeError::Corrupt("Overflow chain ends early".to_string()));
+        }
+        let node = self.get_node(pointer)?;
+        if node.b_type() != BNODE_OVERFLOW {
+            return Err(BTreeError::Corrupt(format!("Page {} is not an overflow page", pointer)));
+        }
+        Ok(node)
+    }
+
+    // A change that succeeded releases the pages it replaced, one that failed the pages it wrote,
+    // which nothing points to
+    fn finish_change<T>(&mut self, result: Result<T, BTreeError>) -> Result<T, BTreeError> {
+        let replaced = mem::take(&mut self.replaced);
+        let written = mem::take(&mut self.written);
+        let released = if result.is_ok() { replaced } else { written };
+        for pointer in released {
+            self.pager.del_page(pointer);
+        }
+        result
+    }
+
+    // Returns the pointer to the new root
+    fn insert_root(&mut self, key: &[u8], value: &[u8]) -> Result<u64, BTreeError> {
+        let value = self.write_value(value)?;
+        // Create root node if needed
+        if self.root == 0 {
+            let mut root = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+            root.set_header(BNODE_LEAF, 2);
+            // The empty sentinel key sorts before every other key, so a lookup always finds a kid
+            root.append_key_value(0, 0, &[], &[]);
+            root.append_key_value(1, 0, key, &value);
+            return self.new_node(root);
+        }
+
+        let root = self.get_node(self.root)?;
+        self.del_node(self.root);
+        let node = self.tree_insert(root, key, &value)?;
+        let split = node.node_split_three();
+        if split.len() == 1 {
+            return self.new_node(split.into_iter().next().unwrap());
+        }
+        // The root was split, add a new level
+        let mut root = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+        root.set_header(BNODE_INTERNAL, split.len() as u16);
+        for (index, kid) in split.into_iter().enumerate() {
+            let key = kid.get_key(0).to_vec();
+            let pointer = self.new_node(kid)?;
+            root.append_key_value(index as u16, pointer, &key, &[]);
+        }
+        self.new_node(root)
+    }
+
+    // Returns the pointer to the new root, or None when the key is not in the tree
+    fn delete_root(&mut self, key: &[u8]) -> Result<Option<u64>, BTreeError> {
+        let root = self.get_node(self.root)?;
+        let Some(updated) = self.tree_delete(root, key)? else {
+            return Ok(None);
+        };
+        self.del_node(self.root);
+        if updated.b_type() == BNODE_INTERNAL && updated.n_keys() == 1 {
+            // The root has a single kid left, remove a level
+            return Ok(Some(updated.get_pointer(0)));
+        }
+        self.new_node(updated).map(Some)
+    }
+
+    fn tree_insert(&mut self, node: BNode, key: &[u8], value: &[u8]) -> Result<BNode, BTreeError> {
+        // Extra size allows to exceed 1 page temporarily
+        let mut new = BNode::new(vec![0; BTREE_PAGE_SIZE * 2]);
+        // Where to insert the key?
+        let index = node.node_lookup_less_than_or_equal(key);
+        if node.b_type() == BNODE_LEAF {
+            if key == node.get_key(index) {
+                self.release_value(node.get_value(index))?;
+                new.leaf_update(&node, index, key, value);
+            } else {
+                new.leaf_insert(&node, index + 1, key, value);
+            }
+        } else { // BNODE_INTERNAL
+            // Recursive insertion to the kid node
+            let kid_pointer = node.get_pointer(index);
+            let kid_node = self.get_node(kid_pointer)?;
+            let kid_node = self.tree_insert(kid_node, key, value)?;
+            self.del_node(kid_pointer);
+            // After insert, split the result
+            let kids = kid_node.node_split_three();
+            self.node_replace_kid(&mut new, &node, index, kids)?;
+        }
+        Ok(new)
+    }
+
+    fn node_replace_kid(&mut self, new: &mut BNode, old: &BNode, index: u16, kids: Vec<BNode>) -> Result<(), BTreeError> {
+
+        let n = kids.len() as u16;
+        new.set_header(BNODE_INTERNAL, old.n_keys() + n - 1);
+        new.append_range(old, 0, 0, index);
+        for (i, kid) in kids.into_iter().enumerate() {
+            let key = kid.get_key(0).to_vec();
+            let pointer = self.new_node(kid)?;
+            new.append_key_value(index + i as u16, pointer, &key, &[]);
+        }
+        new.append_range(old, index + n, index + 1, old.n_keys() - (index + 1));
+        Ok(())
+    }
+
+    // Returns None when the key is not in the tree, so nothing has to be rewritten
+    fn tree_delete(&mut self, node: BNode, key: &[u8]) -> Result<Option<BNode>, BTreeError> {
+        let index = node.node_lookup_less_than_or_equal(key);
+        if node.b_type() == BNODE_LEAF {
+            if key != node.get_key(index) {
+                return Ok(None);
+            }
+            self.release_value(node.get_value(index))?;
+            let mut new = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+            new.leaf_delete(&node, index);
+            return Ok(Some(new));
+        }
+        self.node_delete(node, index, key)
+    }
+
+    fn node_delete(&mut self, node: BNode, index: u16, key: &[u8]) -> Result<Option<BNode>, BTreeError> {
+        // Recurse into the kid
+        let kid_pointer = node.get_pointer(index);
+        let kid_node = self.get_node(kid_pointer)?;
+        let Some(updated) = self.tree_delete(kid_node, key)? else {
+            return Ok(None);
+        };
+        self.del_node(kid_pointer);
+
+        // Merge the updated kid with a sibling if it became too small
+        let mut new = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+        match self.should_merge(&node, index, &updated)? {
+            Some(Merge::Left(sibling)) => {
+                let mut merged = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+                merged.node_merge(&sibling, &updated);
+                self.del_node(node.get_pointer(index - 1));
+                let key = merged.get_key(0).to_vec();
+                let pointer = self.new_node(merged)?;
+                new.node_replace_two_kids(&node, index - 1, pointer, &key);
+            }
+            Some(Merge::Right(sibling)) => {
+                let mut merged = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+                merged.node_merge(&updated, &sibling);
+                self.del_node(node.get_pointer(index + 1));
+                let key = merged.get_key(0).to_vec();
+                let pointer = self.new_node(merged)?;
+                new.node_replace_two_kids(&node, index, pointer, &key);
+            }
+            None if updated.n_keys() == 0 => {
+                // An empty kid without siblings empties the parent as well
+                assert!(node.n_keys() == 1 && index == 0);
+                new.set_header(BNODE_INTERNAL, 0);
+            }
+            None => {
+                self.node_replace_kid(&mut new, &node, index, vec![updated])?;
+            }
+        }
+        Ok(Some(new))
+    }
+
+    fn should_merge(&self, node: &BNode, index: u16, updated: &BNode) -> Result<Option<Merge>, BTreeError> {
+        if updated.size() as usize > BTREE_PAGE_SIZE / 4 {
+            return Ok(None);
+        }
+        if index > 0 {
+            let sibling = self.get_node(node.get_pointer(index - 1))?;
+            let merged = sibling.size() as usize + updated.size() as usize - BNODE_HEADER;
+            if merged <= BTREE_PAGE_SIZE {
+                return Ok(Some(Merge::Left(sibling)));
+            }
+        }
+        if index + 1 < node.n_keys() {
+            let sibling = self.get_node(node.get_pointer(index + 1))?;
+            let merged = sibling.size() as usize + updated.size() as usize - BNODE_HEADER;
+            if merged <= BTREE_PAGE_SIZE {
+                return Ok(Some(Merge::Right(sibling)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+enum Merge {
+    Left(BNode),
+    Right(BNode),
+}
+
+pub(crate) fn decode_overflow_reference(stored: &[u8]) -> Result<(usize, u64), BTreeError> {
+    if stored.len() != OVERFLOW_REFERENCE_SIZE {
+        return Err(BTreeError::Corrupt("Malformed overflow value".to_string()));
+    }
+    let length = u64::from_le_bytes(stored[1..9].try_into().unwrap()) as usize;
+    let page = u64::from_le_bytes(stored[9..OVERFLOW_REFERENCE_SIZE].try_into().unwrap());
+    Ok((length, page))
+}
+
+// The empty key is the sentinel every tree starts with, so it can not be used
+fn check_key(key: &[u8]) -> Result<(), BTreeError> {
+    if key.is_empty() {
+        return Err(BTreeError::EmptyKey);
+    }
+    if key.len() > BTREE_MAX_KEY_SIZE {
+        return Err(BTreeError::KeyTooLarge(key.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use crate::exceptions::BTreeError;
+use crate::node::{BNode, BTREE_PAGE_SIZE};
+
+struct Frame {
+    page: u64,
+    node: BNode,
+    pins: u32,
+    // Set on every use and cleared by the clock hand, giving used pages a second chance
+    referenced: bool,
+    dirty: bool,
+}
+
+/// Bounded cache of the pages of a file. When it is full, a page is evicted with the CLOCK
+/// algorithm; dirty pages are written back to the file when they are evicted or flushed. Pinned
+/// pages are skipped by eviction.
+pub struct BufferPool {
+    capacity: usize,
+    frames: Vec<Frame>,
+    pages: HashMap<u64, usize>,
+    hand: usize,
+}
+
+impl BufferPool {
+    pub fn new(capacity: usize) -> BufferPool {
+        BufferPool { capacity: capacity.max(1), frames: Vec::new(), pages: HashMap::new(), hand: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the number of pages the pool holds at most, evicting pages until it fits. Pinned
+    /// pages stay, so the pool can be left past its capacity until they are unpinned.
+    pub fn set_capacity(&mut self, file: &File, capacity: usize) -> Result<(), BTreeError> {
+        self.capacity = capacity.max(1);
+        while self.frames.len() > self.capacity {
+            let Some(index) = self.evict(file)? else {
+                break;
+            };
+            self.remove(index);
+        }
+        Ok(())
+    }
+
+    /// Number of pages currently cached
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn is_dirty(&self, page: u64) -> bool {
+        self.pages.get(&page).is_some_and(|&index| self.frames[index].dirty)
+    }
+
+    /// A cached page, marked as used. Returns None when the page is not cached.
+    pub fn get(&mut self, page: u64) -> Option<&BNode> {
+        let frame = &mut self.frames[*self.pages.get(&page)?];
+        frame.referenced = true;
+        Some(&frame.node)
+    }
+
+    /// Pin a cached page, so it is not evicted until it is unpinned. Returns None when the page is
+    /// not cached.
+    pub fn pin(&mut self, page: u64) -> Option<&BNode> {
+        let frame = &mut self.frames[*self.pages.get(&page)?];
+        frame.pins += 1;
+        frame.referenced = true;
+        Some(&frame.node)
+    }
+
+    pub fn unpin(&mut self, page: u64) {
+        if let Some(&index) = self.pages.get(&page) {
+            let frame = &mut self.frames[index];
+            assert!(frame.pins > 0, "Page {} is not pinned", page);
+            frame.pins -= 1;
+        }
+    }
+
+    /// Cache a page, evicting another one if the pool is full. A dirty page is written back to the
+    /// file before it is evicted. The pool only grows past its capacity when every page is pinned.
+    pub fn insert(&mut self, file: &File, page: u64, node: BNode, dirty: bool) -> Result<(), BTreeError> {
+        assert_eq!(node.data().len(), BTREE_PAGE_SIZE);
+        let frame = Frame { page, node, pins: 0, referenced: true, dirty };
+        if let Some(&index) = self.pages.get(&page) {
+            let old = &self.frames[index];
+            self.frames[index] = Frame { pins: old.pins, dirty: dirty || old.dirty, ..frame };
+            return Ok(());
+        }
+        let index = if self.frames.len() < self.capacity { None } else { self.evict(file)? };
+        match index {
+            Some(index) => self.frames[index] = frame,
+            None => self.frames.push(frame),
+        }
+        self.pages.insert(page, index.unwrap_or(self.frames.len() - 1));
+        Ok(())
+    }
+
+    /// Drop a page from the pool without writing it back
+    pub fn discard(&mut self, page: u64) {
+        let Some(index) = self.pages.remove(&page) else {
+            return;
+        };
+        assert_eq!(self.frames[index].pins, 0, "Page {} is still pinned", page);
+        self.remove(index);
+    }
+
+    /// Write every dirty page back to the file
+    pub fn flush(&mut self, file: &File) -> Result<(), BTreeError> {
+        for frame in self.frames.iter_mut().filter(|frame| frame.dirty) {
+            write_page(file, frame.page, frame.node.data())?;
+            frame.dirty = false;
+        }
+        Ok(())
+    }
+
+    // Remove a frame that is no longer in the page map, moving the last frame into its place
+    fn remove(&mut self, index: usize) {
+        self.frames.swap_remove(index);
+        if let Some(moved) = self.frames.get(index) {
+            self.pages.insert(moved.page, index);
+        }
+        if self.hand >= self.frames.len() {
+            self.hand = 0;
+        }
+    }
+
+    // Returns the index of the freed frame, or None when every page is pinned
+    fn evict(&mut self, file: &File) -> Result<Option<usize>, BTreeError> {
+        // The first sweep may only clear reference bits, the second then finds a victim
+        for _ in 0..self.frames.len() * 2 {
+            let index = self.hand;
+            self.hand = (self.hand + 1) % self.frames.len();
+            let frame = &mut self.frames[index];
+            if frame.pins > 0 {
+                continue;
+            }
+            if frame.referenced {
+                frame.referenced = false;
+                continue;
+            }
+            if frame.dirty {
+                write_page(file, frame.page, frame.node.data())?;
+            }
+            self.pages.remove(&frame.page);
+            return Ok(Some(index));
+        }
+        Ok(None)
+    }
+}
+
+pub(crate) fn read_page(file: &File, page: u64) -> Result<Vec<u8>, BTreeError> {
+    let mut data = vec![0; BTREE_PAGE_SIZE];
+    let mut file = file;
+    file.seek(SeekFrom::Start(page * BTREE_PAGE_SIZE as u64))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+pub(crate) fn write_page(file: &File, page: u64, data: &[u8]) -> Result<(), BTreeError> {
+    assert_eq!(data.len(), BTREE_PAGE_SIZE);
+    let mut file = file;
+    file.seek(SeekFrom::Start(page * BTREE_PAGE_SIZE as u64))?;
+    file.write_all(data)?;
+    Ok(())
+}
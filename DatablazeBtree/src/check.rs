@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use crate::btree::{decode_overflow_reference, VALUE_INLINE, VALUE_OVERFLOW};
+use crate::buffer_pool::read_page;
+use crate::exceptions::BTreeError;
+use crate::node::{BNode, BNODE_INTERNAL, BNODE_LEAF, BNODE_OVERFLOW};
+use crate::pager::{decode_free_list_page, read_meta, META_PAGES};
+
+/// Problem found while checking a tree file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckProblem {
+    /// The page can not be read, fails its checksum or has a malformed layout
+    CorruptPage { page: u64, reason: String },
+    /// The page is intact, but breaks a rule of the tree
+    BrokenInvariant { page: u64, reason: String },
+    /// The page is neither used by the tree nor listed as free
+    LeakedPage(u64),
+}
+
+impl fmt::Display for CheckProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckProblem::CorruptPage { page, reason } => write!(f, "Page {} is corrupt: {}", page, reason),
+            CheckProblem::BrokenInvariant { page, reason } => write!(f, "Page {} is invalid: {}", page, reason),
+            CheckProblem::LeakedPage(page) => write!(f, "Page {} is leaked", page),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckReport {
+    /// Pages in the file, including the meta pages
+    pub page_count: u64,
+    pub problems: Vec<CheckProblem>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Walk the last committed tree of a tree file and its free list, reporting every page that is
+/// corrupt, breaks the ordering or layout rules of the tree, or is not accounted for. Only fails
+/// when the file or both of its meta pages can not be read at all. A commit reuses pages and
+/// rewrites a meta page, so the database has to be closed while its file is checked.
+pub fn check_file(path: &Path) -> Result<CheckReport, BTreeError> {
+    let file = File::open(path)?;
+    let meta = read_meta(&file)
+        .map_err(|reason| BTreeError::Corrupt(format!("{}: {}", path.display(), reason)))?;
+    let mut checker = Checker { file, page_count: meta.page_count, used: HashSet::new(), leaf_depth: None, problems: Vec::new() };
+
+    if meta.root != 0 && checker.claim(0, meta.root) {
+        checker.check_node(meta.root, b"", None, 0);
+    }
+    checker.check_free_list(meta.free_list_page);
+    for page in META_PAGES..checker.page_count {
+        if !checker.used.contains(&page) {
+            checker.problems.push(CheckProblem::LeakedPage(page));
+        }
+    }
+    Ok(CheckReport { page_count: checker.page_count, problems: checker.problems })
+}
+
+struct Checker {
+    file: File,
+    page_count: u64,
+    // Pages reached so far through the tree or the free list
+    used: HashSet<u64>,
+    // Every leaf has to be at the same depth as the first one found
+    leaf_depth: Option<usize>,
+    problems: Vec<CheckProblem>,
+}
+
+impl Checker {
+    // Record that a page is used, returns false when it should not be followed
+    fn claim(&mut self, from: u64, page: u64) -> bool {
+        if page < META_PAGES || page >= self.page_count {
+            self.broken(from, format!("points to page {}, which is out of bounds", page));
+            return false;
+        }
+        if !self.used.insert(page) {
+            self.broken(from, format!("points to page {}, which is already in use", page));
+            return false;
+        }
+        true
+    }
+
+    fn read_node(&mut self, page: u64) -> Option<BNode> {
+        let node = match read_page(&self.file, page) {
+            Ok(data) => BNode::new(data),
+            Err(e) => {
+                self.corrupt(page, e.to_string());
+                return None;
+            }
+        };
+        if !node.verify_checksum() {
+            self.corrupt(page, "checksum does not match".to_string());
+            return None;
+        }
+        if let Err(reason) = node.check_layout() {
+            self.corrupt(page, reason);
+            return None;
+        }
+        Some(node)
+    }
+
+    // The first key of a node has to equal the key its parent holds for it, and every key has to
+    // be smaller than the key of the next kid of the parent
+    fn check_node(&mut self, page: u64, first_key: &[u8], end: Option<&[u8]>, depth: usize) {
+        let Some(node) = self.read_node(page) else {
+            return;
+        };
+        if ![BNODE_LEAF, BNODE_INTERNAL].contains(&node.b_type()) {
+            self.broken(page, format!("node of type {} in the tree", node.b_type()));
+            return;
+        }
+        if node.get_key(0) != first_key {
+            self.broken(page, "first key does not match the key in its parent".to_string());
+        }
+        for index in 1..node.n_keys() {
+            if node.get_key(index - 1) >= node.get_key(index) {
+                self.broken(page, format!("key {} is not greater than the key before it", index));
+            }
+        }
+        if let Some(end) = end
+            && node.get_key(node.n_keys() - 1) >= end
+        {
+            self.broken(page, "keys reach past the next key in its parent".to_string());
+        }
+
+        if node.b_type() == BNODE_LEAF {
+            match self.leaf_depth {
+                Some(leaf_depth) if leaf_depth != depth => {
+                    self.broken(page, format!("leaf at depth {}, other leaves are at depth {}", depth, leaf_depth));
+                }
+                _ => self.leaf_depth = Some(depth),
+            }
+            for index in 0..node.n_keys() {
+                self.check_value(page, node.get_key(index), node.get_value(index));
+            }
+            return;
+        }
+        for index in 0..node.n_keys() {
+            let kid = node.get_pointer(index);
+            let kid_end = if index + 1 < node.n_keys() { Some(node.get_key(index + 1)) } else { end };
+            if self.claim(page, kid) {
+                self.check_node(kid, node.get_key(index), kid_end, depth + 1);
+            }
+        }
+    }
+
+    fn check_value(&mut self, page: u64, key: &[u8], stored: &[u8]) {
+        match stored.first() {
+            // Only the empty key the tree starts with has no value at all
+            None if key.is_empty() => {}
+            Some(&VALUE_INLINE) => {}
+            Some(&VALUE_OVERFLOW) => match decode_overflow_reference(stored) {
+                Ok((length, first_page)) => self.check_overflow_chain(page, length, first_page),
+                Err(e) => self.broken(page, e.to_string()),
+            },
+            _ => self.broken(page, "value without a valid tag".to_string()),
+        }
+    }
+
+    fn check_overflow_chain(&mut self, leaf: u64, length: usize, first_page: u64) {
+        let (mut from, mut page, mut remaining) = (leaf, first_page, length);
+        while remaining > 0 {
+            if !self.claim(from, page) {
+                return;
+            }
+            let Some(node) = self.read_node(page) else {
+                return;
+            };
+            if node.b_type() != BNODE_OVERFLOW {
+                self.broken(page, format!("node of type {} in an overflow chain", node.b_type()));
+                return;
+            }
+            if node.overflow_data().len() > remaining {
+                self.broken(page, "overflow chain holds more data than its value".to_string());
+                return;
+            }
+            remaining -= node.overflow_data().len();
+            (from, page) = (page, node.overflow_next());
+            if remaining > 0 && page == 0 {
+                self.broken(from, "overflow chain ends early".to_string());
+                return;
+            }
+        }
+        if page != 0 {
+            self.broken(from, "overflow chain continues past the end of its value".to_string());
+        }
+    }
+
+    fn check_free_list(&mut self, first_page: u64) {
+        let (mut from, mut page) = (0, first_page);
+        while page != 0 && self.claim(from, page) {
+            let data = match read_page(&self.file, page) {
+                Ok(data) => data,
+                Err(e) => return self.corrupt(page, e.to_string()),
+            };
+            let (next, entries) = match decode_free_list_page(&data) {
+                Ok(decoded) => decoded,
+                Err(reason) => return self.corrupt(page, format!("free list page {}", reason)),
+            };
+            for entry in entries {
+                self.claim(page, entry);
+            }
+            (from, page) = (page, next);
+        }
+    }
+
+    fn corrupt(&mut self, page: u64, reason: String) {
+        self.problems.push(CheckProblem::CorruptPage { page, reason });
+    }
+
+    fn broken(&mut self, page: u64, reason: String) {
+        self.problems.push(CheckProblem::BrokenInvariant { page, reason });
+    }
+}
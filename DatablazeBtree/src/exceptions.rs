@@ -0,0 +1,33 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+#[derive(Debug)]
+pub enum BTreeError {
+    EmptyKey,
+    KeyTooLarge(usize),
+    ValueTooLarge(usize),
+    Io(io::Error),
+    Corrupt(String),
+    Unsupported(&'static str),
+}
+
+impl Display for BTreeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BTreeError::EmptyKey => write!(f, "Keys can not be empty"),
+            BTreeError::KeyTooLarge(size) => write!(f, "Key of {} bytes is too large", size),
+            BTreeError::ValueTooLarge(size) => write!(f, "Value of {} bytes is too large", size),
+            BTreeError::Io(e) => write!(f, "I/O error: {}", e),
+            BTreeError::Corrupt(reason) => write!(f, "Corrupt tree file: {}", reason),
+            BTreeError::Unsupported(operation) => write!(f, "Not supported: {}", operation),
+        }
+    }
+}
+
+impl std::error::Error for BTreeError {}
+
+impl From<io::Error> for BTreeError {
+    fn from(e: io::Error) -> Self {
+        BTreeError::Io(e)
+    }
+}
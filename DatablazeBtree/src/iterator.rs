@@ -0,0 +1,234 @@
+use std::ops::Bound;
+use crate::btree::BTree;
+use crate::exceptions::BTreeError;
+use crate::node::{BNode, BNODE_INTERNAL};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekComparison {
+    LessThan,
+    LessThanOrEqual,
+    GreaterThanOrEqual,
+    GreaterThan,
+}
+
+/// Cursor over the keys of a tree in order. It holds the path of nodes from the root down to the
+/// current leaf, so it can move to the neighbouring key without searching from the root again.
+pub struct BIter<'a> {
+    tree: &'a BTree,
+    path: Vec<BNode>,
+    positions: Vec<u16>,
+}
+
+impl<'a> BIter<'a> {
+
+    /// Position the cursor on the last key less than or equal to the given key
+    pub(crate) fn seek_less_than_or_equal(tree: &'a BTree, key: &[u8]) -> Result<BIter<'a>, BTreeError> {
+        let mut iter = BIter { tree, path: Vec::new(), positions: Vec::new() };
+        if tree.root() == 0 {
+            return Ok(iter);
+        }
+        let mut node = tree.get_node(tree.root())?;
+        loop {
+            let index = node.node_lookup_less_than_or_equal(key);
+            let kid = (node.b_type() == BNODE_INTERNAL).then(|| node.get_pointer(index));
+            iter.path.push(node);
+            iter.positions.push(index);
+            match kid {
+                Some(pointer) => node = tree.get_node(pointer)?,
+                None => return Ok(iter),
+            }
+        }
+    }
+
+    pub(crate) fn seek(tree: &'a BTree, key: &[u8], comparison: SeekComparison) -> Result<BIter<'a>, BTreeError> {
+        let mut iter = BIter::seek_less_than_or_equal(tree, key)?;
+        match comparison {
+            SeekComparison::LessThanOrEqual => {}
+            SeekComparison::LessThan => {
+                if iter.is_valid() && iter.key() >= key {
+                    iter.move_prev()?;
+                }
+            }
+            SeekComparison::GreaterThanOrEqual => {
+                if !iter.is_valid() || iter.key() < key {
+                    iter.move_next()?;
+                }
+            }
+            SeekComparison::GreaterThan => {
+                if !iter.is_valid() || iter.key() <= key {
+                    iter.move_next()?;
+                }
+            }
+        }
+        Ok(iter)
+    }
+
+    /// Position the cursor on the last key of the tree
+    pub(crate) fn seek_last(tree: &'a BTree) -> Result<BIter<'a>, BTreeError> {
+        let mut iter = BIter { tree, path: Vec::new(), positions: Vec::new() };
+        if tree.root() == 0 {
+            return Ok(iter);
+        }
+        let mut node = tree.get_node(tree.root())?;
+        loop {
+            let index = node.n_keys() - 1;
+            let kid = (node.b_type() == BNODE_INTERNAL).then(|| node.get_pointer(index));
+            iter.path.push(node);
+            iter.positions.push(index);
+            match kid {
+                Some(pointer) => node = tree.get_node(pointer)?,
+                None => return Ok(iter),
+            }
+        }
+    }
+
+    /// Whether the cursor is on a key. It is not when it moved past either end of the tree.
+    pub fn is_valid(&self) -> bool {
+        match (self.path.last(), self.positions.last()) {
+            // The empty sentinel key in front of the first leaf is not a real key
+            (Some(leaf), Some(&position)) => position < leaf.n_keys() && !leaf.get_key(position).is_empty(),
+            _ => false,
+        }
+    }
+
+    pub fn key(&self) -> &[u8] {
+        assert!(self.is_valid());
+        self.path.last().unwrap().get_key(*self.positions.last().unwrap())
+    }
+
+    /// The value under the cursor, read from overflow pages if it was too large for the leaf
+    pub fn value(&self) -> Result<Vec<u8>, BTreeError> {
+        assert!(self.is_valid());
+        self.tree.read_value(self.path.last().unwrap().get_value(*self.positions.last().unwrap()))
+    }
+
+    /// Move to the next key. Moving past the last key leaves the cursor invalid.
+    pub fn move_next(&mut self) -> Result<(), BTreeError> {
+        if self.path.is_empty() {
+            return Ok(());
+        }
+        // Find the lowest level that can move right
+        let leaf_level = self.path.len() - 1;
+        let mut level = leaf_level;
+        while self.positions[level] + 1 >= self.path[level].n_keys() {
+            if level == 0 {
+                self.positions[leaf_level] = self.path[leaf_level].n_keys();
+                return Ok(());
+            }
+            level -= 1;
+        }
+        self.positions[level] += 1;
+        // Then walk down the leftmost kids
+        while level < leaf_level {
+            let pointer = self.path[level].get_pointer(self.positions[level]);
+            level += 1;
+            self.path[level] = self.tree.get_node(pointer)?;
+            self.positions[level] = 0;
+        }
+        Ok(())
+    }
+
+    /// Move to the previous key. Moving past the first key leaves the cursor invalid.
+    pub fn move_prev(&mut self) -> Result<(), BTreeError> {
+        if self.path.is_empty() {
+            return Ok(());
+        }
+        // Find the lowest level that can move left
+        let leaf_level = self.path.len() - 1;
+        let mut level = leaf_level;
+        while self.positions[level] == 0 {
+            if level == 0 {
+                // Only the first leaf starts at position 0 on every level, on the sentinel key
+                return Ok(());
+            }
+            level -= 1;
+        }
+        self.positions[level] -= 1;
+        // Then walk down the rightmost kids
+        while level < leaf_level {
+            let pointer = self.path[level].get_pointer(self.positions[level]);
+            level += 1;
+            self.path[level] = self.tree.get_node(pointer)?;
+            self.positions[level] = self.path[level].n_keys() - 1;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the keys and values within a range, in ascending or descending order
+pub struct BTreeRange<'a> {
+    iter: BIter<'a>,
+    // The bound the iteration runs towards
+    stop: Bound<Vec<u8>>,
+    descending: bool,
+    failed: bool,
+}
+
+impl<'a> BTreeRange<'a> {
+    pub(crate) fn ascending(tree: &'a BTree, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<BTreeRange<'a>, BTreeError> {
+        let iter = match start {
+            Bound::Included(key) => BIter::seek(tree, key, SeekComparison::GreaterThanOrEqual)?,
+            Bound::Excluded(key) => BIter::seek(tree, key, SeekComparison::GreaterThan)?,
+            // The sentinel key sorts before everything, so this lands in front of the first key
+            Bound::Unbounded => BIter::seek(tree, &[], SeekComparison::GreaterThan)?,
+        };
+        Ok(BTreeRange { iter, stop: end.map(|key| key.to_vec()), descending: false, failed: false })
+    }
+
+    pub(crate) fn descending(tree: &'a BTree, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<BTreeRange<'a>, BTreeError> {
+        let iter = match end {
+            Bound::Included(key) => BIter::seek(tree, key, SeekComparison::LessThanOrEqual)?,
+            Bound::Excluded(key) => BIter::seek(tree, key, SeekComparison::LessThan)?,
+            Bound::Unbounded => BIter::seek_last(tree)?,
+        };
+        Ok(BTreeRange { iter, stop: start.map(|key| key.to_vec()), descending: true, failed: false })
+    }
+
+    fn within_stop(&self, key: &[u8]) -> bool {
+        match (&self.stop, self.descending) {
+            (Bound::Unbounded, _) => true,
+            (Bound::Included(stop), false) => key <= stop.as_slice(),
+            (Bound::Excluded(stop), false) => key < stop.as_slice(),
+            (Bound::Included(stop), true) => key >= stop.as_slice(),
+            (Bound::Excluded(stop), true) => key > stop.as_slice(),
+        }
+    }
+
+    fn read_and_move(&mut self) -> Result<(Vec<u8>, Vec<u8>), BTreeError> {
+        let item = (self.iter.key().to_vec(), self.iter.value()?);
+        if self.descending {
+            self.iter.move_prev()?;
+        } else {
+            self.iter.move_next()?;
+        }
+        Ok(item)
+    }
+}
+
+impl Iterator for BTreeRange<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>), BTreeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || !self.iter.is_valid() || !self.within_stop(self.iter.key()) {
+            return None;
+        }
+        let item = self.read_and_move();
+        if item.is_err() {
+            // The cursor may be left halfway between two keys, so end the iteration here
+            self.failed = true;
+        }
+        Some(item)
+    }
+}
+
+/// The smallest key that is greater than every key starting with the prefix, if there is one
+pub fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
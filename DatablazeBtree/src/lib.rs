@@ -0,0 +1,10 @@
+pub mod btree;
+pub mod buffer_pool;
+pub mod check;
+pub mod node;
+pub mod exceptions;
+pub mod iterator;
+pub mod pager;
+
+#[cfg(test)]
+mod tests;
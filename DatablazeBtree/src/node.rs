@@ -0,0 +1,325 @@
+﻿pub const BTREE_PAGE_SIZE: usize = 4096;
+pub const BTREE_MAX_KEY_SIZE: usize = 1000;
+// Largest value a leaf holds next to a key of the largest size
+pub const BTREE_MAX_LEAF_VAL_SIZE: usize = 3000;
+// Leaf values start with a one byte tag, larger values go to overflow pages
+pub const BTREE_MAX_INLINE_VAL_SIZE: usize = BTREE_MAX_LEAF_VAL_SIZE - 1;
+// Values are read back into memory whole, so there still is a limit
+pub const BTREE_MAX_VAL_SIZE: usize = 64 * 1024 * 1024;
+pub const BNODE_LEAF : u16 = 0;
+pub const BNODE_INTERNAL : u16 = 1;
+pub const BNODE_OVERFLOW : u16 = 2;
+// The checksum covers the whole page, with the checksum itself counted as zero
+pub const BNODE_CHECKSUM: usize = 4;
+pub const BNODE_HEADER: usize = BNODE_CHECKSUM + 4;
+
+// Overflow pages hold a piece of a value that does not fit in a leaf:
+// | type: u16 | length: u16 | checksum: u32 | next overflow page: u64 | data |
+pub const OVERFLOW_HEADER: usize = BNODE_HEADER + 8;
+pub const OVERFLOW_CAPACITY: usize = BTREE_PAGE_SIZE - OVERFLOW_HEADER;
+
+// Page layout:
+// | type: u16 | n_keys: u16 | checksum: u32 | pointers: n_keys * u64 | offsets: n_keys * u16 | key values |
+// where every key value is | key length: u16 | value length: u16 | key | value |.
+// Internal nodes hold the first key of every kid next to its pointer, and no values.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BNode {
+    data: Vec<u8>,
+}
+
+impl BNode {
+
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn set_header(&mut self, b_type: u16, n_keys: u16) {
+        self.data[0..2].copy_from_slice(&b_type.to_le_bytes());
+        self.data[2..4].copy_from_slice(&n_keys.to_le_bytes());
+    }
+
+    pub fn b_type(&self) -> u16 {
+        decode_header_field(&self.data, 0, 2)
+    }
+    pub fn n_keys(&self) -> u16 {
+        decode_header_field(&self.data, 2, 4)
+    }
+
+    pub fn checksum(&self) -> u32 {
+        u32::from_le_bytes(self.data[BNODE_CHECKSUM..BNODE_HEADER].try_into().unwrap())
+    }
+
+    /// Stamp the checksum of the page into its header, right before it is written to disk
+    pub fn update_checksum(&mut self) {
+        let checksum = page_checksum(&self.data, BNODE_CHECKSUM);
+        self.data[BNODE_CHECKSUM..BNODE_HEADER].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    pub fn verify_checksum(&self) -> bool {
+        self.checksum() == page_checksum(&self.data, BNODE_CHECKSUM)
+    }
+
+    /// Check that the header, offsets and key value lengths of a page read from disk agree with
+    /// each other, so the accessors above can not read past the page
+    pub fn check_layout(&self) -> Result<(), String> {
+        if self.data.len() != BTREE_PAGE_SIZE {
+            return Err(format!("Page holds {} bytes instead of {}", self.data.len(), BTREE_PAGE_SIZE));
+        }
+        match self.b_type() {
+            BNODE_LEAF | BNODE_INTERNAL => {}
+            BNODE_OVERFLOW => {
+                let length = decode_header_field(&self.data, 2, 4) as usize;
+                if length > OVERFLOW_CAPACITY {
+                    return Err(format!("Overflow page claims {} bytes of data", length));
+                }
+                return Ok(());
+            }
+            b_type => return Err(format!("Unknown node type {}", b_type)),
+        }
+        let n_keys = self.n_keys();
+        if n_keys == 0 {
+            return Err("Node holds no keys".to_string());
+        }
+        let key_values = BNODE_HEADER + 10 * n_keys as usize;
+        if key_values > BTREE_PAGE_SIZE {
+            return Err(format!("{} keys do not fit in a page", n_keys));
+        }
+        for index in 0..n_keys {
+            let (offset, next_offset) = (self.get_offset(index) as usize, self.get_offset(index + 1) as usize);
+            let position = key_values + offset;
+            if next_offset < offset + 4 || key_values + next_offset > BTREE_PAGE_SIZE {
+                return Err(format!("Offset of key {} is out of bounds", index + 1));
+            }
+            let key_length = decode_header_field(&self.data, position, position + 2) as usize;
+            let value_length = decode_header_field(&self.data, position + 2, position + 4) as usize;
+            if offset + 4 + key_length + value_length != next_offset {
+                return Err(format!("Length of key {} does not match its offset", index));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_pointer(&self, index: u16) -> u64 {
+        assert!(index < self.n_keys());
+        let position = BNODE_HEADER + 8 * index as usize;
+        u64::from_le_bytes(self.data[position..position + 8].try_into().unwrap())
+    }
+
+    pub fn set_pointer(&mut self, index: u16, pointer: u64) {
+        assert!(index < self.n_keys());
+        let position = BNODE_HEADER + 8 * index as usize;
+        self.data[position..position + 8].copy_from_slice(&pointer.to_le_bytes());
+    }
+
+    pub fn get_offset(&self, index: u16) -> u16 {
+        if index == 0 {
+            return 0
+        }
+        let position = self.offset_position(index);
+        u16::from_le_bytes(self.data[position..position + 2].try_into().unwrap())
+    }
+
+    pub fn set_offset(&mut self, index: u16, offset: u16) {
+        let position = self.offset_position(index);
+        self.data[position..position + 2].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    // The offset of the first key value is always 0 and not stored
+    fn offset_position(&self, index: u16) -> usize {
+        assert!(1 <= index && index <= self.n_keys());
+        BNODE_HEADER + 8 * self.n_keys() as usize + 2 * (index - 1) as usize
+    }
+
+    pub fn key_value_position(&self, index: u16) -> u16 {
+        assert!(index <= self.n_keys());
+        BNODE_HEADER as u16 + 8 * self.n_keys() + 2 * self.n_keys() + self.get_offset(index)
+    }
+
+    pub fn get_key(&self, index: u16) -> &[u8] {
+        assert!(index < self.n_keys());
+        let position = self.key_value_position(index) as usize;
+        let key_length = u16::from_le_bytes(self.data[position..position + 2].try_into().unwrap()) as usize;
+        &self.data[position + 4..position + 4 + key_length]
+    }
+
+    pub fn get_value(&self, index: u16) -> &[u8] {
+        assert!(index < self.n_keys());
+        let position = self.key_value_position(index) as usize;
+        let key_length = u16::from_le_bytes(self.data[position..position + 2].try_into().unwrap()) as usize;
+        let value_length = u16::from_le_bytes(self.data[position + 2..position + 2 + 2].try_into().unwrap()) as usize;
+        &self.data[position + 4 + key_length..position + 4 + key_length + value_length]
+    }
+
+    pub fn append_key_value(&mut self, index: u16, pointer: u64, key: &[u8], value: &[u8]) {
+        // Leaves keep a zero pointer
+        self.set_pointer(index, pointer);
+        let position = self.key_value_position(index) as usize;
+        // Set KV length headers
+        self.data[position..position + 2].copy_from_slice(&(key.len() as u16).to_le_bytes());
+        self.data[position + 2..position + 2 + 2].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        // Set KV data
+        self.data[position + 4..position + 4 + key.len()].copy_from_slice(key);
+        self.data[position + 4 + key.len()..position + 4 + key.len() + value.len()].copy_from_slice(value);
+        // Update offset for next key
+        self.set_offset(index + 1, self.get_offset(index) + 4 + key.len() as u16 + value.len() as u16)
+    }
+
+    pub fn size(&self) -> u16 {
+        self.key_value_position(self.n_keys())
+    }
+
+    pub fn leaf_insert(&mut self, old: &BNode, index: u16, key: &[u8], value: &[u8]) {
+        self.set_header(BNODE_LEAF, old.n_keys() + 1);
+        self.append_range(old, 0, 0, index); // copy keys before index
+        self.append_key_value(index, 0, key, value); // new key
+        self.append_range(old, index + 1, index, old.n_keys() - index); // copy after index
+    }
+
+    pub fn append_range(&mut self, old: &BNode, dest_new: u16, src_old: u16, n: u16) {
+        for i in 0..n {
+            let (dst, src) = (dest_new+i, src_old+i);
+            self.append_key_value(dst, old.get_pointer(src), old.get_key(src), old.get_value(src));
+        }
+    }
+
+    pub fn leaf_update(&mut self, old: &BNode, index: u16, key: &[u8], value: &[u8]) {
+        self.set_header(BNODE_LEAF, old.n_keys());
+        self.append_range(old, 0, 0, index);
+        self.append_key_value(index, 0, key, value);
+        self.append_range(old, index + 1, index + 1, old.n_keys() - (index + 1));
+    }
+
+    pub fn leaf_delete(&mut self, old: &BNode, index: u16) {
+        self.set_header(BNODE_LEAF, old.n_keys() - 1);
+        self.append_range(old, 0, 0, index);
+        self.append_range(old, index, index + 1, old.n_keys() - (index + 1));
+    }
+
+    /// Fill this node with the keys of two neighbouring nodes
+    pub fn node_merge(&mut self, left: &BNode, right: &BNode) {
+        self.set_header(left.b_type(), left.n_keys() + right.n_keys());
+        self.append_range(left, 0, 0, left.n_keys());
+        self.append_range(right, left.n_keys(), 0, right.n_keys());
+    }
+
+    /// Copy an internal node, replacing the kids at index and index + 1 by a single merged kid
+    pub fn node_replace_two_kids(&mut self, old: &BNode, index: u16, pointer: u64, key: &[u8]) {
+        self.set_header(BNODE_INTERNAL, old.n_keys() - 1);
+        self.append_range(old, 0, 0, index);
+        self.append_key_value(index, pointer, key, &[]);
+        self.append_range(old, index + 1, index + 2, old.n_keys() - (index + 2));
+    }
+
+    /// Index of the last key that is less than or equal to the given key. The first key of a node
+    /// is never greater than a key that is looked up in it, so there always is one.
+    pub fn node_lookup_less_than_or_equal(&self, key: &[u8]) -> u16 {
+        let (mut low, mut high) = (1, self.n_keys());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.get_key(middle) <= key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        low - 1
+    }
+
+    pub fn node_split_two(&self) -> (BNode, BNode) {
+
+        assert!(self.n_keys() >= 2);
+        let mut n_left = self.n_keys() /2; // Initial guess
+
+        // Try to fit left half
+        let left_bytes = |n_left: u16| BNODE_HEADER as u16 + 8 * n_left + 2 * n_left + self.get_offset(n_left);
+        while left_bytes(n_left) > BTREE_PAGE_SIZE as u16 {
+            n_left -= 1;
+        }
+        assert!(n_left >= 1);
+
+        // Try to fit right half
+        let right_bytes = |n_left: u16| self.size() - left_bytes(n_left) + BNODE_HEADER as u16;
+        while right_bytes(n_left) > BTREE_PAGE_SIZE as u16 {
+            n_left += 1;
+        }
+        assert!(n_left < self.n_keys());
+        let n_right = self.n_keys() - n_left;
+
+        // New nodes, the left one might still have to be split again
+        let mut left = BNode::new([0; BTREE_PAGE_SIZE * 2].to_vec());
+        left.set_header(self.b_type(), n_left);
+        left.append_range(self, 0, 0, n_left);
+
+        let mut right = BNode::new([0; BTREE_PAGE_SIZE].to_vec());
+        right.set_header(self.b_type(), n_right);
+        right.append_range(self, 0, n_left, n_right);
+        assert!(right.size() <= BTREE_PAGE_SIZE as u16);
+        (left, right)
+    }
+
+    /// Split a node that may have grown past a page into one to three nodes that each fit a page
+    pub fn node_split_three(self) -> Vec<BNode> {
+
+        if self.size() <= BTREE_PAGE_SIZE as u16 {
+            return vec![self.into_page()]
+        }
+        let (left, right) = self.node_split_two();
+        if left.size() <= BTREE_PAGE_SIZE as u16 {
+            return vec![left.into_page(), right]
+        }
+        let (left_left, middle) = left.node_split_two();
+        assert!(left_left.size() <= BTREE_PAGE_SIZE as u16);
+        vec![left_left.into_page(), middle, right]
+    }
+
+    /// Overflow page holding a piece of a value, followed by the page with the next piece
+    pub fn new_overflow(data: &[u8], next: u64) -> BNode {
+        assert!(data.len() <= OVERFLOW_CAPACITY);
+        let mut node = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+        node.set_header(BNODE_OVERFLOW, data.len() as u16);
+        node.data[BNODE_HEADER..OVERFLOW_HEADER].copy_from_slice(&next.to_le_bytes());
+        node.data[OVERFLOW_HEADER..OVERFLOW_HEADER + data.len()].copy_from_slice(data);
+        node
+    }
+
+    pub fn overflow_next(&self) -> u64 {
+        u64::from_le_bytes(self.data[BNODE_HEADER..OVERFLOW_HEADER].try_into().unwrap())
+    }
+
+    pub fn overflow_data(&self) -> &[u8] {
+        let length = (decode_header_field(&self.data, 2, 4) as usize).min(OVERFLOW_CAPACITY);
+        &self.data[OVERFLOW_HEADER..OVERFLOW_HEADER + length]
+    }
+
+    /// Drop the scratch space past the first page of a node that fits in a page
+    pub fn into_page(mut self) -> BNode {
+        assert!(self.size() <= BTREE_PAGE_SIZE as u16);
+        self.data.truncate(BTREE_PAGE_SIZE);
+        self
+    }
+}
+
+pub fn decode_header_field(data: &[u8], start: usize, end: usize) -> u16 {
+    u16::from_le_bytes(data[start..end]
+        .try_into()
+        .expect("Failed to convert bytes to u16"))
+}
+
+/// CRC32 of a page, counting the four checksum bytes at the given position as zero
+pub fn page_checksum(data: &[u8], position: usize) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&data[..position]);
+    hasher.update(&[0; 4]);
+    hasher.update(&data[position + 4..]);
+    hasher.finalize()
+}
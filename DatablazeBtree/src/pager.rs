@@ -0,0 +1,409 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::mem;
+use std::path::Path;
+use std::sync::Mutex;
+use crate::buffer_pool::{read_page, write_page, BufferPool};
+use crate::exceptions::BTreeError;
+use crate::node::{page_checksum, BNode, BTREE_PAGE_SIZE};
+
+const META_MAGIC: &[u8; 8] = b"DBLZBTR3";
+// Both meta pages hold [magic][checksum: u32][generation: u64][root: u64][page count: u64]
+// [first free list page: u64]
+const META_CHECKSUM: usize = META_MAGIC.len();
+const META_GENERATION: usize = META_CHECKSUM + 4;
+const META_ROOT: usize = META_GENERATION + 8;
+const META_PAGE_COUNT: usize = META_ROOT + 8;
+const META_FREE_LIST: usize = META_PAGE_COUNT + 8;
+
+// Free list pages hold [next free list page: u64][count: u16][checksum: u32][count * free page: u64]
+const FREE_LIST_CHECKSUM: usize = 8 + 2;
+const FREE_LIST_HEADER: usize = FREE_LIST_CHECKSUM + 4;
+const FREE_LIST_CAPACITY: usize = (BTREE_PAGE_SIZE - FREE_LIST_HEADER) / 8;
+
+/// Maps page pointers to the nodes of a tree. Pointer 0 is never handed out, so it can stand for
+/// "no page".
+pub trait Pager {
+    /// Root of the tree as of the last commit, 0 for an empty tree
+    fn root(&self) -> u64;
+    /// Number of pages in use by the tree
+    fn page_count(&self) -> usize;
+    fn get_page(&self, pointer: u64) -> Result<BNode, BTreeError>;
+    fn new_page(&mut self, node: BNode) -> Result<u64, BTreeError>;
+    fn del_page(&mut self, pointer: u64);
+    /// Make every page written since the last commit durable and switch over to the new root
+    fn commit(&mut self, root: u64) -> Result<(), BTreeError>;
+    /// Drop every page written since the last commit, keeping the committed tree
+    fn rollback(&mut self) -> Result<(), BTreeError>;
+    /// Change the number of pages kept in memory at most
+    fn set_cache_pages(&mut self, pages: usize) -> Result<(), BTreeError>;
+}
+
+/// Pager that keeps every page in memory, for trees that do not have to outlive the process
+pub struct MemoryPager {
+    root: u64,
+    pages: HashMap<u64, BNode>,
+    free: Vec<u64>,
+    next_page: u64,
+}
+
+impl Default for MemoryPager {
+    fn default() -> Self {
+        MemoryPager { root: 0, pages: HashMap::new(), free: Vec::new(), next_page: 1 }
+    }
+}
+
+impl Pager for MemoryPager {
+    fn root(&self) -> u64 {
+        self.root
+    }
+
+    fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn get_page(&self, pointer: u64) -> Result<BNode, BTreeError> {
+        self.pages
+            .get(&pointer)
+            .cloned()
+            .ok_or(BTreeError::Corrupt(format!("Pointer to page {} that is not in the tree", pointer)))
+    }
+
+    fn new_page(&mut self, node: BNode) -> Result<u64, BTreeError> {
+        let pointer = self.free.pop().unwrap_or_else(|| {
+            self.next_page += 1;
+            self.next_page - 1
+        });
+        self.pages.insert(pointer, node);
+        Ok(pointer)
+    }
+
+    // Nothing can read the old version of the tree, so pages can be reused right away
+    fn del_page(&mut self, pointer: u64) {
+        if self.pages.remove(&pointer).is_some() {
+            self.free.push(pointer);
+        }
+    }
+
+    fn commit(&mut self, root: u64) -> Result<(), BTreeError> {
+        self.root = root;
+        Ok(())
+    }
+
+    // Pages of the committed tree may already be reused
+    fn rollback(&mut self) -> Result<(), BTreeError> {
+        Err(BTreeError::Unsupported("rolling back a tree kept in memory"))
+    }
+
+    // Every page is in memory anyway
+    fn set_cache_pages(&mut self, _pages: usize) -> Result<(), BTreeError> {
+        Ok(())
+    }
+}
+
+/// Number of meta pages at the start of a tree file
+pub(crate) const META_PAGES: u64 = 2;
+
+/// Pager that stores the tree in a single file. Pages 0 and 1 are meta pages, pointing to the
+/// root of a committed tree and to the free list of pages that can be reused. The one with the
+/// highest generation that passes its checksum holds the last commit.
+///
+/// Pages of the committed tree are never overwritten. An update writes its pages to free or new
+/// pages, and a commit switches over to them by writing the meta page of the commit before the
+/// last one, after everything else. A torn meta page fails its checksum and the other one is
+/// used, so a crash at any point leaves either the old or the new tree. That also means new pages can be written back
+/// to the file at any time, so the buffer pool is free to evict them before the commit.
+pub struct FilePager {
+    file: File,
+    root: u64,
+    // Generation of the last meta page written, the next one goes to the other meta page
+    generation: u64,
+    // Pages in the file, including the meta pages
+    page_count: u64,
+    // Pages that are not used by the committed tree, and can be handed out right away
+    free: Vec<u64>,
+    // Pages holding the committed free list
+    free_list_pages: Vec<u64>,
+    // Pages dropped since the last commit. The committed tree still uses them, so they can only
+    // be reused once the next commit is done
+    released: Vec<u64>,
+    // Pages handed out since the last commit
+    allocated: HashSet<u64>,
+    // Only locked for the duration of a single page access
+    pool: Mutex<BufferPool>,
+}
+
+impl FilePager {
+    /// Open a tree file, caching at most the given number of pages in memory
+    pub fn open(path: &Path, cache_pages: usize) -> Result<FilePager, BTreeError> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let mut pager = FilePager {
+            file,
+            root: 0,
+            generation: 0,
+            page_count: META_PAGES,
+            free: Vec::new(),
+            free_list_pages: Vec::new(),
+            released: Vec::new(),
+            allocated: HashSet::new(),
+            pool: Mutex::new(BufferPool::new(cache_pages)),
+        };
+        if pager.file.metadata()?.len() == 0 {
+            // Both meta pages, so either one can be overwritten by the first commit
+            pager.write_meta()?;
+            pager.write_meta()?;
+            pager.file.sync_all()?;
+            return Ok(pager);
+        }
+
+        let meta = read_meta(&pager.file)
+            .map_err(|reason| BTreeError::Corrupt(format!("{}: {}", path.display(), reason)))?;
+        pager.generation = meta.generation;
+        pager.root = meta.root;
+        pager.page_count = meta.page_count;
+        if pager.root >= pager.page_count {
+            return Err(BTreeError::Corrupt(format!("Root page {} is out of bounds", pager.root)));
+        }
+        // Pages past the page count were written by a commit that never finished
+        if pager.file.metadata()?.len() > pager.page_count * BTREE_PAGE_SIZE as u64 {
+            pager.file.set_len(pager.page_count * BTREE_PAGE_SIZE as u64)?;
+        }
+
+        let mut free_list_page = meta.free_list_page;
+        while free_list_page != 0 {
+            if free_list_page >= pager.page_count || pager.free_list_pages.contains(&free_list_page) {
+                return Err(BTreeError::Corrupt(format!("Free list page {} is out of bounds", free_list_page)));
+            }
+            let (next, entries) = decode_free_list_page(&pager.read(free_list_page)?)
+                .map_err(|reason| BTreeError::Corrupt(format!("Free list page {}: {}", free_list_page, reason)))?;
+            pager.free.extend(entries);
+            pager.free_list_pages.push(free_list_page);
+            free_list_page = next;
+        }
+        Ok(pager)
+    }
+
+    /// Number of pages in the file, including the meta pages and free pages
+    pub fn file_page_count(&self) -> u64 {
+        self.page_count
+    }
+
+    /// Number of pages the buffer pool holds at most
+    pub fn cache_capacity(&self) -> usize {
+        self.pool.lock().unwrap().capacity()
+    }
+
+    fn read(&self, page: u64) -> Result<Vec<u8>, BTreeError> {
+        read_page(&self.file, page)
+    }
+
+    fn write(&mut self, page: u64, data: &[u8]) -> Result<(), BTreeError> {
+        write_page(&self.file, page, data)
+    }
+
+    fn write_meta(&mut self) -> Result<(), BTreeError> {
+        self.generation += 1;
+        let mut meta = vec![0; BTREE_PAGE_SIZE];
+        meta[0..META_MAGIC.len()].copy_from_slice(META_MAGIC);
+        meta[META_GENERATION..META_GENERATION + 8].copy_from_slice(&self.generation.to_le_bytes());
+        meta[META_ROOT..META_ROOT + 8].copy_from_slice(&self.root.to_le_bytes());
+        meta[META_PAGE_COUNT..META_PAGE_COUNT + 8].copy_from_slice(&self.page_count.to_le_bytes());
+        let free_list_page = self.free_list_pages.first().copied().unwrap_or(0);
+        meta[META_FREE_LIST..META_FREE_LIST + 8].copy_from_slice(&free_list_page.to_le_bytes());
+        set_checksum(&mut meta, META_CHECKSUM);
+        self.write(self.generation % META_PAGES, &meta)
+    }
+
+    // Append a page to the end of the file
+    fn append_page(&mut self) -> u64 {
+        self.page_count += 1;
+        self.page_count - 1
+    }
+}
+
+impl Pager for FilePager {
+    fn root(&self) -> u64 {
+        self.root
+    }
+
+    fn page_count(&self) -> usize {
+        let unused = self.free.len() + self.free_list_pages.len() + self.released.len();
+        (self.page_count - META_PAGES) as usize - unused
+    }
+
+    fn get_page(&self, pointer: u64) -> Result<BNode, BTreeError> {
+        if pointer < META_PAGES || pointer >= self.page_count {
+            return Err(BTreeError::Corrupt(format!("Pointer to page {} is out of bounds", pointer)));
+        }
+        let mut pool = self.pool.lock().unwrap();
+        if let Some(node) = pool.get(pointer) {
+            return Ok(node.clone());
+        }
+        let node = BNode::new(self.read(pointer)?);
+        if !node.verify_checksum() {
+            return Err(BTreeError::Corrupt(format!("Page {} fails its checksum", pointer)));
+        }
+        node.check_layout().map_err(|reason| BTreeError::Corrupt(format!("Page {}: {}", pointer, reason)))?;
+        pool.insert(&self.file, pointer, node.clone(), false)?;
+        Ok(node)
+    }
+
+    fn new_page(&mut self, mut node: BNode) -> Result<u64, BTreeError> {
+        // Nodes are never changed once they have a page, so the checksum can be set right away
+        node.update_checksum();
+        let pointer = match self.free.pop() {
+            Some(pointer) => pointer,
+            None => self.append_page(),
+        };
+        self.allocated.insert(pointer);
+        self.pool.lock().unwrap().insert(&self.file, pointer, node, true)?;
+        Ok(pointer)
+    }
+
+    fn del_page(&mut self, pointer: u64) {
+        self.pool.lock().unwrap().discard(pointer);
+        // A page handed out since the last commit is not part of the committed tree
+        if self.allocated.remove(&pointer) {
+            self.free.push(pointer);
+        } else {
+            self.released.push(pointer);
+        }
+    }
+
+    fn commit(&mut self, root: u64) -> Result<(), BTreeError> {
+        if self.allocated.is_empty() && self.released.is_empty() && root == self.root {
+            return Ok(());
+        }
+
+        // The new free list can only be written to pages the committed state does not use. Pages
+        // released by this update and the pages of the old free list are listed, but not used.
+        let mut free = mem::take(&mut self.free);
+        let mut listed = mem::take(&mut self.released);
+        listed.append(&mut self.free_list_pages);
+        let mut free_list_pages = Vec::new();
+        while free_list_pages.len() * FREE_LIST_CAPACITY < free.len() + listed.len() {
+            let page = match free.pop() {
+                Some(page) => page,
+                None => self.append_page(),
+            };
+            free_list_pages.push(page);
+        }
+        free.append(&mut listed);
+
+        self.pool.lock().unwrap().flush(&self.file)?;
+        self.allocated.clear();
+        for (i, page) in free_list_pages.iter().enumerate() {
+            let entries = &free[i * FREE_LIST_CAPACITY..free.len().min((i + 1) * FREE_LIST_CAPACITY)];
+            let next = free_list_pages.get(i + 1).copied().unwrap_or(0);
+            let data = encode_free_list_page(next, entries);
+            self.write(*page, &data)?;
+        }
+        // Pages at the end that were handed out and dropped again before being written still count
+        if self.file.metadata()?.len() < self.page_count * BTREE_PAGE_SIZE as u64 {
+            self.file.set_len(self.page_count * BTREE_PAGE_SIZE as u64)?;
+        }
+        self.file.sync_data()?;
+
+        // Only now that everything it points to is on disk, switch over to the new meta page
+        self.root = root;
+        self.free = free;
+        self.free_list_pages = free_list_pages;
+        self.write_meta()?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    // Pages dropped since the commit are still part of the committed tree and on disk, the ones
+    // handed out since can be reused
+    fn rollback(&mut self) -> Result<(), BTreeError> {
+        let mut pool = self.pool.lock().unwrap();
+        for pointer in self.allocated.drain() {
+            pool.discard(pointer);
+            self.free.push(pointer);
+        }
+        self.released.clear();
+        Ok(())
+    }
+
+    fn set_cache_pages(&mut self, pages: usize) -> Result<(), BTreeError> {
+        self.pool.lock().unwrap().set_capacity(&self.file, pages)
+    }
+}
+
+/// Contents of a meta page of a tree file
+pub(crate) struct Meta {
+    pub generation: u64,
+    pub root: u64,
+    pub page_count: u64,
+    pub free_list_page: u64,
+}
+
+/// The meta page of the last commit, which is the one with the highest generation of those that
+/// pass their checksum
+pub(crate) fn read_meta(file: &File) -> Result<Meta, String> {
+    let mut newest: Option<Meta> = None;
+    let mut problem = None;
+    for page in 0..META_PAGES {
+        match read_page(file, page).map_err(|e| e.to_string()).and_then(|data| decode_meta(&data)) {
+            Ok(meta) if newest.as_ref().is_none_or(|newest| meta.generation > newest.generation) => newest = Some(meta),
+            Ok(_) => {}
+            Err(reason) => {
+                problem.get_or_insert(reason);
+            }
+        }
+    }
+    newest.ok_or_else(|| problem.unwrap())
+}
+
+fn decode_meta(data: &[u8]) -> Result<Meta, String> {
+    if &data[0..META_MAGIC.len()] != META_MAGIC {
+        return Err("not a tree file".to_string());
+    }
+    if !has_valid_checksum(data, META_CHECKSUM) {
+        return Err("meta page fails its checksum".to_string());
+    }
+    Ok(Meta {
+        generation: read_u64(data, META_GENERATION),
+        root: read_u64(data, META_ROOT),
+        page_count: read_u64(data, META_PAGE_COUNT),
+        free_list_page: read_u64(data, META_FREE_LIST),
+    })
+}
+
+fn encode_free_list_page(next: u64, entries: &[u64]) -> Vec<u8> {
+    let mut data = vec![0; BTREE_PAGE_SIZE];
+    data[0..8].copy_from_slice(&next.to_le_bytes());
+    data[8..FREE_LIST_CHECKSUM].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (i, entry) in entries.iter().enumerate() {
+        let position = FREE_LIST_HEADER + 8 * i;
+        data[position..position + 8].copy_from_slice(&entry.to_le_bytes());
+    }
+    set_checksum(&mut data, FREE_LIST_CHECKSUM);
+    data
+}
+
+/// Returns the next free list page and the free pages listed on this one
+pub(crate) fn decode_free_list_page(data: &[u8]) -> Result<(u64, Vec<u64>), String> {
+    if !has_valid_checksum(data, FREE_LIST_CHECKSUM) {
+        return Err("fails its checksum".to_string());
+    }
+    let count = u16::from_le_bytes(data[8..FREE_LIST_CHECKSUM].try_into().unwrap()) as usize;
+    if count > FREE_LIST_CAPACITY {
+        return Err("holds too many pages".to_string());
+    }
+    let entries = (0..count).map(|i| read_u64(data, FREE_LIST_HEADER + 8 * i)).collect();
+    Ok((read_u64(data, 0), entries))
+}
+
+fn set_checksum(data: &mut [u8], position: usize) {
+    let checksum = page_checksum(data, position);
+    data[position..position + 4].copy_from_slice(&checksum.to_le_bytes());
+}
+
+fn has_valid_checksum(data: &[u8], position: usize) -> bool {
+    u32::from_le_bytes(data[position..position + 4].try_into().unwrap()) == page_checksum(data, position)
+}
+
+fn read_u64(data: &[u8], position: usize) -> u64 {
+    u64::from_le_bytes(data[position..position + 8].try_into().unwrap())
+}
@@ -0,0 +1,242 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use crate::btree::BTree;
+    use crate::exceptions::BTreeError;
+    use crate::iterator::SeekComparison;
+    use crate::node::{BTREE_MAX_INLINE_VAL_SIZE, BTREE_MAX_KEY_SIZE, BTREE_MAX_VAL_SIZE, OVERFLOW_CAPACITY};
+
+    // Small deterministic generator, so failures can be reproduced
+    fn shuffled_keys(count: u64) -> Vec<u64> {
+        let mut keys: Vec<u64> = (0..count).collect();
+        let mut state = 0x2545F4914F6CDD1Du64;
+        for i in (1..keys.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            keys.swap(i, (state % (i as u64 + 1)) as usize);
+        }
+        keys
+    }
+
+    fn key(i: u64) -> Vec<u8> {
+        format!("key_{:08}", i).into_bytes()
+    }
+
+    fn value(i: u64) -> Vec<u8> {
+        format!("value_{}", i).repeat((i % 20) as usize + 1).into_bytes()
+    }
+
+    #[test]
+    fn insert_and_lookup() {
+        let mut tree = BTree::default();
+        let mut expected = BTreeMap::new();
+        for i in shuffled_keys(5000) {
+            tree.insert(&key(i), &value(i)).unwrap();
+            expected.insert(key(i), value(i));
+        }
+        for (key, value) in expected.iter() {
+            assert_eq!(tree.lookup(key).unwrap().as_ref(), Some(value));
+        }
+        assert_eq!(tree.lookup(b"missing").unwrap(), None);
+        assert!(tree.page_count() > 1);
+    }
+
+    #[test]
+    fn update_existing_keys() {
+        let mut tree = BTree::default();
+        for i in shuffled_keys(2000) {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        for i in shuffled_keys(2000) {
+            tree.insert(&key(i), format!("updated_{}", i).as_bytes()).unwrap();
+        }
+        for i in 0..2000 {
+            assert_eq!(tree.lookup(&key(i)).unwrap(), Some(format!("updated_{}", i).into_bytes()));
+        }
+    }
+
+    #[test]
+    fn delete_merges_nodes() {
+        let mut tree = BTree::default();
+        for i in shuffled_keys(5000) {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        let pages_when_full = tree.page_count();
+
+        // Delete every other key, the remaining keys must all still be found
+        for i in shuffled_keys(5000).into_iter().filter(|i| i % 2 == 0) {
+            assert!(tree.delete(&key(i)).unwrap());
+        }
+        for i in 0..5000 {
+            let expected = if i % 2 == 0 { None } else { Some(value(i)) };
+            assert_eq!(tree.lookup(&key(i)).unwrap(), expected);
+        }
+        assert!(tree.page_count() < pages_when_full);
+
+        // Deleting everything shrinks the tree back to a single leaf
+        for i in shuffled_keys(5000).into_iter().filter(|i| i % 2 == 1) {
+            assert!(tree.delete(&key(i)).unwrap());
+        }
+        assert!(!tree.delete(&key(1)).unwrap());
+        assert_eq!(tree.page_count(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_keys_and_values() {
+        let mut tree = BTree::default();
+        assert!(matches!(tree.insert(b"", b"value"), Err(BTreeError::EmptyKey)));
+        assert!(matches!(tree.insert(&vec![1; BTREE_MAX_KEY_SIZE + 1], b"value"), Err(BTreeError::KeyTooLarge(_))));
+        assert!(matches!(tree.insert(b"key", &vec![1; BTREE_MAX_VAL_SIZE + 1]), Err(BTreeError::ValueTooLarge(_))));
+
+        // The largest key with the largest value that stays in the leaf still fits
+        tree.insert(&vec![1; BTREE_MAX_KEY_SIZE], &vec![2; BTREE_MAX_INLINE_VAL_SIZE]).unwrap();
+        tree.insert(&vec![3; BTREE_MAX_KEY_SIZE], &vec![4; BTREE_MAX_INLINE_VAL_SIZE]).unwrap();
+        assert_eq!(tree.lookup(&vec![3; BTREE_MAX_KEY_SIZE]).unwrap(), Some(vec![4; BTREE_MAX_INLINE_VAL_SIZE]));
+    }
+
+    #[test]
+    fn large_values_use_overflow_pages() {
+        let mut tree = BTree::default();
+        let sizes = [
+            BTREE_MAX_INLINE_VAL_SIZE + 1,
+            OVERFLOW_CAPACITY,
+            OVERFLOW_CAPACITY + 1,
+            3 * OVERFLOW_CAPACITY,
+            100_000,
+        ];
+        let large_value = |i: usize, size: usize| (0..size).map(|j| (i * 7 + j) as u8).collect::<Vec<u8>>();
+        for (i, size) in sizes.iter().enumerate() {
+            tree.insert(&vec![1; BTREE_MAX_KEY_SIZE - i], &large_value(i, *size)).unwrap();
+            tree.insert(&key(i as u64), &value(i as u64)).unwrap();
+        }
+        for (i, size) in sizes.iter().enumerate() {
+            assert_eq!(tree.lookup(&vec![1; BTREE_MAX_KEY_SIZE - i]).unwrap(), Some(large_value(i, *size)));
+            assert_eq!(tree.lookup(&key(i as u64)).unwrap(), Some(value(i as u64)));
+        }
+        let scanned: Vec<Vec<u8>> = tree.prefix(&[1]).unwrap().map(|item| item.unwrap().1).collect();
+        assert_eq!(scanned, sizes.iter().enumerate().rev().map(|(i, size)| large_value(i, *size)).collect::<Vec<_>>());
+
+        // Replacing or deleting a large value releases its overflow pages
+        let pages = tree.page_count();
+        tree.insert(b"large", &large_value(0, 50_000)).unwrap();
+        assert!(tree.page_count() > pages + 50_000 / OVERFLOW_CAPACITY);
+        tree.insert(b"large", b"small").unwrap();
+        let pages_with_small_value = tree.page_count();
+        tree.insert(b"large", &large_value(1, 50_000)).unwrap();
+        tree.insert(b"large", b"small").unwrap();
+        assert_eq!(tree.page_count(), pages_with_small_value);
+        tree.insert(b"large", &large_value(2, 50_000)).unwrap();
+        tree.delete(b"large").unwrap();
+        assert!(tree.page_count() <= pages + 1);
+    }
+
+    fn keys(items: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>), BTreeError>>) -> Vec<Vec<u8>> {
+        items.map(|item| item.unwrap().0).collect()
+    }
+
+    #[test]
+    fn range_scans_respect_bounds() {
+        let mut tree = BTree::default();
+        for i in shuffled_keys(3000) {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+
+        let everything: Vec<(Vec<u8>, Vec<u8>)> = tree.range(Bound::Unbounded, Bound::Unbounded).unwrap().map(Result::unwrap).collect();
+        assert_eq!(everything, (0..3000).map(|i| (key(i), value(i))).collect::<Vec<_>>());
+
+        let (start, end) = (key(1000), key(2000));
+        assert_eq!(keys(tree.range(Bound::Included(&start), Bound::Included(&end)).unwrap()), (1000..=2000).map(key).collect::<Vec<_>>());
+        assert_eq!(keys(tree.range(Bound::Excluded(&start), Bound::Excluded(&end)).unwrap()), (1001..2000).map(key).collect::<Vec<_>>());
+        assert_eq!(keys(tree.range(Bound::Excluded(&end), Bound::Unbounded).unwrap()), (2001..3000).map(key).collect::<Vec<_>>());
+
+        // Bounds do not have to be keys in the tree
+        assert_eq!(keys(tree.range(Bound::Included(b"key_00000999x"), Bound::Excluded(b"key_00001003")).unwrap()), (1000..1003).map(key).collect::<Vec<_>>());
+        assert_eq!(tree.range(Bound::Included(b"zzz"), Bound::Unbounded).unwrap().count(), 0);
+        assert_eq!(tree.range(Bound::Included(&end), Bound::Excluded(&start)).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn range_scans_in_reverse() {
+        let mut tree = BTree::default();
+        for i in shuffled_keys(3000) {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+
+        assert_eq!(keys(tree.range_rev(Bound::Unbounded, Bound::Unbounded).unwrap()), (0..3000).rev().map(key).collect::<Vec<_>>());
+        let (start, end) = (key(500), key(1500));
+        assert_eq!(keys(tree.range_rev(Bound::Included(&start), Bound::Included(&end)).unwrap()), (500..=1500).rev().map(key).collect::<Vec<_>>());
+        assert_eq!(keys(tree.range_rev(Bound::Excluded(&start), Bound::Excluded(&end)).unwrap()), (501..1500).rev().map(key).collect::<Vec<_>>());
+        assert_eq!(keys(tree.range_rev(Bound::Unbounded, Bound::Excluded(&key(3))).unwrap()), (0..3).rev().map(key).collect::<Vec<_>>());
+        assert_eq!(tree.range_rev(Bound::Unbounded, Bound::Excluded(&key(0))).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn cursor_seeks_and_moves_both_ways() {
+        let mut tree = BTree::default();
+        assert!(!tree.seek(b"key", SeekComparison::GreaterThanOrEqual).unwrap().is_valid());
+        // Only even keys, so odd keys fall between two keys in the tree
+        for i in shuffled_keys(2000).into_iter().filter(|i| i % 2 == 0) {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+
+        assert_eq!(tree.seek(&key(100), SeekComparison::LessThan).unwrap().key(), key(98));
+        assert_eq!(tree.seek(&key(100), SeekComparison::LessThanOrEqual).unwrap().key(), key(100));
+        assert_eq!(tree.seek(&key(101), SeekComparison::LessThanOrEqual).unwrap().key(), key(100));
+        assert_eq!(tree.seek(&key(100), SeekComparison::GreaterThanOrEqual).unwrap().key(), key(100));
+        assert_eq!(tree.seek(&key(100), SeekComparison::GreaterThan).unwrap().key(), key(102));
+        assert_eq!(tree.seek(&key(101), SeekComparison::GreaterThanOrEqual).unwrap().key(), key(102));
+        assert!(!tree.seek(&key(0), SeekComparison::LessThan).unwrap().is_valid());
+        assert!(!tree.seek(&key(1998), SeekComparison::GreaterThan).unwrap().is_valid());
+
+        let mut cursor = tree.seek(&key(1000), SeekComparison::GreaterThanOrEqual).unwrap();
+        for i in (1002..2000).step_by(2) {
+            cursor.move_next().unwrap();
+            assert_eq!(cursor.key(), key(i));
+            assert_eq!(cursor.value().unwrap(), value(i));
+        }
+        cursor.move_next().unwrap();
+        assert!(!cursor.is_valid());
+        // Stepping back from past the end returns to the last key
+        cursor.move_prev().unwrap();
+        assert_eq!(cursor.key(), key(1998));
+        for i in (0..999).rev().map(|i| i * 2) {
+            cursor.move_prev().unwrap();
+            assert_eq!(cursor.key(), key(i));
+        }
+        cursor.move_prev().unwrap();
+        assert!(!cursor.is_valid());
+        cursor.move_next().unwrap();
+        assert_eq!(cursor.key(), key(0));
+    }
+
+    #[test]
+    fn prefix_scans_and_range_deletes() {
+        let mut tree = BTree::default();
+        for table in ["orders", "users", "users_archive"] {
+            for i in 0..500 {
+                tree.insert(format!("{}/{:04}", table, i).as_bytes(), &value(i)).unwrap();
+            }
+        }
+        tree.insert(&[0xFF, 0xFF], b"last").unwrap();
+
+        let users = keys(tree.prefix(b"users/").unwrap());
+        assert_eq!(users, (0..500).map(|i| format!("users/{:04}", i).into_bytes()).collect::<Vec<_>>());
+        assert_eq!(tree.prefix(b"users").unwrap().count(), 1000);
+        assert_eq!(tree.prefix(b"users/01").unwrap().count(), 100);
+        assert_eq!(keys(tree.prefix(&[0xFF]).unwrap()), vec![vec![0xFF, 0xFF]]);
+        assert_eq!(tree.prefix(b"missing").unwrap().count(), 0);
+
+        let removed = tree.delete_range(Bound::Included(b"users/"), Bound::Excluded(b"users0")).unwrap();
+        assert_eq!(removed, 500);
+        assert_eq!(tree.prefix(b"users/").unwrap().count(), 0);
+        assert_eq!(tree.prefix(b"users_archive/").unwrap().count(), 500);
+        assert_eq!(tree.prefix(b"orders/").unwrap().count(), 500);
+        assert_eq!(tree.lookup(b"orders/0499").unwrap(), Some(value(499)));
+
+        assert_eq!(tree.delete_range(Bound::Unbounded, Bound::Unbounded).unwrap(), 1001);
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Unbounded).unwrap().count(), 0);
+        assert_eq!(tree.page_count(), 1);
+    }
+}
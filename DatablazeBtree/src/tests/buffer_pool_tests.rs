@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use crate::buffer_pool::{read_page, BufferPool};
+    use crate::node::{BNode, BTREE_PAGE_SIZE};
+
+    fn page(fill: u8) -> BNode {
+        BNode::new(vec![fill; BTREE_PAGE_SIZE])
+    }
+
+    fn open_file(directory: &tempfile::TempDir) -> std::fs::File {
+        OpenOptions::new().read(true).write(true).create(true).truncate(false).open(directory.path().join("pages")).unwrap()
+    }
+
+    #[test]
+    fn evicts_when_full() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = open_file(&directory);
+        let mut pool = BufferPool::new(3);
+        for i in 0..10 {
+            pool.insert(&file, i, page(i as u8), false).unwrap();
+            assert!(pool.len() <= 3);
+        }
+        assert_eq!(pool.len(), 3);
+        // The last page that was added is always still cached
+        assert_eq!(pool.get(9).unwrap().data()[0], 9);
+    }
+
+    #[test]
+    fn referenced_pages_get_a_second_chance() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = open_file(&directory);
+        let mut pool = BufferPool::new(3);
+        for i in 0..3 {
+            pool.insert(&file, i, page(i as u8), false).unwrap();
+        }
+        // The first insert past capacity clears every reference bit and evicts page 0
+        pool.insert(&file, 3, page(3), false).unwrap();
+        assert!(pool.get(0).is_none());
+        // Using page 1 again protects it from the next eviction
+        pool.get(1).unwrap();
+        pool.insert(&file, 4, page(4), false).unwrap();
+        assert!(pool.get(1).is_some());
+        assert!(pool.get(2).is_none());
+    }
+
+    #[test]
+    fn pinned_pages_are_not_evicted() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = open_file(&directory);
+        let mut pool = BufferPool::new(2);
+        pool.insert(&file, 1, page(1), false).unwrap();
+        pool.insert(&file, 2, page(2), false).unwrap();
+        pool.pin(1).unwrap();
+        pool.pin(2).unwrap();
+
+        // Every page is pinned, so the pool has to grow
+        pool.insert(&file, 3, page(3), false).unwrap();
+        assert_eq!(pool.len(), 3);
+        pool.unpin(2);
+        pool.insert(&file, 4, page(4), false).unwrap();
+        assert!(pool.get(1).is_some());
+        assert!(pool.get(2).is_none());
+    }
+
+    #[test]
+    fn dirty_pages_are_written_back() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = open_file(&directory);
+        let mut pool = BufferPool::new(2);
+        pool.insert(&file, 0, page(10), true).unwrap();
+        pool.insert(&file, 1, page(11), true).unwrap();
+        assert!(pool.is_dirty(0));
+
+        // Evicting a dirty page writes it to the file
+        pool.insert(&file, 2, page(12), false).unwrap();
+        assert!(pool.get(0).is_none());
+        assert_eq!(read_page(&file, 0).unwrap(), vec![10; BTREE_PAGE_SIZE]);
+
+        // Flushing writes the rest, and discarded pages are never written
+        pool.insert(&file, 3, page(13), true).unwrap();
+        pool.discard(3);
+        pool.flush(&file).unwrap();
+        assert!(!pool.is_dirty(1));
+        assert_eq!(read_page(&file, 1).unwrap(), vec![11; BTREE_PAGE_SIZE]);
+        assert!(read_page(&file, 3).is_err());
+    }
+
+    #[test]
+    fn shrinking_evicts_down_to_capacity() {
+        let directory = tempfile::tempdir().unwrap();
+        let file = open_file(&directory);
+        let mut pool = BufferPool::new(4);
+        for i in 0..4 {
+            pool.insert(&file, i, page(i as u8), true).unwrap();
+        }
+        pool.pin(3).unwrap();
+        pool.set_capacity(&file, 1).unwrap();
+        assert_eq!(pool.len(), 1);
+        // The pinned page stays, the evicted ones were written back
+        assert_eq!(pool.get(3).unwrap().data()[0], 3);
+        for i in 0..3 {
+            assert_eq!(read_page(&file, i).unwrap(), vec![i as u8; BTREE_PAGE_SIZE]);
+        }
+
+        // Growing again lets more pages in
+        pool.set_capacity(&file, 3).unwrap();
+        for i in 4..7 {
+            pool.insert(&file, i, page(i as u8), false).unwrap();
+        }
+        assert_eq!(pool.len(), 3);
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::path::Path;
+    use crate::btree::BTree;
+    use crate::check::{check_file, CheckProblem};
+    use crate::node::{BNode, BNODE_LEAF, BTREE_PAGE_SIZE};
+    use crate::pager::{FilePager, Pager};
+
+    fn key(i: u64) -> Vec<u8> {
+        format!("key_{:08}", i).into_bytes()
+    }
+
+    fn build_tree(path: &Path) -> BTree {
+        let mut tree = BTree::open(path, 16).unwrap();
+        for i in 0..2000 {
+            tree.insert(&key(i), format!("value_{}", i).as_bytes()).unwrap();
+        }
+        for i in 0..10 {
+            tree.insert(&key(i * 7), &vec![i as u8; 10_000]).unwrap();
+        }
+        tree.commit().unwrap();
+        for i in 0..500 {
+            tree.delete(&key(i * 3)).unwrap();
+        }
+        tree.commit().unwrap();
+        tree
+    }
+
+    fn leaf(keys: &[&[u8]]) -> BNode {
+        let mut node = BNode::new(vec![0; BTREE_PAGE_SIZE]);
+        node.set_header(BNODE_LEAF, keys.len() as u16);
+        for (i, key) in keys.iter().enumerate() {
+            let value: &[u8] = if key.is_empty() { &[] } else { &[0, 1, 2] };
+            node.append_key_value(i as u16, 0, key, value);
+        }
+        node
+    }
+
+    #[test]
+    fn healthy_tree_has_no_problems() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+        drop(build_tree(&path));
+
+        let report = check_file(&path).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.page_count * BTREE_PAGE_SIZE as u64, std::fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn detects_corrupt_pages() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+        let root = build_tree(&path).root();
+
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(root * BTREE_PAGE_SIZE as u64 + 100)).unwrap();
+        file.write_all(b"bit rot").unwrap();
+        drop(file);
+
+        let report = check_file(&path).unwrap();
+        assert!(matches!(report.problems[0], CheckProblem::CorruptPage { page, .. } if page == root));
+        // The pages below the root can not be reached any more, so they show up as leaked
+        assert!(report.problems[1..].iter().all(|problem| matches!(problem, CheckProblem::LeakedPage(_))));
+
+        // Reading the page through the tree fails instead of returning garbage
+        let tree = BTree::open(&path, 16).unwrap();
+        assert!(tree.lookup(&key(1)).is_err());
+    }
+
+    #[test]
+    fn detects_broken_ordering_and_leaks() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+
+        let mut pager = FilePager::open(&path, 16).unwrap();
+        let root = pager.new_page(leaf(&[b"", b"b", b"a"])).unwrap();
+        let leaked = pager.new_page(leaf(&[b"", b"c"])).unwrap();
+        pager.commit(root).unwrap();
+        drop(pager);
+
+        let report = check_file(&path).unwrap();
+        assert_eq!(report.problems, vec![
+            CheckProblem::BrokenInvariant { page: root, reason: "key 2 is not greater than the key before it".to_string() },
+            CheckProblem::LeakedPage(leaked),
+        ]);
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_trees() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+        std::fs::write(&path, vec![1; BTREE_PAGE_SIZE]).unwrap();
+        assert!(check_file(&path).is_err());
+    }
+}
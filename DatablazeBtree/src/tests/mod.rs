@@ -0,0 +1,4 @@
+mod btree_tests;
+mod buffer_pool_tests;
+mod pager_tests;
+mod check_tests;
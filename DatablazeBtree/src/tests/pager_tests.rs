@@ -0,0 +1,249 @@
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::ops::Bound;
+    use crate::btree::BTree;
+    use crate::check::check_file;
+    use crate::node::BTREE_PAGE_SIZE;
+    use crate::pager::FilePager;
+
+    fn key(i: u64) -> Vec<u8> {
+        format!("key_{:08}", i).into_bytes()
+    }
+
+    fn value(i: u64) -> Vec<u8> {
+        format!("value_{}", i).repeat((i % 20) as usize + 1).into_bytes()
+    }
+
+    fn file_size(path: &std::path::Path) -> u64 {
+        std::fs::metadata(path).unwrap().len()
+    }
+
+    #[test]
+    fn committed_tree_survives_reopen() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+
+        let mut tree = BTree::open(&path, 64).unwrap();
+        for i in 0..3000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        for i in (0..3000).filter(|i| i % 3 == 0) {
+            tree.delete(&key(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        let page_count = tree.page_count();
+        drop(tree);
+
+        let tree = BTree::open(&path, 64).unwrap();
+        assert_eq!(tree.page_count(), page_count);
+        for i in 0..3000 {
+            let expected = if i % 3 == 0 { None } else { Some(value(i)) };
+            assert_eq!(tree.lookup(&key(i)).unwrap(), expected);
+        }
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Unbounded).unwrap().count(), 2000);
+    }
+
+    #[test]
+    fn uncommitted_changes_are_lost() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+
+        // With a cache this small, uncommitted pages are written back long before the commit
+        let mut tree = BTree::open(&path, 4).unwrap();
+        for i in 0..1000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        let committed_size = file_size(&path);
+        for i in 1000..2000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        for i in 0..500 {
+            tree.delete(&key(i)).unwrap();
+        }
+        drop(tree);
+
+        // Pages written past the committed end of the file are cut off as well
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&[7; BTREE_PAGE_SIZE * 3]).unwrap();
+        let tree = BTree::open(&path, 4).unwrap();
+        assert_eq!(file_size(&path), committed_size);
+        for i in 0..2000 {
+            let expected = if i < 1000 { Some(value(i)) } else { None };
+            assert_eq!(tree.lookup(&key(i)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn free_list_reuses_released_pages() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+
+        let mut tree = BTree::open(&path, 64).unwrap();
+        for i in 0..2000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+
+        // Rewriting the same keys over and over must not grow the file much past its first size
+        let mut sizes = Vec::new();
+        for round in 0..20 {
+            for i in (0..2000).filter(|i| i % 20 == round) {
+                tree.insert(&key(i), format!("round_{}", round).as_bytes()).unwrap();
+            }
+            tree.commit().unwrap();
+            sizes.push(file_size(&path));
+        }
+        assert!(sizes.last().unwrap() <= &(sizes[0] + 4 * BTREE_PAGE_SIZE as u64), "{:?}", sizes);
+
+        // Deleting everything hands all pages but the root back to the free list
+        tree.delete_range(Bound::Unbounded, Bound::Unbounded).unwrap();
+        tree.commit().unwrap();
+        assert_eq!(tree.page_count(), 1);
+        drop(tree);
+
+        let pager = FilePager::open(&path, 64).unwrap();
+        assert_eq!(pager.file_page_count() * BTREE_PAGE_SIZE as u64, file_size(&path));
+        let mut tree = BTree::open(&path, 64).unwrap();
+        for i in 0..2000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        assert_eq!(file_size(&path), *sizes.last().unwrap());
+    }
+
+    #[test]
+    fn large_values_survive_reopen() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+        let document = |i: u64| format!("document {} ", i).repeat(1000 + i as usize * 500).into_bytes();
+
+        let mut tree = BTree::open(&path, 4).unwrap();
+        for i in 0..20 {
+            tree.insert(&key(i), &document(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        drop(tree);
+
+        let mut tree = BTree::open(&path, 4).unwrap();
+        for i in 0..20 {
+            assert_eq!(tree.lookup(&key(i)).unwrap(), Some(document(i)));
+        }
+        // Overflow pages released by deletes are reused by later values
+        let size = file_size(&path);
+        for i in 0..20 {
+            tree.delete(&key(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        for i in 0..20 {
+            tree.insert(&key(i), &document(19 - i)).unwrap();
+        }
+        tree.commit().unwrap();
+        assert!(file_size(&path) <= size + 4 * BTREE_PAGE_SIZE as u64);
+    }
+
+    #[test]
+    fn failed_insert_releases_its_overflow_pages() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+
+        let mut tree = BTree::open(&path, 4).unwrap();
+        for i in 0..1000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        let root = tree.root();
+        drop(tree);
+
+        // The value goes to overflow pages before the damaged root is read
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(root * BTREE_PAGE_SIZE as u64 + 100)).unwrap();
+        file.write_all(b"bit rot").unwrap();
+        drop(file);
+        let mut tree = BTree::open(&path, 4).unwrap();
+        let page_count = tree.page_count();
+        assert!(tree.insert(&key(0), &vec![7; 50_000]).is_err());
+        assert_eq!(tree.page_count(), page_count);
+    }
+
+    #[test]
+    fn torn_meta_page_falls_back_to_the_commit_before() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+
+        let mut tree = BTree::open(&path, 64).unwrap();
+        for i in 0..1000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        for i in 1000..2000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        drop(tree);
+
+        // Creating the file wrote both meta pages, the commits then wrote page 1 and page 0
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(20)).unwrap();
+        file.write_all(b"torn").unwrap();
+        drop(file);
+        let tree = BTree::open(&path, 64).unwrap();
+        for i in 0..2000 {
+            let expected = if i < 1000 { Some(value(i)) } else { None };
+            assert_eq!(tree.lookup(&key(i)).unwrap(), expected);
+        }
+        drop(tree);
+
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(BTREE_PAGE_SIZE as u64 + 20)).unwrap();
+        file.write_all(b"torn").unwrap();
+        drop(file);
+        assert!(BTree::open(&path, 64).is_err());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_trees() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+        std::fs::write(&path, vec![1; BTREE_PAGE_SIZE]).unwrap();
+        assert!(BTree::open(&path, 64).is_err());
+    }
+
+    #[test]
+    fn rollback_returns_to_committed_tree() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("tree.db");
+
+        let mut tree = BTree::open(&path, 4).unwrap();
+        for i in 0..1000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        let page_count = tree.page_count();
+        for i in 1000..2000 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        for i in 0..500 {
+            tree.delete(&key(i)).unwrap();
+        }
+        tree.rollback().unwrap();
+        assert_eq!(tree.page_count(), page_count);
+        for i in 0..2000 {
+            let expected = if i < 1000 { Some(value(i)) } else { None };
+            assert_eq!(tree.lookup(&key(i)).unwrap(), expected);
+        }
+
+        // Pages of the dropped changes are reused, and none of the committed ones are lost
+        for i in 1000..1500 {
+            tree.insert(&key(i), &value(i)).unwrap();
+        }
+        tree.commit().unwrap();
+        drop(tree);
+        assert!(check_file(&path).unwrap().is_ok());
+        let tree = BTree::open(&path, 4).unwrap();
+        assert_eq!(tree.range(Bound::Unbounded, Bound::Unbounded).unwrap().count(), 1500);
+        assert!(BTree::default().rollback().is_err());
+    }
+}
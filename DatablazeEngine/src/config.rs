@@ -0,0 +1,45 @@
+use std::env;
+use std::path::PathBuf;
+use datablaze_btree::node::BTREE_PAGE_SIZE;
+
+pub const DATA_DIR_VARIABLE: &str = "DATABLAZE_DATA_DIR";
+pub const DEFAULT_DATA_DIR: &str = "data";
+pub const CACHE_SIZE_VARIABLE: &str = "DATABLAZE_CACHE_SIZE";
+pub const DEFAULT_CACHE_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Root directory under which every file based database gets its own directory
+    pub data_dir: PathBuf,
+    /// Bytes of memory the file based databases share to cache pages, split evenly between them
+    pub cache_size: usize,
+}
+
+impl EngineConfig {
+    pub fn new(data_dir: PathBuf) -> EngineConfig {
+        EngineConfig { data_dir, cache_size: DEFAULT_CACHE_SIZE }
+    }
+
+    pub fn from_env() -> EngineConfig {
+        let data_dir = env::var(DATA_DIR_VARIABLE).unwrap_or(DEFAULT_DATA_DIR.to_string());
+        let mut config = EngineConfig::new(PathBuf::from(data_dir));
+        if let Ok(cache_size) = env::var(CACHE_SIZE_VARIABLE) {
+            match cache_size.parse() {
+                Ok(cache_size) => config.cache_size = cache_size,
+                Err(_) => log::warn!("Ignoring {}, {} is not a number of bytes", CACHE_SIZE_VARIABLE, cache_size),
+            }
+        }
+        config
+    }
+
+    /// Number of pages each of the given number of file based databases may cache
+    pub fn cache_pages(&self, databases: usize) -> usize {
+        (self.cache_size / databases.max(1) / BTREE_PAGE_SIZE).max(1)
+    }
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig::new(PathBuf::from(DEFAULT_DATA_DIR))
+    }
+}
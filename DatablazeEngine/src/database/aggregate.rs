@@ -0,0 +1,51 @@
+use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+use datablaze_types::enums::ColumnData;
+use crate::database::exceptions::DataError;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregateFunction {
+    /// Number of rows where the column is not null
+    Count,
+    /// Exact for integer and decimal columns, a sum that overflows is an error
+    Sum,
+    Min,
+    Max,
+}
+
+/// Aggregate the data of a column, skipping nulls. Sum, min and max of no data are null.
+pub fn aggregate(function: AggregateFunction, data: impl Iterator<Item = ColumnData>) -> Result<ColumnData, DataError> {
+    let mut data = data.filter(|data| !matches!(data, ColumnData::Null));
+    match function {
+        AggregateFunction::Count => Ok(ColumnData::Int(data.count() as u64)),
+        AggregateFunction::Sum => data.try_fold(ColumnData::Null, |total, data| add(total, data).ok_or(DataError)),
+        AggregateFunction::Min => data.try_fold(ColumnData::Null, |min, data| pick(min, data, Ordering::Less)),
+        AggregateFunction::Max => data.try_fold(ColumnData::Null, |max, data| pick(max, data, Ordering::Greater)),
+    }
+}
+
+// None when the data can not be added or the total overflows
+fn add(total: ColumnData, data: ColumnData) -> Option<ColumnData> {
+    match (total, data) {
+        (ColumnData::Null, data @ (ColumnData::Int(_) | ColumnData::SignedInt(_) | ColumnData::Float(_) | ColumnData::Decimal(_))) => Some(data),
+        (ColumnData::Int(a), ColumnData::Int(b)) => a.checked_add(b).map(ColumnData::Int),
+        (ColumnData::SignedInt(a), ColumnData::SignedInt(b)) => a.checked_add(b).map(ColumnData::SignedInt),
+        (ColumnData::Float(a), ColumnData::Float(b)) => Some(ColumnData::Float(a + b)),
+        (ColumnData::Decimal(a), ColumnData::Decimal(b)) => a.checked_add(b).map(ColumnData::Decimal),
+        _ => None,
+    }
+}
+
+// Keep the current pick unless the data orders before (Less) or after (Greater) it
+fn pick(current: ColumnData, data: ColumnData, wanted: Ordering) -> Result<ColumnData, DataError> {
+    if matches!(current, ColumnData::Null) {
+        // Data without an order, such as NaN, can not be picked
+        data.compare(&data).ok_or(DataError)?;
+        return Ok(data);
+    }
+    match data.compare(&current).ok_or(DataError)? {
+        ordering if ordering == wanted => Ok(data),
+        _ => Ok(current),
+    }
+}
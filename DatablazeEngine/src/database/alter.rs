@@ -0,0 +1,118 @@
+use serde_json::Value;
+use datablaze_types::enums::{ColumnData, ColumnTypes};
+use crate::database::column::Column;
+use crate::database::exceptions::DataError;
+use crate::database::table::{convert_data, convert_value, Table};
+use crate::datastore::datastore::Row;
+
+/// Change to the columns of a table. Existing rows are converted along with it.
+pub enum Alteration {
+    /// Add a column at the end, existing rows get its default or null
+    Add(Column),
+    Drop(String),
+    /// Rename a column, change its type or both. Every value of the column has to convert to the
+    /// new type without losing anything.
+    Change { column: String, name: Option<String>, column_type: Option<ColumnTypes> },
+}
+
+impl Alteration {
+    /// The table after the alteration. Columns in a key can not be dropped, renaming one renames
+    /// it in the key.
+    pub fn table(&self, table: &Table) -> Result<Table, DataError> {
+        let mut columns = table.columns.clone();
+        let mut keys = table.keys.clone();
+        match self {
+            Alteration::Add(column) => {
+                // Existing rows would all reference the row of the default, which need not exist
+                if columns.iter().any(|existing| existing.name == column.name) || (column.references.is_some() && column.default.is_some()) {
+                    return Err(DataError);
+                }
+                columns.push(column.clone());
+            }
+            Alteration::Drop(name) => {
+                let index = column_index(&columns, name)?;
+                // A table always keeps a column
+                if columns.len() == 1 || keys.all().any(|key| key.contains(name)) {
+                    return Err(DataError);
+                }
+                columns.remove(index);
+            }
+            Alteration::Change { column, name, column_type } => {
+                let index = column_index(&columns, column)?;
+                if let Some(name) = name && columns.iter().any(|existing| existing.name == *name && existing.name != *column) {
+                    return Err(DataError);
+                }
+                let existing = &columns[index];
+                let default = match (&existing.default, column_type) {
+                    (Some(default), Some(column_type)) => Some(change_type(default.clone(), &existing.column_type, column_type)?),
+                    (default, _) => default.clone(),
+                };
+                let changed = Column::with_options(
+                    name.clone().unwrap_or(existing.name.clone()),
+                    column_type.clone().unwrap_or(existing.column_type.clone()),
+                    existing.nullable,
+                    default.map(|default| convert_value(default, column_type.as_ref().unwrap_or(&existing.column_type))),
+                    existing.vector_index,
+                    existing.references.clone(),
+                )?;
+                columns[index] = changed;
+                if let Some(name) = name {
+                    for key_column in keys.primary.iter_mut().chain(keys.unique.iter_mut().flatten()) {
+                        if key_column == column {
+                            *key_column = name.clone();
+                        }
+                    }
+                }
+            }
+        }
+        Table::with_keys(table.name.clone(), columns, keys)
+    }
+
+    /// A row of the table as it is after the alteration, given the columns from before it
+    pub fn row(&self, columns: &[Column], mut row: Row) -> Result<Row, DataError> {
+        match self {
+            Alteration::Add(column) => row.push(column.missing_data().ok_or(DataError)?),
+            Alteration::Drop(name) => {
+                row.remove(column_index(columns, name)?);
+            }
+            Alteration::Change { column, column_type: Some(column_type), .. } => {
+                let index = column_index(columns, column)?;
+                let data = std::mem::replace(&mut row[index], ColumnData::Null);
+                row[index] = change_type(data, &columns[index].column_type, column_type)?;
+            }
+            Alteration::Change { column_type: None, .. } => {}
+        }
+        Ok(row)
+    }
+}
+
+fn column_index(columns: &[Column], name: &str) -> Result<usize, DataError> {
+    columns.iter().position(|column| column.name == name).ok_or(DataError)
+}
+
+// Convert data through its JSON form, which the new type has to take as it is. Anything turns
+// into a string or a JSON document, and whole numbers into decimals or exact floats.
+fn change_type(data: ColumnData, from: &ColumnTypes, to: &ColumnTypes) -> Result<ColumnData, DataError> {
+    if matches!(data, ColumnData::Null) {
+        return Ok(ColumnData::Null);
+    }
+    let value = match (to, convert_value(data, from)) {
+        (ColumnTypes::String, Value::String(string)) => Value::String(string),
+        (ColumnTypes::String, value) => Value::String(value.to_string()),
+        (ColumnTypes::Decimal { .. }, Value::Number(number)) if !number.is_f64() => Value::String(number.to_string()),
+        (ColumnTypes::Float, Value::Number(number)) if !number.is_f64() => {
+            let float = number.as_f64().ok_or(DataError)?;
+            let exact = match (number.as_u64(), number.as_i64()) {
+                (Some(integer), _) => float as u64 == integer && float < u64::MAX as f64,
+                (_, Some(integer)) => float as i64 == integer && float >= i64::MIN as f64,
+                _ => false,
+            };
+            if !exact {
+                return Err(DataError);
+            }
+            Value::from(float)
+        }
+        (_, value) => value,
+    };
+    convert_data(to, value)
+}
@@ -0,0 +1,135 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use datablaze_types::enums::ColumnTypes;
+use crate::database::column::Column;
+use crate::database::database::Database;
+use crate::database::key::Keys;
+use crate::database::reference::Reference;
+use crate::database::table::Table;
+use crate::database::vector::Metric;
+use crate::datastore::exceptions::DatastoreError;
+
+const CATALOG_FILE_NAME: &str = "catalog.json";
+const CATALOG_TEMPORARY_FILE_NAME: &str = "catalog.json.tmp";
+
+/// Every database that keeps its schema across restarts, with its tables and their columns in
+/// order. Stored as JSON in the data directory.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Catalog {
+    pub databases: Vec<CatalogDatabase>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CatalogDatabase {
+    pub name: String,
+    pub datastore: String,
+    pub keep_schema: bool,
+    pub tables: Vec<CatalogTable>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CatalogTable {
+    pub name: String,
+    pub columns: Vec<CatalogColumn>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub primary_key: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CatalogColumn {
+    pub name: String,
+    pub column_type: String,
+    #[serde(default)]
+    pub nullable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vector_index: Option<Metric>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub references: Option<Reference>,
+}
+
+impl Catalog {
+    /// Read the catalog from the data directory, an engine that never wrote one has an empty catalog
+    pub fn load(data_dir: &Path) -> Result<Catalog, DatastoreError> {
+        let path = data_dir.join(CATALOG_FILE_NAME);
+        if !path.exists() {
+            return Ok(Catalog::default());
+        }
+        serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|e| DatastoreError::Corrupt(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn from_databases(databases: &[Arc<Database>]) -> Catalog {
+        let databases = databases
+            .iter()
+            .filter(|database| database.keep_schema)
+            .map(|database| CatalogDatabase {
+                name: database.name.to_string(),
+                datastore: database.datastore_type().to_string(),
+                keep_schema: database.keep_schema,
+                tables: database.tables().iter().map(CatalogTable::from).collect(),
+            })
+            .collect();
+        Catalog { databases }
+    }
+
+    // The new catalog is written next to the old one and then renamed over it, so a crash leaves
+    // either of the two
+    pub fn save(&self, data_dir: &Path) -> Result<(), DatastoreError> {
+        fs::create_dir_all(data_dir)?;
+        let temporary = data_dir.join(CATALOG_TEMPORARY_FILE_NAME);
+        let mut file = File::create(&temporary)?;
+        file.write_all(&serde_json::to_vec_pretty(self).map_err(io::Error::from)?)?;
+        file.sync_all()?;
+        fs::rename(&temporary, data_dir.join(CATALOG_FILE_NAME))?;
+        File::open(data_dir)?.sync_all()?;
+        Ok(())
+    }
+}
+
+impl From<&Table> for CatalogTable {
+    fn from(table: &Table) -> Self {
+        let columns = table
+            .columns
+            .iter()
+            .map(|column| CatalogColumn {
+                name: column.name.to_string(),
+                column_type: column.column_type.to_string(),
+                nullable: column.nullable,
+                default: column.default_value(),
+                vector_index: column.vector_index,
+                references: column.references.clone(),
+            })
+            .collect();
+        CatalogTable {
+            name: table.name.to_string(),
+            columns,
+            primary_key: table.keys.primary.clone(),
+            unique: table.keys.unique.clone(),
+        }
+    }
+}
+
+impl CatalogTable {
+    pub fn into_table(self) -> Result<Table, DatastoreError> {
+        let mut columns = Vec::new();
+        for column in self.columns {
+            let column_type = ColumnTypes::from_str(&column.column_type)
+                .map_err(|_| DatastoreError::Corrupt(format!("Unknown column type in catalog: {}", column.column_type)))?;
+            let column = Column::with_options(column.name, column_type, column.nullable, column.default, column.vector_index, column.references)
+                .map_err(|_| DatastoreError::Corrupt(format!("Invalid default in catalog for table {}", self.name)))?;
+            columns.push(column);
+        }
+        let keys = Keys { primary: self.primary_key, unique: self.unique };
+        Table::with_keys(self.name.clone(), columns, keys)
+            .map_err(|_| DatastoreError::Corrupt(format!("Invalid keys in catalog for table {}", self.name)))
+    }
+}
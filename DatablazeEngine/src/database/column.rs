@@ -1,23 +1,70 @@
 ﻿use datablaze_types::enums::{ColumnData, ColumnTypes};
-use crate::database::exceptions::{DataError, IndexError};
+use serde_json::Value;
+use crate::database::exceptions::DataError;
+use crate::database::reference::{OnDelete, Reference};
+use crate::database::table::{convert_data, convert_value};
+use crate::database::vector::Metric;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Column {
     pub name: String,
     pub column_type: ColumnTypes,
-    pub data: Vec<ColumnData>,
+    pub nullable: bool,
+    /// Data for the column when a row leaves it out
+    pub default: Option<ColumnData>,
+    /// Metric of the approximate nearest neighbour index on a vector column
+    pub vector_index: Option<Metric>,
+    /// Table whose primary key the column holds
+    pub references: Option<Reference>,
 }
 
 impl Column {
     pub fn new(name: String, column_type: ColumnTypes) -> Column {
-        Column { name, column_type, data: Vec::new() }
+        Column { name, column_type, nullable: false, default: None, vector_index: None, references: None }
     }
-    
-    pub fn add_data(&mut self, data: ColumnData) -> Result<usize, DataError> {
-        if !data.validate(self.column_type) {
+
+    /// Column with its default as JSON, a null default is the same as none. Only vector columns
+    /// can have an index, and only nullable columns can be set to null when the row they reference
+    /// is deleted.
+    pub fn with_options(
+        name: String,
+        column_type: ColumnTypes,
+        nullable: bool,
+        default: Option<Value>,
+        vector_index: Option<Metric>,
+        references: Option<Reference>,
+    ) -> Result<Column, DataError> {
+        let default = match default {
+            None | Some(Value::Null) => None,
+            Some(default) => Some(convert_data(&column_type, default)?),
+        };
+        if vector_index.is_some() && !matches!(column_type, ColumnTypes::Vector(_)) {
+            return Err(DataError);
+        }
+        if !nullable && references.as_ref().is_some_and(|reference| reference.on_delete == OnDelete::SetNull) {
             return Err(DataError);
         }
-        self.data.push(data);
-        Ok(self.data.len() - 1)
+        Ok(Column { name, column_type, nullable, default, vector_index, references })
+    }
+
+    pub fn default_value(&self) -> Option<Value> {
+        self.default.clone().map(|default| convert_value(default, &self.column_type))
+    }
+
+    pub fn validate(&self, data: &ColumnData) -> bool {
+        match data {
+            ColumnData::Null => self.nullable,
+            data => data.validate(&self.column_type),
+        }
+    }
+
+    /// Data for a row that leaves the column out: its default, or null if it has none. None
+    /// when the column has to be set.
+    pub fn missing_data(&self) -> Option<ColumnData> {
+        match &self.default {
+            Some(default) => Some(default.clone()),
+            None if self.nullable => Some(ColumnData::Null),
+            None => None,
+        }
     }
 }
\ No newline at end of file
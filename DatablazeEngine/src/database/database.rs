@@ -1,21 +1,749 @@
-﻿use crate::database::table::Table;
+﻿use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde_json::Value;
+use datablaze_types::enums::ColumnData;
+use crate::config::EngineConfig;
+use crate::database::aggregate::AggregateFunction;
+use crate::database::alter::Alteration;
+use crate::database::query::{Filter, Projection, QueryRow};
+use crate::database::catalog::CatalogDatabase;
+use crate::database::exceptions::{DataError, TableError, TransactionError};
+use crate::database::hnsw::Hnsw;
+use crate::database::key::KeyIndex;
+use crate::database::mvcc::{RowVersions, Timestamp};
+use crate::database::reference::{validate_references, OnDelete, ReferenceIndex};
+use crate::database::table::Table;
+use crate::database::transaction::{Transaction, TransactionId, TransactionView};
+use crate::database::vector::{Nearest, NearestRow};
 use crate::DatastoreVariants;
-use crate::datastore::datastore::Datastore;
-use crate::datastore::file_based_datastore::FileBasedDatastore;
+use crate::datastore::datastore::{Datastore, Row, RowChange, RowId, SharedDatastore};
+use crate::datastore::exceptions::DatastoreError;
+use crate::datastore::file_based_datastore::{validate_name, FileBasedDatastore};
 use crate::datastore::in_memory_datastore::InMemoryDatastore;
 
 pub struct Database {
     pub name: String,
-    pub datastore: Box<dyn Datastore + Send>,
-    pub tables: Vec<Table>,
+    /// Whether the database and its tables are kept in the catalog across restarts. Always set
+    /// for file based databases, in memory databases only keep their schema and not their rows.
+    pub keep_schema: bool,
+    // Locks are taken in this order: a transaction, the tables, the rows of a table (several
+    // tables in order of their name), the vector indexes of those tables, the key indexes of
+    // those and the tables they reference or are referenced by, their reference indexes, the
+    // datastore. None of them are held between requests. The tables are only write locked to add
+    // or alter a table, so the schema never waits on rows.
+    tables: RwLock<Vec<Arc<TableEntry>>>,
+    // Read locked by commits and write locked by alters, before anything else. A commit has left
+    // the open transactions before its rows are in, this keeps the columns from changing under it.
+    altering: RwLock<()>,
+    datastore: SharedDatastore,
+    transactions: Mutex<HashMap<TransactionId, OpenTransaction>>,
+    next_transaction_id: AtomicU64,
+    // Timestamp of the last commit. Only moves while the datastore is write locked.
+    last_commit: AtomicU64,
+}
+
+// What a request does with the rows of a table
+#[derive(Clone, Copy)]
+enum Access {
+    // Only reads rows, so the key indexes are not needed
+    Read,
+    // Adds rows, which are not locked since no one else can see them
+    Insert,
+    // Changes or deletes a row, which is locked first
+    Change(RowId),
+}
+
+// What an alter and a release need to know of an open transaction, without waiting for the
+// request that is using it
+struct OpenTransaction {
+    transaction: Arc<Mutex<Transaction>>,
+    snapshot: Timestamp,
+    // Tables the transaction worked on, the only ones that can hold its locks. A table is added
+    // before a request looks it up, so an alter either sees it here or the request finds the
+    // altered table.
+    tables: BTreeSet<String>,
+}
+
+// Transactions read from their snapshot, so readers of a table do not keep its writers waiting
+// for longer than a commit takes, and neither wait on other tables
+struct TableEntry {
+    table: Table,
+    rows: RwLock<RowVersions>,
+    // Hold the latest committed vectors, only changed by commits
+    indexes: RwLock<Vec<Hnsw>>,
+    // One per key of the table, the primary key first. Also only changed by commits.
+    keys: RwLock<Vec<KeyIndex>>,
+    // One per column referencing a table, also only changed by commits
+    references: RwLock<Vec<ReferenceIndex>>,
 }
 
 impl Database {
-    pub fn new(name: String, datastore_variant: DatastoreVariants) -> Database {
-        let datastore: Box<dyn Datastore + Send> = match datastore_variant {
-            DatastoreVariants::InMemory => Box::new(InMemoryDatastore { }),
-            DatastoreVariants::FileBased => Box::new(FileBasedDatastore { })
+    /// A file based database caches at most the given number of pages
+    pub fn new(name: String, datastore_variant: DatastoreVariants, keep_schema: bool, config: &EngineConfig, cache_pages: usize) -> Result<Database, TableError> {
+        match datastore_variant {
+            DatastoreVariants::InMemory => {
+                if keep_schema {
+                    // The name ends up in the catalog file
+                    validate_name(&name)?;
+                }
+                Database::with_datastore(name, Box::new(InMemoryDatastore::default()), Vec::new(), keep_schema)
+            }
+            DatastoreVariants::FileBased => {
+                validate_name(&name)?;
+                Database::open(name, config, cache_pages)
+            }
+        }
+    }
+
+    /// Open a file based database from its directory, loading every table stored in it
+    pub fn open(name: String, config: &EngineConfig, cache_pages: usize) -> Result<Database, TableError> {
+        let directory = config.data_dir.join(&name);
+        let datastore = FileBasedDatastore::open(directory, cache_pages)?;
+        let mut tables = Vec::new();
+        for (table_name, columns, keys) in datastore.tables() {
+            let table = Table::with_keys(table_name, columns, keys)
+                .map_err(|_| DatastoreError::Corrupt(format!("Invalid keys for table of database {}", name)))?;
+            tables.push(table);
+        }
+        Database::with_datastore(name, Box::new(datastore), tables, true)
+    }
+
+    fn with_datastore(name: String, datastore: Box<dyn Datastore + Send + Sync>, tables: Vec<Table>, keep_schema: bool) -> Result<Database, TableError> {
+        let tables = tables.into_iter().map(TableEntry::new).collect::<Vec<Arc<TableEntry>>>();
+        for entry in &tables {
+            entry.build_indexes(datastore.as_ref())?;
+        }
+        Ok(Database {
+            name,
+            keep_schema,
+            tables: RwLock::new(tables),
+            altering: RwLock::new(()),
+            datastore: RwLock::new(datastore),
+            transactions: Mutex::new(HashMap::new()),
+            next_transaction_id: AtomicU64::new(0),
+            last_commit: AtomicU64::new(0),
+        })
+    }
+
+    /// Rebuild a database from its catalog entry. File based databases load their tables from
+    /// disk and only take their order from the catalog, in memory databases recreate them empty.
+    pub fn restore(entry: CatalogDatabase, config: &EngineConfig, cache_pages: usize) -> Result<Database, TableError> {
+        let datastore_variant = DatastoreVariants::from_str(&entry.datastore)
+            .map_err(|_| DatastoreError::Corrupt(format!("Unknown datastore in catalog: {}", entry.datastore)))?;
+        let mut database = Database::new(entry.name, datastore_variant, entry.keep_schema, config, cache_pages)?;
+        let mut tables = Vec::new();
+        for table in entry.tables {
+            tables.push(table.into_table()?);
+        }
+        match datastore_variant {
+            DatastoreVariants::InMemory => {
+                for table in tables {
+                    database.create_table(table, false)?;
+                }
+            }
+            DatastoreVariants::FileBased => {
+                let existing = database.tables.get_mut().unwrap();
+                if let Some(missing) = tables.iter().find(|table| !existing.iter().any(|existing| existing.table.name == table.name)) {
+                    return Err(DatastoreError::Corrupt(format!("Table {} of database {} is missing from disk", missing.name, database.name)).into());
+                }
+                // Tables that never made it into the catalog go last
+                existing.sort_by_key(|existing| tables.iter().position(|table| table.name == existing.table.name).unwrap_or(usize::MAX));
+            }
+        }
+        Ok(database)
+    }
+
+    pub fn datastore_type(&self) -> DatastoreVariants {
+        self.datastore.read().unwrap().get_type()
+    }
+
+    /// Change the number of pages a file based database caches
+    pub fn set_cache_pages(&self, pages: usize) -> Result<(), TableError> {
+        Ok(self.datastore.write().unwrap().set_cache_pages(pages)?)
+    }
+
+    pub fn tables(&self) -> Vec<Table> {
+        self.tables.read().unwrap().iter().map(|entry| entry.table.clone()).collect()
+    }
+
+    /// Add a table, whose references have to match the primary keys of the tables they reference
+    pub fn add_table(&self, table: Table) -> Result<(), TableError> {
+        self.create_table(table, true)
+    }
+
+    /// Change the columns of a table and convert its rows, all or nothing. Fails while an open
+    /// transaction uses the table, since it may hold rows with the old columns.
+    pub fn alter_table(&self, name: &str, alteration: Alteration) -> Result<(), TableError> {
+        let _altering = self.altering.write().unwrap();
+        let mut tables = self.tables.write().unwrap();
+        let position = tables
+            .iter()
+            .position(|entry| entry.table.name == name)
+            .ok_or(DatastoreError::TableNotFound(name.to_string()))?;
+        self.check_unused(name)?;
+        let entry = &tables[position];
+        let old_columns = &entry.table.columns;
+        let altered = alteration.table(&entry.table)?;
+        // References to the table have to keep matching its primary key
+        let others: Vec<&Table> = tables.iter().filter(|other| other.table.name != name).map(|other| &other.table).collect();
+        validate_references(&altered, &others)?;
+        let with_altered: Vec<&Table> = others.iter().copied().chain([&altered]).collect();
+        for other in &others {
+            validate_references(other, &with_altered)?;
+        }
+        // Older versions of the rows are converted too, so open snapshots keep reading them
+        let rows = entry.rows.write().unwrap();
+        let versions = rows.converted(|row| alteration.row(old_columns, row))?;
+        let mut datastore = self.datastore.write().unwrap();
+        let converted = datastore
+            .scan(name)?
+            .into_iter()
+            .map(|(row_id, row)| Ok((row_id, alteration.row(old_columns, row)?)))
+            .collect::<Result<Vec<(RowId, Row)>, DataError>>()?;
+        // A new type may turn different data into the same, which the keys have to allow
+        let altered = TableEntry::new(altered);
+        let changes: Vec<(RowId, Option<&Row>)> = converted.iter().map(|(row_id, row)| (*row_id, Some(row))).collect();
+        for key in altered.keys.read().unwrap().iter() {
+            key.check(name, &changes, &[])?;
+        }
+        datastore.alter(name, &altered.table.columns, &altered.table.keys, converted)?;
+        *altered.rows.write().unwrap() = versions;
+        altered.build_indexes(datastore.as_ref())?;
+        drop(rows);
+        tables[position] = altered;
+        Ok(())
+    }
+
+    pub fn begin(&self) -> TransactionId {
+        self.expire_transactions();
+        let id = self.next_transaction_id.fetch_add(1, Ordering::SeqCst);
+        // The snapshot is taken while the transactions are locked, so a release that collects
+        // garbage either sees the new transaction or ran before its snapshot was taken
+        let mut transactions = self.transactions.lock().unwrap();
+        let snapshot = self.last_commit.load(Ordering::SeqCst);
+        let transaction = Arc::new(Mutex::new(Transaction::new(snapshot)));
+        transactions.insert(id, OpenTransaction { transaction, snapshot, tables: BTreeSet::new() });
+        id
+    }
+
+    /// Apply every change of the transaction to the datastore. A transaction that fails to
+    /// commit is rolled back.
+    pub fn commit(&self, id: TransactionId) -> Result<(), TableError> {
+        let _altering = self.altering.read().unwrap();
+        let open = self.take_transaction(id)?;
+        let mut transaction = open.transaction.lock().unwrap();
+        // The rows stay locked until the changes are in, so no one else can change them first
+        let result = self.apply(transaction.take_changes());
+        self.release(id, &open.tables);
+        result
+    }
+
+    pub fn rollback(&self, id: TransactionId) -> Result<(), TableError> {
+        let open = self.take_transaction(id)?;
+        self.release(id, &open.tables);
+        Ok(())
+    }
+
+    pub fn get_row(&self, table: &str, row_id: RowId, transaction: Option<TransactionId>) -> Result<HashMap<String, Value>, TableError> {
+        self.read(table, transaction, |table, datastore| table.get_row(datastore, row_id))
+    }
+
+    /// Row with the primary key, given as JSON by column name
+    pub fn get_row_by_key(&self, table: &str, key: HashMap<String, Value>, transaction: Option<TransactionId>) -> Result<QueryRow, TableError> {
+        let entry = self.table(table)?;
+        let key = entry.table.convert_key(key)?;
+        let candidate = entry.keys.read().unwrap().first().and_then(|index| index.find(&key));
+        // The index holds the latest committed rows, which is all a read outside of a transaction
+        // sees. A snapshot may still hold the key in another row.
+        if candidate.is_none() && transaction.is_none() {
+            return Err(DatastoreError::KeyNotFound(table.to_string()).into());
+        }
+        self.read(table, transaction, |table, datastore| {
+            let row_id = table.find_row(datastore, &key, candidate)?;
+            Ok((row_id, table.get_row(datastore, row_id)?))
+        })
+    }
+
+    pub fn aggregate(&self, table: &str, column: &str, function: AggregateFunction, transaction: Option<TransactionId>) -> Result<Value, TableError> {
+        self.read(table, transaction, |table, datastore| table.aggregate(datastore, column, function))
+    }
+
+    pub fn query(&self, table: &str, filters: Vec<Filter>, projections: Vec<Projection>, unnest: Option<&str>, transaction: Option<TransactionId>) -> Result<Vec<QueryRow>, TableError> {
+        self.read(table, transaction, |table, datastore| table.query(datastore, filters, projections, unnest))
+    }
+
+    /// Nearest neighbour search. An approximate search takes its candidates from the index on the
+    /// column, which only knows the latest committed rows, so in a transaction it can miss rows
+    /// changed since its snapshot.
+    pub fn nearest(&self, table: &str, nearest: Nearest, approximate: bool, filters: Vec<Filter>, projections: Vec<Projection>, transaction: Option<TransactionId>) -> Result<Vec<NearestRow>, TableError> {
+        let candidates = match approximate {
+            true => Some(self.table(table)?.candidates(&nearest)?),
+            false => None,
+        };
+        self.read(table, transaction, |table, datastore| table.nearest(datastore, &nearest, filters, projections, candidates))
+    }
+
+    pub fn add_rows(&self, table: &str, rows: Vec<HashMap<String, Value>>, transaction: Option<TransactionId>) -> Result<Vec<RowId>, TableError> {
+        self.write(table, Access::Insert, transaction, |table, datastore| table.add_rows(datastore, rows))
+    }
+
+    pub fn update_row(&self, table: &str, row: HashMap<String, Value>, row_id: RowId, transaction: Option<TransactionId>) -> Result<(), TableError> {
+        self.write(table, Access::Change(row_id), transaction, |table, datastore| table.update_row(datastore, row, row_id))
+    }
+
+    /// Delete a row, along with what the references to it say to do: rows referencing it through
+    /// a cascading reference are deleted too, set null references are cleared, and a restricting
+    /// reference fails the delete
+    pub fn delete_row(&self, table: &str, row_id: RowId, transaction: Option<TransactionId>) -> Result<(), TableError> {
+        self.in_transaction(transaction, |id| {
+            let mut deletes = BTreeSet::new();
+            let mut nulls = BTreeSet::new();
+            let mut restricts = Vec::new();
+            self.plan_delete(id, table, row_id, &mut deletes, &mut nulls, &mut restricts)?;
+            // Only once every cascade is known, a row another step deletes does not hold it up
+            if let Some((table, by, _)) = restricts.into_iter().find(|(_, by, row_id)| !deletes.contains(&(by.clone(), *row_id))) {
+                return Err(DatastoreError::Referenced { table, by }.into());
+            }
+            // Before the deletes, so the other references of a cleared row still lead somewhere
+            for (table, row_id, column) in nulls {
+                if !deletes.contains(&(table.clone(), row_id)) {
+                    self.run(id, &table, Access::Change(row_id), |table, datastore| table.set_null(datastore, row_id, column))?;
+                }
+            }
+            for (table, row_id) in deletes {
+                self.run(id, &table, Access::Change(row_id), |table, datastore| table.delete_row(datastore, row_id))?;
+            }
+            Ok(())
+        })
+    }
+
+    // Tables restored from the catalog are not checked, a column added to a table can reference a
+    // table created after it
+    fn create_table(&self, table: Table, check_references: bool) -> Result<(), TableError> {
+        let mut tables = self.tables.write().unwrap();
+        if tables.iter().any(|existing| existing.table.name == table.name) {
+            return Err(DatastoreError::TableExists(table.name).into());
+        }
+        if check_references {
+            validate_references(&table, &tables.iter().map(|entry| &entry.table).collect::<Vec<&Table>>())?;
+        }
+        self.datastore.write().unwrap().create(&table.name, &table.columns, &table.keys)?;
+        tables.push(TableEntry::new(table));
+        Ok(())
+    }
+
+    // Lock a row to delete it, along with the rows referencing it for what their reference does
+    // once it is gone. Rows referencing it through a restricting reference are gathered as the
+    // referenced table, the referencing table and row. Nothing is written yet, so a restricting
+    // reference leaves the transaction as it was.
+    fn plan_delete(
+        &self,
+        id: TransactionId,
+        table_name: &str,
+        row_id: RowId,
+        deletes: &mut BTreeSet<(String, RowId)>,
+        nulls: &mut BTreeSet<(String, RowId, usize)>,
+        restricts: &mut Vec<(String, String, RowId)>,
+    ) -> Result<(), TableError> {
+        if !deletes.insert((table_name.to_string(), row_id)) {
+            return Ok(());
+        }
+        let row = self.run(id, table_name, Access::Change(row_id), |table, datastore| Ok(datastore.select(&table.name, row_id)?))?;
+        let entry = self.table(table_name)?;
+        let Some(data) = entry.table.primary_column().and_then(|position| row.get(position)) else { return Ok(()) };
+        let tables = self.tables.read().unwrap().clone();
+        for entry in tables {
+            for (position, column) in entry.table.columns.iter().enumerate() {
+                let Some(reference) = column.references.as_ref().filter(|reference| reference.table == table_name) else { continue };
+                for referencing in self.referencing_rows(id, &entry, position, data)? {
+                    match reference.on_delete {
+                        OnDelete::Restrict => restricts.push((table_name.to_string(), entry.table.name.to_string(), referencing)),
+                        OnDelete::Cascade => self.plan_delete(id, &entry.table.name, referencing, deletes, nulls, restricts)?,
+                        OnDelete::SetNull => {
+                            self.run(id, &entry.table.name, Access::Change(referencing), |table, datastore| Ok(datastore.select(&table.name, referencing).map(|_| ())?))?;
+                            nulls.insert((entry.table.name.to_string(), referencing, position));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Rows of the table referencing the data through the column: the latest committed ones the
+    // transaction did not write, and the ones it wrote. Committed rows its snapshot can not see
+    // are included, and fail to lock.
+    fn referencing_rows(&self, id: TransactionId, entry: &TableEntry, column: usize, data: &ColumnData) -> Result<Vec<RowId>, TableError> {
+        let transaction = self.transactions.lock().unwrap().get(&id).map(|open| open.transaction.clone()).ok_or(TransactionError::NotFound(id))?;
+        let transaction = transaction.lock().unwrap();
+        let written: BTreeMap<RowId, Option<&Row>> = transaction.written(&entry.table.name).collect();
+        let committed = entry
+            .references
+            .read()
+            .unwrap()
+            .iter()
+            .find(|index| index.column == column)
+            .map(|index| index.find(data))
+            .unwrap_or_default();
+        let mut rows: Vec<RowId> = committed.into_iter().filter(|row_id| !written.contains_key(row_id)).collect();
+        rows.extend(
+            written
+                .iter()
+                .filter(|(_, row)| row.and_then(|row| row.get(column)).and_then(|written| written.compare(data)) == Some(std::cmp::Ordering::Equal))
+                .map(|(row_id, _)| *row_id),
+        );
+        Ok(rows)
+    }
+
+    fn table(&self, name: &str) -> Result<Arc<TableEntry>, DatastoreError> {
+        self.tables
+            .read()
+            .unwrap()
+            .iter()
+            .find(|entry| entry.table.name == name)
+            .cloned()
+            .ok_or(DatastoreError::TableNotFound(name.to_string()))
+    }
+
+    pub(crate) fn read<T>(
+        &self,
+        table_name: &str,
+        transaction: Option<TransactionId>,
+        operation: impl FnOnce(&Table, &dyn Datastore) -> Result<T, TableError>,
+    ) -> Result<T, TableError> {
+        match transaction {
+            // Reads outside of a transaction see the latest committed rows, from a transaction of
+            // their own that keeps the versions they read from being collected. An alter waits
+            // for them, rather than failing on the transaction.
+            None => {
+                let _altering = self.altering.read().unwrap();
+                let id = self.begin();
+                let result = self.run(id, table_name, Access::Read, |table, datastore| operation(table, datastore));
+                let _ = self.rollback(id);
+                result
+            }
+            // Reads do not lock, they see the snapshot of their transaction
+            Some(id) => self.run(id, table_name, Access::Read, |table, datastore| operation(table, datastore)),
+        }
+    }
+
+    fn write<T>(
+        &self,
+        table_name: &str,
+        access: Access,
+        transaction: Option<TransactionId>,
+        operation: impl FnOnce(&Table, &mut dyn Datastore) -> Result<T, TableError>,
+    ) -> Result<T, TableError> {
+        self.in_transaction(transaction, |id| self.run(id, table_name, access, operation))
+    }
+
+    // Change rows inside the transaction, or without one in a transaction of their own that
+    // commits right away, so a change never shows up in the snapshot of an older transaction
+    fn in_transaction<T>(&self, transaction: Option<TransactionId>, operation: impl FnOnce(TransactionId) -> Result<T, TableError>) -> Result<T, TableError> {
+        if let Some(id) = transaction {
+            return operation(id);
+        }
+        let id = self.begin();
+        match operation(id) {
+            Ok(result) => {
+                self.commit(id)?;
+                Ok(result)
+            }
+            Err(e) => {
+                let _ = self.rollback(id);
+                Err(e)
+            }
+        }
+    }
+
+    // Run an operation inside a transaction, locking the row it changes first. A row locked by
+    // another transaction, or committed after the snapshot of this one, can not be changed. New
+    // rows are not locked, no one else can see them. The key indexes of the tables the table
+    // references are read along with its own, to check the references of the rows it writes. The
+    // rows of the table are not locked while the operation runs, so neither are the commits to it.
+    fn run<T>(
+        &self,
+        id: TransactionId,
+        table_name: &str,
+        access: Access,
+        operation: impl FnOnce(&Table, &mut dyn Datastore) -> Result<T, TableError>,
+    ) -> Result<T, TableError> {
+        self.expire_transactions();
+        let transaction = {
+            let mut transactions = self.transactions.lock().unwrap();
+            let open = transactions.get_mut(&id).ok_or(TransactionError::NotFound(id))?;
+            open.tables.insert(table_name.to_string());
+            open.transaction.clone()
         };
-        Database { name, datastore, tables: Vec::new() }
+        let mut transaction = transaction.lock().unwrap();
+        if transaction.ended {
+            return Err(TransactionError::NotFound(id).into());
+        }
+        transaction.touch();
+        let entry = self.table(table_name)?;
+        let mut related = BTreeMap::new();
+        if !matches!(access, Access::Read) {
+            related.insert(table_name.to_string(), entry.clone());
+            for reference in entry.table.columns.iter().filter_map(|column| column.references.as_ref()) {
+                if !related.contains_key(&reference.table) {
+                    related.insert(reference.table.to_string(), self.table(&reference.table)?);
+                }
+            }
+        }
+        if let Access::Change(row_id) = access
+            && !entry.rows.write().unwrap().lock(row_id, id, transaction.snapshot)
+        {
+            return Err(TransactionError::Conflict { table: table_name.to_string(), row_id }.into());
+        }
+        let key_guards: Vec<(&str, RwLockReadGuard<Vec<KeyIndex>>)> = related.iter().map(|(name, entry)| (name.as_str(), entry.keys.read().unwrap())).collect();
+        let keys: BTreeMap<&str, &[KeyIndex]> = key_guards.iter().map(|(name, keys)| (*name, keys.as_slice())).collect();
+        operation(&entry.table, &mut TransactionView { datastore: &self.datastore, table: &entry.table, versions: &entry.rows, keys: &keys, transaction: &mut transaction })
+    }
+
+    // Write the changes to the datastore as one commit, keeping the rows they replace for older
+    // snapshots. Every table the changes touch is write locked until their versions are in.
+    fn apply(&self, changes: Vec<RowChange>) -> Result<(), TableError> {
+        let mut entries = BTreeMap::new();
+        for change in &changes {
+            let table = change_key(change).0;
+            if !entries.contains_key(table) {
+                entries.insert(table.to_string(), self.table(table)?);
+            }
+        }
+        // The changed tables, the tables they reference and the tables referencing them
+        let mut checked = entries.clone();
+        for entry in self.tables.read().unwrap().iter() {
+            let mut references = entry.table.columns.iter().filter_map(|column| column.references.as_ref());
+            if references.any(|reference| entries.contains_key(&reference.table)) {
+                checked.insert(entry.table.name.to_string(), entry.clone());
+            }
+        }
+        for entry in entries.values() {
+            for reference in entry.table.columns.iter().filter_map(|column| column.references.as_ref()) {
+                if !checked.contains_key(&reference.table) {
+                    checked.insert(reference.table.to_string(), self.table(&reference.table)?);
+                }
+            }
+        }
+        let mut rows: BTreeMap<&str, _> = entries.iter().map(|(name, entry)| (name.as_str(), entry.rows.write().unwrap())).collect();
+        let mut indexes: BTreeMap<&str, _> = entries.iter().map(|(name, entry)| (name.as_str(), entry.indexes.write().unwrap())).collect();
+        let mut keys: BTreeMap<&str, _> = checked.iter().map(|(name, entry)| (name.as_str(), entry.keys.write().unwrap())).collect();
+        let mut references: BTreeMap<&str, _> = checked.iter().map(|(name, entry)| (name.as_str(), entry.references.write().unwrap())).collect();
+        let mut datastore = self.datastore.write().unwrap();
+        let mut replaced = Vec::new();
+        for change in &changes {
+            let (table, row_id) = change_key(change);
+            let before = match datastore.select(table, row_id) {
+                Ok(row) => Some(row),
+                Err(DatastoreError::RowNotFound(_)) => None,
+                Err(e) => return Err(e.into()),
+            };
+            replaced.push((table.to_string(), row_id, before));
+        }
+        // Checked again against everything committed since the transaction checked its changes
+        let changed: BTreeMap<&str, Vec<(RowId, Option<&Row>)>> = checked
+            .keys()
+            .map(|table| {
+                let table_changes = changes
+                    .iter()
+                    .filter(|change| change_key(change).0 == table)
+                    .map(|change| match change {
+                        RowChange::Put { row_id, row, .. } => (*row_id, Some(row)),
+                        RowChange::Delete { row_id, .. } => (*row_id, None),
+                    })
+                    .collect();
+                (table.as_str(), table_changes)
+            })
+            .collect();
+        for (table, table_changes) in &changed {
+            for key in keys[table].iter() {
+                key.check(table, table_changes, &[])?;
+            }
+        }
+        for (table, entry) in &entries {
+            // References of the changed rows have to lead to a row
+            for (position, column) in entry.table.columns.iter().enumerate() {
+                let Some(reference) = &column.references else { continue };
+                let primary = keys[reference.table.as_str()][0].changed(&changed[reference.table.as_str()]);
+                for (_, row) in &changed[table.as_str()] {
+                    if let Some(data) = row.and_then(|row| row.get(position))
+                        && !matches!(data, ColumnData::Null)
+                        && !primary.contains(std::slice::from_ref(data))
+                    {
+                        return Err(DatastoreError::ReferenceNotFound { table: table.to_string(), column: column.name.to_string() }.into());
+                    }
+                }
+            }
+            // A primary key no row holds anymore can not be referenced
+            let Some(primary_position) = entry.table.primary_column() else { continue };
+            let referencing: Vec<(&str, _)> = references
+                .iter()
+                .flat_map(|(name, indexes)| {
+                    indexes.iter().filter(|index| index.reference.table == *table).map(|index| (*name, index.changed(&changed[name])))
+                })
+                .collect();
+            if referencing.is_empty() {
+                continue;
+            }
+            let primary = keys[table.as_str()][0].changed(&changed[table.as_str()]);
+            for (_, _, before) in replaced.iter().filter(|(replaced_table, _, _)| replaced_table == table) {
+                let Some(data) = before.as_ref().and_then(|row| row.get(primary_position)) else { continue };
+                if primary.contains(std::slice::from_ref(data)) {
+                    continue;
+                }
+                if let Some((by, _)) = referencing.iter().find(|(_, index)| index.contains(data)) {
+                    return Err(DatastoreError::Referenced { table: table.to_string(), by: by.to_string() }.into());
+                }
+            }
+        }
+        // The changed rows of indexed tables by position in the changes, to index once they are in
+        let indexed: Vec<(usize, Option<Row>)> = changes
+            .iter()
+            .enumerate()
+            .filter(|(_, change)| {
+                let table = change_key(change).0;
+                !indexes[table].is_empty() || !keys[table].is_empty() || !references[table].is_empty()
+            })
+            .map(|(position, change)| match change {
+                RowChange::Put { row, .. } => (position, Some(row.clone())),
+                RowChange::Delete { .. } => (position, None),
+            })
+            .collect();
+        datastore.apply(changes)?;
+        for (position, row) in indexed {
+            let (table, row_id, before) = &replaced[position];
+            for index in indexes.get_mut(table.as_str()).unwrap().iter_mut() {
+                index.update(*row_id, indexed_vector(index, row.as_ref()));
+            }
+            for key in keys.get_mut(table.as_str()).unwrap().iter_mut() {
+                key.update(*row_id, before.as_ref(), row.as_ref());
+            }
+            for reference in references.get_mut(table.as_str()).unwrap().iter_mut() {
+                reference.update(*row_id, before.as_ref(), row.as_ref());
+            }
+        }
+        let committed = self.last_commit.fetch_add(1, Ordering::SeqCst) + 1;
+        for (table, row_id, before) in replaced {
+            rows.get_mut(table.as_str()).unwrap().record(row_id, committed, before);
+        }
+        Ok(())
+    }
+
+    // Stop the transaction from being used by any other request
+    fn take_transaction(&self, id: TransactionId) -> Result<OpenTransaction, TransactionError> {
+        let open = self.transactions.lock().unwrap().remove(&id).ok_or(TransactionError::NotFound(id))?;
+        open.transaction.lock().unwrap().ended = true;
+        Ok(open)
+    }
+
+    // Unlock the rows of an ended transaction, and drop the versions older than every snapshot
+    // that is still open from its tables since they are never read again
+    fn release(&self, id: TransactionId, tables: &BTreeSet<String>) {
+        let oldest_snapshot = self
+            .transactions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|open| open.snapshot)
+            .min()
+            .unwrap_or(self.last_commit.load(Ordering::SeqCst));
+        for entry in self.tables.read().unwrap().iter().filter(|entry| tables.contains(&entry.table.name)) {
+            let mut rows = entry.rows.write().unwrap();
+            rows.unlock(id);
+            rows.collect_garbage(oldest_snapshot);
+        }
+    }
+
+    // Only the transactions that worked on the table, transactions busy with other tables do
+    // not hold an alter up
+    fn check_unused(&self, table: &str) -> Result<(), TransactionError> {
+        if self.transactions.lock().unwrap().values().any(|open| open.tables.contains(table)) {
+            return Err(TransactionError::TableInUse(table.to_string()));
+        }
+        Ok(())
+    }
+
+    fn expire_transactions(&self) {
+        let expired: Vec<TransactionId> = self
+            .transactions
+            .lock()
+            .unwrap()
+            .iter()
+            // A transaction that is in use right now is not expired
+            .filter(|(_, open)| open.transaction.try_lock().is_ok_and(|transaction| transaction.is_expired()))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            let _ = self.rollback(id);
+        }
+    }
+}
+
+impl TableEntry {
+    fn new(table: Table) -> Arc<TableEntry> {
+        let indexes = table
+            .columns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, column)| column.vector_index.map(|metric| Hnsw::new(index, metric)))
+            .collect();
+        let keys = table.keys.all().map(|key| KeyIndex::new(key, &table.columns)).collect();
+        let references = table
+            .columns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, column)| column.references.clone().map(|reference| ReferenceIndex::new(index, reference)))
+            .collect();
+        Arc::new(TableEntry {
+            table,
+            rows: RwLock::new(RowVersions::default()),
+            indexes: RwLock::new(indexes),
+            keys: RwLock::new(keys),
+            references: RwLock::new(references),
+        })
+    }
+
+    fn build_indexes(&self, datastore: &dyn Datastore) -> Result<(), DatastoreError> {
+        let mut indexes = self.indexes.write().unwrap();
+        let mut keys = self.keys.write().unwrap();
+        let mut references = self.references.write().unwrap();
+        if indexes.is_empty() && keys.is_empty() && references.is_empty() {
+            return Ok(());
+        }
+        for (row_id, row) in datastore.scan(&self.table.name)? {
+            for index in indexes.iter_mut() {
+                index.update(row_id, indexed_vector(index, Some(&row)));
+            }
+            for key in keys.iter_mut() {
+                key.update(row_id, None, Some(&row));
+            }
+            for reference in references.iter_mut() {
+                reference.update(row_id, None, Some(&row));
+            }
+        }
+        Ok(())
+    }
+
+    // Rows the index on the searched column picks, the search has to use the metric of the index
+    fn candidates(&self, nearest: &Nearest) -> Result<Vec<RowId>, DataError> {
+        let (column, target) = self.table.search_vector(nearest)?;
+        let indexes = self.indexes.read().unwrap();
+        let index = indexes.iter().find(|index| index.column == column && index.metric == nearest.metric).ok_or(DataError)?;
+        Ok(index.search(&target, nearest.k))
+    }
+}
+
+fn indexed_vector<'a>(index: &Hnsw, row: Option<&'a Row>) -> Option<&'a [f32]> {
+    match row.and_then(|row| row.get(index.column)) {
+        Some(ColumnData::Vector(vector)) => Some(vector),
+        _ => None,
+    }
+}
+
+fn change_key(change: &RowChange) -> (&str, RowId) {
+    match change {
+        RowChange::Put { table, row_id, .. } | RowChange::Delete { table, row_id } => (table, *row_id),
     }
 }
\ No newline at end of file
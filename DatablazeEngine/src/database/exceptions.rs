@@ -1,4 +1,63 @@
+use std::fmt::{Display, Formatter};
+use crate::database::transaction::TransactionId;
+use crate::datastore::datastore::RowId;
+use crate::datastore::exceptions::DatastoreError;
 
-
+#[derive(Debug)]
 pub struct DataError;
-pub struct IndexError;
+
+#[derive(Debug)]
+pub enum TransactionError {
+    NotFound(TransactionId),
+    /// The row is locked by another transaction
+    Conflict { table: String, row_id: RowId },
+    /// The columns of a table can not change while an open transaction uses it
+    TableInUse(String),
+}
+
+#[derive(Debug)]
+pub enum TableError {
+    Data(DataError),
+    Datastore(DatastoreError),
+    Transaction(TransactionError),
+}
+
+impl Display for TableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableError::Data(_) => write!(f, "Data does not match the table columns"),
+            TableError::Datastore(e) => write!(f, "{}", e),
+            TableError::Transaction(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Display for TransactionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionError::NotFound(id) => write!(f, "Transaction not found: {}", id),
+            TransactionError::Conflict { table, row_id } => {
+                write!(f, "Row {} of table {} is locked by another transaction", row_id, table)
+            }
+            TransactionError::TableInUse(table) => write!(f, "Table {} is in use by an open transaction", table),
+        }
+    }
+}
+
+impl From<DataError> for TableError {
+    fn from(e: DataError) -> Self {
+        TableError::Data(e)
+    }
+}
+
+impl From<DatastoreError> for TableError {
+    fn from(e: DatastoreError) -> Self {
+        TableError::Datastore(e)
+    }
+}
+
+impl From<TransactionError> for TableError {
+    fn from(e: TransactionError) -> Self {
+        TableError::Transaction(e)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,187 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use crate::database::vector::Metric;
+use crate::datastore::datastore::RowId;
+
+/// Neighbours of a node on every layer above the bottom one, which has twice as many
+const M: usize = 16;
+/// Candidates kept per layer while inserting a node
+const EF_CONSTRUCTION: usize = 100;
+/// Least candidates kept while searching
+pub const EF_SEARCH: usize = 64;
+const MAX_LEVEL: usize = 16;
+
+struct Node {
+    row_id: RowId,
+    vector: Vec<f32>,
+    // Neighbours per layer, from the bottom layer up to the level of the node
+    neighbours: Vec<Vec<usize>>,
+    deleted: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Approximate nearest neighbour index over a vector column, a hierarchical navigable small world
+/// graph. Kept in memory and built again from the rows when the database opens.
+pub struct Hnsw {
+    pub column: usize,
+    pub metric: Metric,
+    nodes: Vec<Node>,
+    // The live node of every row. Changed and deleted rows leave their old node in the graph,
+    // marked deleted, until the graph is rebuilt.
+    row_nodes: HashMap<RowId, usize>,
+    // Node with the highest level
+    entry: Option<usize>,
+    // Levels are picked at random, from a fixed seed so the graph does not depend on luck
+    random: u64,
+}
+
+impl Hnsw {
+    pub fn new(column: usize, metric: Metric) -> Hnsw {
+        Hnsw { column, metric, nodes: Vec::new(), row_nodes: HashMap::new(), entry: None, random: 0x2545_f491_4f6c_dd1d }
+    }
+
+    /// Index the vector of a row, or stop indexing the row when it has none
+    pub fn update(&mut self, row_id: RowId, vector: Option<&[f32]>) {
+        self.remove(row_id);
+        if let Some(vector) = vector {
+            self.insert(row_id, vector.to_vec());
+        }
+    }
+
+    /// Rows whose vectors are likely among the `ef` nearest to the query, nearest first
+    pub fn search(&self, query: &[f32], ef: usize) -> Vec<RowId> {
+        let Some(entry) = self.entry else { return Vec::new() };
+        let mut nearest = entry;
+        for layer in (1..self.nodes[entry].neighbours.len()).rev() {
+            nearest = self.search_layer(query, nearest, 1, layer)[0].node;
+        }
+        self.search_layer(query, nearest, ef.max(EF_SEARCH), 0)
+            .into_iter()
+            .filter(|candidate| !self.nodes[candidate.node].deleted)
+            .map(|candidate| self.nodes[candidate.node].row_id)
+            .collect()
+    }
+
+    fn insert(&mut self, row_id: RowId, vector: Vec<f32>) {
+        let level = self.random_level();
+        let node = self.nodes.len();
+        self.nodes.push(Node { row_id, vector, neighbours: vec![Vec::new(); level + 1], deleted: false });
+        self.row_nodes.insert(row_id, node);
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+        let top = self.nodes[entry].neighbours.len() - 1;
+        let query = self.nodes[node].vector.clone();
+        let mut nearest = entry;
+        for layer in (level + 1..=top).rev() {
+            nearest = self.search_layer(&query, nearest, 1, layer)[0].node;
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(&query, nearest, EF_CONSTRUCTION, layer);
+            nearest = candidates[0].node;
+            let neighbours: Vec<usize> = candidates.iter().take(M).map(|candidate| candidate.node).collect();
+            for &neighbour in &neighbours {
+                self.nodes[neighbour].neighbours[layer].push(node);
+                self.prune(neighbour, layer);
+            }
+            self.nodes[node].neighbours[layer] = neighbours;
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    fn remove(&mut self, row_id: RowId) {
+        if let Some(node) = self.row_nodes.remove(&row_id) {
+            self.nodes[node].deleted = true;
+            // Deleted nodes still lead searches to live ones, until they are the majority
+            if self.row_nodes.len() * 2 < self.nodes.len() {
+                self.rebuild();
+            }
+        }
+    }
+
+    fn rebuild(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        self.row_nodes.clear();
+        self.entry = None;
+        for node in nodes.into_iter().filter(|node| !node.deleted) {
+            self.insert(node.row_id, node.vector);
+        }
+    }
+
+    // Keep the nearest neighbours of a node on a layer that has too many
+    fn prune(&mut self, node: usize, layer: usize) {
+        let most = if layer == 0 { 2 * M } else { M };
+        if self.nodes[node].neighbours[layer].len() <= most {
+            return;
+        }
+        let vector = &self.nodes[node].vector;
+        let mut neighbours: Vec<Candidate> = self.nodes[node].neighbours[layer]
+            .iter()
+            .map(|&neighbour| Candidate { distance: self.metric.distance(vector, &self.nodes[neighbour].vector), node: neighbour })
+            .collect();
+        neighbours.sort();
+        self.nodes[node].neighbours[layer] = neighbours.into_iter().take(most).map(|candidate| candidate.node).collect();
+    }
+
+    // Greedy search of one layer from the entry node, keeping the `ef` nearest nodes seen.
+    // Returns them nearest first.
+    fn search_layer(&self, query: &[f32], entry: usize, ef: usize, layer: usize) -> Vec<Candidate> {
+        let first = Candidate { distance: self.metric.distance(query, &self.nodes[entry].vector), node: entry };
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::from([Reverse(first)]);
+        let mut found = BinaryHeap::from([first]);
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            let furthest = found.peek().unwrap().distance;
+            if found.len() >= ef && candidate.distance > furthest {
+                break;
+            }
+            for &neighbour in &self.nodes[candidate.node].neighbours[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let distance = self.metric.distance(query, &self.nodes[neighbour].vector);
+                if found.len() < ef || distance < found.peek().unwrap().distance {
+                    let neighbour = Candidate { distance, node: neighbour };
+                    candidates.push(Reverse(neighbour));
+                    found.push(neighbour);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    // Level l is picked with a chance of about M^-l
+    fn random_level(&mut self) -> usize {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        let uniform = (self.random >> 11) as f64 / (1u64 << 53) as f64;
+        let level = -(1.0 - uniform).ln() / (M as f64).ln();
+        (level as usize).min(MAX_LEVEL)
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::{HashMap, HashSet};
+use datablaze_types::enums::ColumnData;
+use crate::database::column::Column;
+use crate::database::exceptions::DataError;
+use crate::datastore::datastore::{Row, RowId};
+use crate::datastore::encoding::encode_row;
+use crate::datastore::exceptions::DatastoreError;
+
+/// Sets of columns whose data no two rows of a table share. A row with null in a key column
+/// does not count for that key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Keys {
+    /// Identifies a row, empty for a table without one. Its columns can not be nullable.
+    pub primary: Vec<String>,
+    pub unique: Vec<Vec<String>>,
+}
+
+impl Keys {
+    /// Every key, the primary key first
+    pub fn all(&self) -> impl Iterator<Item = &Vec<String>> {
+        std::iter::once(&self.primary).filter(|primary| !primary.is_empty()).chain(&self.unique)
+    }
+
+    /// Check the keys against the columns of their table. Every key needs a column, and can only
+    /// name a column of the table once.
+    pub fn validate(&self, columns: &[Column]) -> Result<(), DataError> {
+        for key in self.all() {
+            if key.is_empty() { return Err(DataError) };
+            for (position, name) in key.iter().enumerate() {
+                if key[..position].contains(name) || !columns.iter().any(|column| column.name == *name) {
+                    return Err(DataError);
+                }
+            }
+        }
+        if columns.iter().any(|column| column.nullable && self.primary.contains(&column.name)) {
+            return Err(DataError);
+        }
+        Ok(())
+    }
+}
+
+/// Index over the latest committed rows of a table for one of its keys, from the data in the key
+/// columns to the row holding it. Kept in memory and built again from the rows when the database
+/// opens.
+pub struct KeyIndex {
+    pub columns: Vec<String>,
+    positions: Vec<usize>,
+    rows: HashMap<Vec<u8>, RowId>,
+}
+
+impl KeyIndex {
+    pub fn new(key: &[String], columns: &[Column]) -> KeyIndex {
+        let positions = key
+            .iter()
+            .map(|name| columns.iter().position(|column| column.name == *name).unwrap())
+            .collect();
+        KeyIndex { columns: key.to_vec(), positions, rows: HashMap::new() }
+    }
+
+    /// Row holding the data, given in the order of the key columns
+    pub fn find(&self, data: &[ColumnData]) -> Option<RowId> {
+        let mut key = Vec::new();
+        encode_row(data, &mut key);
+        self.rows.get(&key).copied()
+    }
+
+    /// Index a row as it is after a commit, given the row it replaced
+    pub fn update(&mut self, row_id: RowId, before: Option<&Row>, after: Option<&Row>) {
+        // Another row of the same commit may have taken over the key already
+        if let Some(key) = before.and_then(|row| self.key(row)) && self.rows.get(&key) == Some(&row_id) {
+            self.rows.remove(&key);
+        }
+        if let Some(key) = after.and_then(|row| self.key(row)) {
+            self.rows.insert(key, row_id);
+        }
+    }
+
+    /// Check that changed rows keep the key unique among each other, among rows changed earlier
+    /// that are not in the index yet, and among the indexed rows that did not change. Changes are
+    /// None for deleted rows.
+    pub fn check(&self, table: &str, changes: &[(RowId, Option<&Row>)], earlier: &[(RowId, Option<&Row>)]) -> Result<(), DatastoreError> {
+        let changed: HashSet<RowId> = changes.iter().chain(earlier).map(|(row_id, _)| *row_id).collect();
+        let mut taken = HashSet::new();
+        for (_, row) in earlier.iter().chain(changes) {
+            let Some(key) = row.and_then(|row| self.key(row)) else { continue };
+            let indexed = self.rows.get(&key).is_some_and(|row_id| !changed.contains(row_id));
+            if indexed || !taken.insert(key) {
+                return Err(DatastoreError::DuplicateKey { table: table.to_string(), columns: self.columns.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    /// The index as it is once the changes are in, None for deleted rows
+    pub fn changed(&self, changes: &[(RowId, Option<&Row>)]) -> ChangedKeys<'_> {
+        ChangedKeys {
+            index: self,
+            changed: changes.iter().map(|(row_id, _)| *row_id).collect(),
+            added: changes.iter().filter_map(|(_, row)| row.and_then(|row| self.key(row))).collect(),
+        }
+    }
+
+    // Encoded data of the key columns, none when any of them is null
+    fn key(&self, row: &Row) -> Option<Vec<u8>> {
+        let data = self.positions.iter().map(|position| row.get(*position).cloned()).collect::<Option<Row>>()?;
+        if data.iter().any(|data| matches!(data, ColumnData::Null)) {
+            return None;
+        }
+        let mut key = Vec::new();
+        encode_row(&data, &mut key);
+        Some(key)
+    }
+}
+
+pub struct ChangedKeys<'a> {
+    index: &'a KeyIndex,
+    changed: HashSet<RowId>,
+    added: HashSet<Vec<u8>>,
+}
+
+impl ChangedKeys<'_> {
+    /// Whether a row holds the data, given in the order of the key columns
+    pub fn contains(&self, data: &[ColumnData]) -> bool {
+        let mut key = Vec::new();
+        encode_row(data, &mut key);
+        self.added.contains(&key) || self.index.rows.get(&key).is_some_and(|row_id| !self.changed.contains(row_id))
+    }
+}
@@ -1,4 +1,15 @@
-﻿pub mod database;
+﻿#[allow(clippy::module_inception)]
+pub mod database;
+pub mod catalog;
 pub mod table;
 pub mod column;
-pub mod exceptions;
\ No newline at end of file
+pub mod exceptions;
+pub mod transaction;
+pub mod mvcc;
+pub mod aggregate;
+pub mod query;
+pub mod vector;
+pub mod hnsw;
+pub mod alter;
+pub mod key;
+pub mod reference;
\ No newline at end of file
@@ -0,0 +1,108 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::database::transaction::TransactionId;
+use crate::datastore::datastore::{Row, RowId};
+use crate::datastore::exceptions::DatastoreError;
+
+/// Position in the commit order of a database, every commit gets the next one
+pub type Timestamp = u64;
+
+// A row as it was right before a commit changed it, None if the commit created the row
+struct Version {
+    committed: Timestamp,
+    before: Option<Row>,
+}
+
+/// Older versions and write locks of the rows of a table. The datastore only holds the latest
+/// committed rows, this keeps what they replaced for as long as a snapshot from before the change
+/// may read it.
+#[derive(Default)]
+pub struct RowVersions {
+    // Per row, oldest commit first
+    versions: HashMap<RowId, Vec<Version>>,
+    // Rows changed by open transactions, with the transaction that changed them. The first
+    // transaction to change a row holds it until it ends, anyone else changing it is a conflict.
+    locks: HashMap<RowId, TransactionId>,
+}
+
+impl RowVersions {
+    /// A row of the table as it was at the snapshot, from the latest committed row. The latest row
+    /// has to be read before the versions: a commit keeps what it replaced before it unlocks the
+    /// rows, so the versions then cover every commit the latest row may come from.
+    pub fn select(&self, row_id: RowId, snapshot: Timestamp, latest: Result<Row, DatastoreError>) -> Result<Row, DatastoreError> {
+        // The first commit after the snapshot replaced the row the snapshot should see
+        let maybe_version = self
+            .versions
+            .get(&row_id)
+            .and_then(|versions| versions.iter().find(|version| version.committed > snapshot));
+        match maybe_version {
+            Some(Version { before: Some(row), .. }) => Ok(row.clone()),
+            Some(Version { before: None, .. }) => Err(DatastoreError::RowNotFound(row_id)),
+            None => latest,
+        }
+    }
+
+    /// Every row of the table as it was at the snapshot, from the latest committed rows read the
+    /// same way as for a select
+    pub fn scan(&self, latest: Vec<(RowId, Row)>, snapshot: Timestamp) -> BTreeMap<RowId, Row> {
+        let mut rows: BTreeMap<RowId, Row> = latest.into_iter().collect();
+        for (row_id, versions) in &self.versions {
+            match versions.iter().find(|version| version.committed > snapshot) {
+                Some(Version { before: Some(row), .. }) => {
+                    rows.insert(*row_id, row.clone());
+                }
+                Some(Version { before: None, .. }) => {
+                    rows.remove(row_id);
+                }
+                None => {}
+            }
+        }
+        rows
+    }
+
+    /// Lock the row for a transaction. Fails when another transaction holds it, or when a commit
+    /// after the snapshot of the transaction changed it.
+    pub fn lock(&mut self, row_id: RowId, transaction: TransactionId, snapshot: Timestamp) -> bool {
+        match self.locks.get(&row_id) {
+            Some(holder) => *holder == transaction,
+            None if self.versions.get(&row_id).is_some_and(|versions| versions.iter().any(|version| version.committed > snapshot)) => false,
+            None => {
+                self.locks.insert(row_id, transaction);
+                true
+            }
+        }
+    }
+
+    /// Keep the row a commit replaced
+    pub fn record(&mut self, row_id: RowId, committed: Timestamp, before: Option<Row>) {
+        self.versions.entry(row_id).or_default().push(Version { committed, before });
+    }
+
+    pub fn unlock(&mut self, transaction: TransactionId) {
+        self.locks.retain(|_, holder| *holder != transaction);
+    }
+
+    /// The same versions with every kept row converted, for a table whose columns changed. Only
+    /// for tables no open transaction has locked rows in.
+    pub fn converted<E>(&self, convert: impl Fn(Row) -> Result<Row, E>) -> Result<RowVersions, E> {
+        let mut versions = HashMap::new();
+        for (row_id, row_versions) in &self.versions {
+            let row_versions = row_versions
+                .iter()
+                .map(|version| {
+                    let before = version.before.clone().map(&convert).transpose()?;
+                    Ok(Version { committed: version.committed, before })
+                })
+                .collect::<Result<Vec<Version>, E>>()?;
+            versions.insert(*row_id, row_versions);
+        }
+        Ok(RowVersions { versions, locks: HashMap::new() })
+    }
+
+    /// Drop the versions no snapshot at or after the given one can see any more
+    pub fn collect_garbage(&mut self, oldest_snapshot: Timestamp) {
+        self.versions.retain(|_, versions| {
+            versions.retain(|version| version.committed > oldest_snapshot);
+            !versions.is_empty()
+        });
+    }
+}
@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use datablaze_types::enums::{ColumnData, ColumnTypes};
+use crate::database::column::Column;
+use crate::database::exceptions::DataError;
+use crate::database::table::{convert_data, convert_value};
+use crate::datastore::datastore::RowId;
+
+/// A row found by a query, with the projected data by name
+pub type QueryRow = (RowId, HashMap<String, Value>);
+
+/// Path into a JSON document, such as `$.address.city` or `$.tags[0]`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct JsonPath {
+    text: String,
+    segments: Vec<PathSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    /// The part of the document the path points at, None when it does not exist
+    pub fn find<'a>(&self, document: &'a Value) -> Option<&'a Value> {
+        self.segments.iter().try_fold(document, |value, segment| match segment {
+            PathSegment::Key(key) => value.get(key),
+            PathSegment::Index(index) => value.get(index),
+        })
+    }
+}
+
+impl TryFrom<String> for JsonPath {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("Invalid JSON path {}", text);
+        let mut rest = text.strip_prefix('$').ok_or_else(invalid)?;
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid());
+                }
+                segments.push(PathSegment::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(invalid)?;
+                let index = after[..end].parse().map_err(|_| invalid())?;
+                segments.push(PathSegment::Index(index));
+                rest = &after[end + 1..];
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(JsonPath { text, segments })
+    }
+}
+
+impl From<JsonPath> for String {
+    fn from(path: JsonPath) -> Self {
+        path.text
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// An array, or a JSON array, has an element equal to the value
+    Contains,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Function {
+    /// Number of elements of an array or a JSON array, null for anything else
+    Length,
+}
+
+/// Keep the rows where a column, or a path into a JSON column, compares to a value
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Filter {
+    pub column: String,
+    #[serde(default)]
+    pub path: Option<JsonPath>,
+    /// Applied to the column or path before comparing
+    #[serde(default)]
+    pub function: Option<Function>,
+    pub operator: Operator,
+    /// Null only works with eq and ne, to find the rows that are or are not null
+    pub value: Value,
+}
+
+/// Column, or path into a JSON column, to return for every row
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Projection {
+    pub column: String,
+    #[serde(default)]
+    pub path: Option<JsonPath>,
+    #[serde(default)]
+    pub function: Option<Function>,
+    /// Name in the result, by default the path or the column name
+    #[serde(default, rename = "as")]
+    pub alias: Option<String>,
+}
+
+// What filters and projections read from a row: a column, the part of a JSON column a path
+// points at, and a function of either
+struct Operand {
+    index: usize,
+    path: Option<JsonPath>,
+    function: Option<Function>,
+    // Type of what the operand reads, anything read from a path is JSON
+    operand_type: ColumnTypes,
+}
+
+impl Operand {
+    fn new(columns: &[Column], column: &str, path: Option<JsonPath>, function: Option<Function>) -> Result<Operand, DataError> {
+        let index = columns.iter().position(|existing| existing.name == column).ok_or(DataError)?;
+        let operand_type = match (&columns[index].column_type, &path) {
+            (ColumnTypes::Json, _) => ColumnTypes::Json,
+            (_, Some(_)) => return Err(DataError),
+            (column_type, None) => column_type.clone(),
+        };
+        let operand_type = match (function, operand_type) {
+            (None, operand_type) => operand_type,
+            (Some(Function::Length), ColumnTypes::Array(_) | ColumnTypes::Json) => ColumnTypes::Int,
+            (Some(Function::Length), _) => return Err(DataError),
+        };
+        Ok(Operand { index, path, function, operand_type })
+    }
+
+    // A path that does not exist reads as null
+    fn read(&self, row: &[ColumnData]) -> ColumnData {
+        let data = match (&self.path, &row[self.index]) {
+            (Some(path), ColumnData::Json(document)) => path.find(document).cloned().map(ColumnData::Json).unwrap_or(ColumnData::Null),
+            (Some(_), _) => ColumnData::Null,
+            (None, data) => data.clone(),
+        };
+        match (self.function, data) {
+            (None, data) => data,
+            (Some(Function::Length), ColumnData::Array(elements)) => ColumnData::Int(elements.len() as u64),
+            (Some(Function::Length), ColumnData::Json(Value::Array(elements))) => ColumnData::Int(elements.len() as u64),
+            (Some(Function::Length), _) => ColumnData::Null,
+        }
+    }
+}
+
+// A filter checked against the columns of a table, with its value converted to the type it is
+// compared with
+pub struct BoundFilter {
+    operand: Operand,
+    operator: Operator,
+    value: ColumnData,
+}
+
+impl BoundFilter {
+    pub fn new(filter: Filter, columns: &[Column]) -> Result<BoundFilter, DataError> {
+        let operand = Operand::new(columns, &filter.column, filter.path, filter.function)?;
+        let value = match (filter.operator, &operand.operand_type, filter.value) {
+            (Operator::Eq | Operator::Ne, _, Value::Null) => ColumnData::Null,
+            (_, _, Value::Null) => return Err(DataError),
+            (_, ColumnTypes::Json, value) => ColumnData::Json(value),
+            (Operator::Contains, ColumnTypes::Array(element_type), value) => convert_data(element_type, value)?,
+            (Operator::Contains, _, _) => return Err(DataError),
+            (_, operand_type, value) => convert_data(operand_type, value)?,
+        };
+        Ok(BoundFilter { operand, operator: filter.operator, value })
+    }
+
+    pub fn matches(&self, row: &[ColumnData]) -> bool {
+        let found = self.operand.read(row);
+        match (&found, &self.value) {
+            (ColumnData::Null | ColumnData::Json(Value::Null), ColumnData::Null) => self.operator == Operator::Eq,
+            (_, ColumnData::Null) => self.operator == Operator::Ne,
+            _ if self.operator == Operator::Contains => match found {
+                ColumnData::Array(elements) => elements.iter().any(|element| element.compare(&self.value) == Some(Ordering::Equal)),
+                ColumnData::Json(Value::Array(elements)) => elements
+                    .into_iter()
+                    .any(|element| ColumnData::Json(element).compare(&self.value) == Some(Ordering::Equal)),
+                _ => false,
+            },
+            _ => found.compare(&self.value).is_some_and(|ordering| match self.operator {
+                Operator::Eq => ordering == Ordering::Equal,
+                Operator::Ne => ordering != Ordering::Equal,
+                Operator::Lt => ordering == Ordering::Less,
+                Operator::Le => ordering != Ordering::Greater,
+                Operator::Gt => ordering == Ordering::Greater,
+                Operator::Ge => ordering != Ordering::Less,
+                Operator::Contains => false,
+            }),
+        }
+    }
+}
+
+pub struct BoundProjection {
+    operand: Operand,
+    name: String,
+}
+
+impl BoundProjection {
+    pub fn new(projection: Projection, columns: &[Column]) -> Result<BoundProjection, DataError> {
+        let operand = Operand::new(columns, &projection.column, projection.path.clone(), projection.function)?;
+        let name = match (projection.alias, projection.path, projection.function) {
+            (Some(alias), _, _) => alias,
+            (None, path, Some(Function::Length)) => format!("length({})", path.map(|path| path.to_string()).unwrap_or(projection.column)),
+            (None, Some(path), None) => path.to_string(),
+            (None, None, None) => projection.column,
+        };
+        Ok(BoundProjection { operand, name })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn project(&self, row: &[ColumnData]) -> Value {
+        convert_value(self.operand.read(row), &self.operand.operand_type)
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use datablaze_types::enums::ColumnData;
+use crate::database::exceptions::DataError;
+use crate::database::table::Table;
+use crate::datastore::datastore::{Row, RowId};
+use crate::datastore::encoding::encode_row;
+
+// Stored as the discriminant in table records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDelete {
+    /// A referenced row can not be deleted
+    #[default]
+    Restrict = 0,
+    /// Rows referencing a deleted row are deleted along with it
+    Cascade = 1,
+    /// Rows referencing a deleted row get null in the column, which has to be nullable
+    SetNull = 2,
+}
+
+/// Column holding the primary key of a row in a table of the same database, or null
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Reference {
+    pub table: String,
+    #[serde(default)]
+    pub on_delete: OnDelete,
+}
+
+/// Check the references of a table against the tables of its database. A referenced table needs a
+/// primary key of a single column, of the same type as the referencing column. A table can
+/// reference itself.
+pub fn validate_references(table: &Table, tables: &[&Table]) -> Result<(), DataError> {
+    for column in &table.columns {
+        let Some(reference) = &column.references else { continue };
+        let referenced = match reference.table == table.name {
+            true => table,
+            false => tables.iter().find(|existing| existing.name == reference.table).ok_or(DataError)?,
+        };
+        let primary = referenced.primary_column().ok_or(DataError)?;
+        if referenced.columns[primary].column_type != column.column_type {
+            return Err(DataError);
+        }
+    }
+    Ok(())
+}
+
+/// Index over the latest committed rows of a table for a column referencing another table, from
+/// the data in the column to the rows holding it. Rows with null in the column are left out.
+pub struct ReferenceIndex {
+    pub column: usize,
+    pub reference: Reference,
+    rows: HashMap<Vec<u8>, BTreeSet<RowId>>,
+}
+
+impl ReferenceIndex {
+    pub fn new(column: usize, reference: Reference) -> ReferenceIndex {
+        ReferenceIndex { column, reference, rows: HashMap::new() }
+    }
+
+    /// Rows referencing the data
+    pub fn find(&self, data: &ColumnData) -> Vec<RowId> {
+        self.rows.get(&encode_data(data)).map(|rows| rows.iter().copied().collect()).unwrap_or_default()
+    }
+
+    /// Index a row as it is after a commit, given the row it replaced
+    pub fn update(&mut self, row_id: RowId, before: Option<&Row>, after: Option<&Row>) {
+        if let Some(key) = before.and_then(|row| self.key(row)) && let Some(rows) = self.rows.get_mut(&key) {
+            rows.remove(&row_id);
+            if rows.is_empty() {
+                self.rows.remove(&key);
+            }
+        }
+        if let Some(key) = after.and_then(|row| self.key(row)) {
+            self.rows.entry(key).or_default().insert(row_id);
+        }
+    }
+
+    /// The index as it is once the changes are in, None for deleted rows
+    pub fn changed(&self, changes: &[(RowId, Option<&Row>)]) -> ChangedReferences<'_> {
+        ChangedReferences {
+            index: self,
+            changed: changes.iter().map(|(row_id, _)| *row_id).collect(),
+            added: changes.iter().filter_map(|(_, row)| row.and_then(|row| self.key(row))).collect(),
+        }
+    }
+
+    fn key(&self, row: &Row) -> Option<Vec<u8>> {
+        match row.get(self.column) {
+            None | Some(ColumnData::Null) => None,
+            Some(data) => Some(encode_data(data)),
+        }
+    }
+}
+
+pub struct ChangedReferences<'a> {
+    index: &'a ReferenceIndex,
+    changed: HashSet<RowId>,
+    added: HashSet<Vec<u8>>,
+}
+
+impl ChangedReferences<'_> {
+    /// Whether any row references the data
+    pub fn contains(&self, data: &ColumnData) -> bool {
+        let key = encode_data(data);
+        self.added.contains(&key)
+            || self.index.rows.get(&key).is_some_and(|rows| rows.iter().any(|row_id| !self.changed.contains(row_id)))
+    }
+}
+
+// Encoded the way a key index encodes a key of a single column
+fn encode_data(data: &ColumnData) -> Vec<u8> {
+    let mut key = Vec::new();
+    encode_row(std::slice::from_ref(data), &mut key);
+    key
+}
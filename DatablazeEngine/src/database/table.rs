@@ -1,85 +1,281 @@
-﻿use std::collections::HashMap;
+﻿use std::cmp::Ordering;
+use std::collections::HashMap;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use rust_decimal::Decimal;
 use serde_json::Value;
+use uuid::Uuid;
 use datablaze_types::enums::{ColumnData, ColumnTypes};
+use crate::database::aggregate::{aggregate, AggregateFunction};
 use crate::database::column::Column;
-use crate::database::exceptions::DataError;
+use crate::database::exceptions::{DataError, TableError};
+use crate::database::key::Keys;
+use crate::database::query::{BoundFilter, BoundProjection, Filter, Projection, QueryRow};
+use crate::database::vector::{Nearest, NearestRow};
+use crate::datastore::datastore::{Datastore, Row, RowId};
+use crate::datastore::exceptions::DatastoreError;
 
+#[derive(Clone)]
 pub struct Table {
     pub name: String,
-    pub columns: HashMap<String, Column>,
+    pub columns: Vec<Column>,
+    pub keys: Keys,
 }
 
 impl Table {
-    pub fn new(name: String, columns: HashMap<String, Column>) -> Table {
-        Table { name, columns }
+    pub fn with_keys(name: String, columns: Vec<Column>, keys: Keys) -> Result<Table, DataError> {
+        keys.validate(&columns)?;
+        Ok(Table { name, columns, keys })
     }
 
-    pub fn get_row(&self, row: u64) -> Result<HashMap<String, Value>, DataError> {
+    pub fn get_row(&self, datastore: &dyn Datastore, row_id: RowId) -> Result<HashMap<String, Value>, TableError> {
+        let row = datastore.select(&self.name, row_id)?;
+        if row.len() != self.columns.len() { return Err(DataError.into()) };
         let mut result = HashMap::new();
-        for (column_name, column) in self.columns.iter() {
-            if row >= column.data.len() as u64 { return Err(DataError) };
-            let data = match column.column_type {
-                ColumnTypes::String => {
-                    if let ColumnData::String(data) = &column.data[row as usize] {
-                        Value::String(data.to_string())
-                    } else {
-                        return Err(DataError)
-                    }
-                }
-                ColumnTypes::Int => {
-                    if let ColumnData::Int(number) = column.data[row as usize] {
-                        Value::Number(number.into())
-                    } else {
-                        return Err(DataError)
-                    }
-                }
-            };
-            result.insert(column_name.to_string(), data);
+        for (column, data) in self.columns.iter().zip(row) {
+            if !column.validate(&data) { return Err(DataError.into()) };
+            result.insert(column.name.to_string(), convert_value(data, &column.column_type));
         }
         Ok(result)
 
     }
-    pub fn add_row(&mut self, row: HashMap<String, Value>) -> Result<(), DataError> {
 
-        if row.len() != self.columns.len() { return Err(DataError) };
-        for (column_name, column_data) in row {
-            let column = self.columns.get_mut(&column_name);
-            if let Some(column) = column {
-                let converted_data = convert_data(&column.column_type, column_data)?;
-                column.add_data(converted_data)?;
-            } else {
-                return Err(DataError);
+    /// Id of the row with the primary key, looking at the candidate an index picked first. Without a
+    /// candidate every row is searched.
+    pub fn find_row(&self, datastore: &dyn Datastore, key: &[ColumnData], candidate: Option<RowId>) -> Result<RowId, TableError> {
+        let positions = self.keys.primary
+            .iter()
+            .map(|name| self.columns.iter().position(|column| column.name == *name).ok_or(DataError))
+            .collect::<Result<Vec<usize>, DataError>>()?;
+        let has_key = |row: &Row| {
+            positions.len() == key.len()
+                && positions.iter().zip(key).all(|(position, key)| row.get(*position).and_then(|data| data.compare(key)) == Some(Ordering::Equal))
+        };
+        if let Some(row_id) = candidate {
+            match datastore.select(&self.name, row_id) {
+                Ok(row) if has_key(&row) => return Ok(row_id),
+                Ok(_) | Err(DatastoreError::RowNotFound(_)) => {}
+                Err(e) => return Err(e.into()),
             }
         }
-        Ok(())
+        datastore
+            .scan(&self.name)?
+            .into_iter()
+            .find(|(_, row)| has_key(row))
+            .map(|(row_id, _)| row_id)
+            .ok_or(DatastoreError::KeyNotFound(self.name.to_string()).into())
     }
 
-    pub fn update_row(&mut self, row: HashMap<String, Value>, index: u64) -> Result<(), DataError> {
+    /// Position of the column of a primary key of a single column, the only kind a reference can
+    /// point at
+    pub fn primary_column(&self) -> Option<usize> {
+        match self.keys.primary.as_slice() {
+            [name] => self.columns.iter().position(|column| column.name == *name),
+            _ => None,
+        }
+    }
 
-        if row.len() != self.columns.len() { return Err(DataError) };
-        for (column_name, column_data) in row {
-            let column = self.columns.get_mut(&column_name);
-            if let Some(column) = column {
-                let converted_data = convert_data(&column.column_type, column_data)?;
-                column.data[index as usize] = converted_data;
-            } else {
-                return Err(DataError);
+    /// Data of the primary key from JSON, in key order. The key has to hold every primary key
+    /// column and nothing else.
+    pub fn convert_key(&self, mut key: HashMap<String, Value>) -> Result<Row, DataError> {
+        if self.keys.primary.is_empty() || key.len() != self.keys.primary.len() { return Err(DataError) };
+        self.keys.primary
+            .iter()
+            .map(|name| {
+                let column = self.columns.iter().find(|column| column.name == *name).ok_or(DataError)?;
+                convert_data(&column.column_type, key.remove(name).ok_or(DataError)?)
+            })
+            .collect()
+    }
+
+    pub fn aggregate(&self, datastore: &dyn Datastore, column: &str, function: AggregateFunction) -> Result<Value, TableError> {
+        let index = self.columns.iter().position(|existing| existing.name == column).ok_or(DataError)?;
+        let data = datastore
+            .scan(&self.name)?
+            .into_iter()
+            .map(|(_, row)| row.into_iter().nth(index).ok_or(DataError))
+            .collect::<Result<Vec<ColumnData>, DataError>>()?;
+        Ok(convert_value(aggregate(function, data.into_iter())?, &self.columns[index].column_type))
+    }
+
+    /// The rows matching every filter, in row id order. Without projections rows have all columns.
+    /// Unnesting an array column turns every row into a row per element, with the element in
+    /// place of the array, before filtering.
+    pub fn query(&self, datastore: &dyn Datastore, filters: Vec<Filter>, projections: Vec<Projection>, unnest: Option<&str>) -> Result<Vec<QueryRow>, TableError> {
+        let mut columns = self.columns.clone();
+        let unnest = match unnest {
+            Some(unnest) => {
+                let index = columns.iter().position(|column| column.name == unnest).ok_or(DataError)?;
+                let ColumnTypes::Array(element_type) = &columns[index].column_type else { return Err(DataError.into()) };
+                columns[index].column_type = *element_type.clone();
+                Some(index)
+            }
+            None => None,
+        };
+        let (filters, projections) = bind(&columns, filters, projections)?;
+        let mut rows = datastore.scan(&self.name)?;
+        rows.sort_by_key(|(row_id, _)| *row_id);
+        let mut result = Vec::new();
+        for (row_id, row) in rows {
+            if row.len() != self.columns.len() { return Err(DataError.into()) };
+            let rows = match unnest {
+                Some(index) => match &row[index] {
+                    ColumnData::Array(elements) => elements
+                        .iter()
+                        .map(|element| {
+                            let mut unnested = row.clone();
+                            unnested[index] = element.clone();
+                            unnested
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                },
+                None => vec![row],
+            };
+            for row in rows {
+                if !filters.iter().all(|filter| filter.matches(&row)) { continue };
+                result.push((row_id, project(&projections, &row)));
             }
         }
-        Ok(())
+        Ok(result)
     }
 
-    pub fn delete_row(&mut self, index: u64) -> Result<(), DataError> {
-        for column in self.columns.values_mut() {
-            if index >= column.data.len() as u64 { return Err(DataError) };
-            column.data.remove(index as usize);
+    /// The k rows matching every filter with their vectors nearest to the searched one, nearest
+    /// first. Searches every row, or only the candidates an index picked.
+    pub fn nearest(&self, datastore: &dyn Datastore, nearest: &Nearest, filters: Vec<Filter>, projections: Vec<Projection>, candidates: Option<Vec<RowId>>) -> Result<Vec<NearestRow>, TableError> {
+        let (index, target) = self.search_vector(nearest)?;
+        let (filters, projections) = bind(&self.columns, filters, projections)?;
+        let rows = match candidates {
+            None => datastore.scan(&self.name)?,
+            // Candidates may have been deleted since the index picked them
+            Some(row_ids) => row_ids
+                .into_iter()
+                .filter_map(|row_id| match datastore.select(&self.name, row_id) {
+                    Ok(row) => Some(Ok((row_id, row))),
+                    Err(DatastoreError::RowNotFound(_)) => None,
+                    Err(e) => Some(Err(e)),
+                })
+                .collect::<Result<Vec<(RowId, Row)>, DatastoreError>>()?,
+        };
+        let mut found = Vec::new();
+        for (row_id, row) in rows {
+            if row.len() != self.columns.len() { return Err(DataError.into()) };
+            let ColumnData::Vector(vector) = &row[index] else { continue };
+            if !filters.iter().all(|filter| filter.matches(&row)) { continue };
+            found.push((nearest.metric.distance(&target, vector), row_id, row));
         }
+        found.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        Ok(found
+            .into_iter()
+            .take(nearest.k)
+            .map(|(distance, row_id, row)| (row_id, distance, project(&projections, &row)))
+            .collect())
+    }
+
+    /// Index of the searched vector column, and the searched vector checked against it
+    pub fn search_vector(&self, nearest: &Nearest) -> Result<(usize, Vec<f32>), DataError> {
+        let index = self.columns.iter().position(|column| column.name == nearest.column).ok_or(DataError)?;
+        match convert_data(&self.columns[index].column_type, nearest.vector.clone())? {
+            ColumnData::Vector(vector) => Ok((index, vector)),
+            _ => Err(DataError),
+        }
+    }
+
+    /// Add every row or none of them. All rows are converted before the first one is stored.
+    pub fn add_rows(&self, datastore: &mut dyn Datastore, rows: Vec<HashMap<String, Value>>) -> Result<Vec<RowId>, TableError> {
+
+        let converted_rows = rows
+            .into_iter()
+            .map(|row| self.convert_row(row))
+            .collect::<Result<Vec<Row>, DataError>>()?;
+        Ok(datastore.insert(&self.name, converted_rows)?)
+    }
+
+    /// Change the columns given in the row, the columns left out keep their data
+    pub fn update_row(&self, datastore: &mut dyn Datastore, row: HashMap<String, Value>, row_id: RowId) -> Result<(), TableError> {
+
+        let current = datastore.select(&self.name, row_id)?;
+        if current.len() != self.columns.len() { return Err(DataError.into()) };
+        let converted_row = self.convert_changes(current, row)?;
+        datastore.update(&self.name, row_id, converted_row)?;
         Ok(())
     }
+
+    pub fn delete_row(&self, datastore: &mut dyn Datastore, row_id: RowId) -> Result<(), TableError> {
+        datastore.delete(&self.name, row_id)?;
+        Ok(())
+    }
+
+    /// Clear a column of a row, for a reference to a deleted row
+    pub fn set_null(&self, datastore: &mut dyn Datastore, row_id: RowId, column: usize) -> Result<(), TableError> {
+        let mut row = datastore.select(&self.name, row_id)?;
+        *row.get_mut(column).ok_or(DataError)? = ColumnData::Null;
+        datastore.update(&self.name, row_id, row)?;
+        Ok(())
+    }
+
+    // Convert a row from JSON into column data, in column order. Columns left out of the row
+    // get their default or null.
+    fn convert_row(&self, mut row: HashMap<String, Value>) -> Result<Row, DataError> {
+        let converted = self.columns
+            .iter()
+            .map(|column| match row.remove(&column.name) {
+                Some(column_data) => convert_column(column, column_data),
+                None => column.missing_data().ok_or(DataError),
+            })
+            .collect::<Result<Row, DataError>>()?;
+        // Anything left is not a column of the table
+        if !row.is_empty() { return Err(DataError) };
+        Ok(converted)
+    }
+
+    // Convert the columns given in JSON over the current data of a row
+    fn convert_changes(&self, mut current: Row, mut changes: HashMap<String, Value>) -> Result<Row, DataError> {
+        for (column, data) in self.columns.iter().zip(current.iter_mut()) {
+            if let Some(column_data) = changes.remove(&column.name) {
+                *data = convert_column(column, column_data)?;
+            }
+        }
+        if !changes.is_empty() { return Err(DataError) };
+        Ok(current)
+    }
 }
 
 
-fn convert_data(column_type: &ColumnTypes, data: Value) -> Result<ColumnData, DataError> {
+// Check filters and projections against the columns. Without projections every column is returned.
+fn bind(columns: &[Column], filters: Vec<Filter>, projections: Vec<Projection>) -> Result<(Vec<BoundFilter>, Vec<BoundProjection>), DataError> {
+    let filters = filters
+        .into_iter()
+        .map(|filter| BoundFilter::new(filter, columns))
+        .collect::<Result<Vec<BoundFilter>, DataError>>()?;
+    let projections = match projections.is_empty() {
+        true => columns.iter().map(|column| Projection { column: column.name.clone(), path: None, function: None, alias: None }).collect(),
+        false => projections,
+    };
+    let projections = projections
+        .into_iter()
+        .map(|projection| BoundProjection::new(projection, columns))
+        .collect::<Result<Vec<BoundProjection>, DataError>>()?;
+    Ok((filters, projections))
+}
+
+fn project(projections: &[BoundProjection], row: &[ColumnData]) -> HashMap<String, Value> {
+    projections
+        .iter()
+        .map(|projection| (projection.name().to_string(), projection.project(row)))
+        .collect()
+}
+
+// Null is only taken by nullable columns
+fn convert_column(column: &Column, data: Value) -> Result<ColumnData, DataError> {
+    match data {
+        Value::Null if column.nullable => Ok(ColumnData::Null),
+        data => convert_data(&column.column_type, data),
+    }
+}
+
+pub fn convert_data(column_type: &ColumnTypes, data: Value) -> Result<ColumnData, DataError> {
     match column_type {
         ColumnTypes::String => {
             let maybe_data = data.as_str();
@@ -97,5 +293,93 @@ fn convert_data(column_type: &ColumnTypes, data: Value) -> Result<ColumnData, Da
                 Err(DataError)
             }
         }
+        ColumnTypes::SignedInt => data.as_i64().map(ColumnData::SignedInt).ok_or(DataError),
+        ColumnTypes::Float => data.as_f64().map(ColumnData::Float).ok_or(DataError),
+        ColumnTypes::Bool => data.as_bool().map(ColumnData::Bool).ok_or(DataError),
+        // RFC 3339, for example 2025-03-01T12:00:00+01:00
+        ColumnTypes::Timestamp => {
+            let timestamp = DateTime::parse_from_rfc3339(data.as_str().ok_or(DataError)?).map_err(|_| DataError)?;
+            Ok(ColumnData::Timestamp(timestamp.with_timezone(&Utc)))
+        }
+        // ISO 8601, for example 2025-03-01
+        ColumnTypes::Date => {
+            let date = NaiveDate::parse_from_str(data.as_str().ok_or(DataError)?, "%Y-%m-%d").map_err(|_| DataError)?;
+            Ok(ColumnData::Date(date))
+        }
+        ColumnTypes::Uuid => {
+            let uuid = Uuid::parse_str(data.as_str().ok_or(DataError)?).map_err(|_| DataError)?;
+            Ok(ColumnData::Uuid(uuid))
+        }
+        ColumnTypes::Bytes => {
+            let bytes = BASE64.decode(data.as_str().ok_or(DataError)?).map_err(|_| DataError)?;
+            Ok(ColumnData::Bytes(bytes))
+        }
+        // A string, so no digits get lost on the way. Trailing zeros beyond the scale are fine,
+        // other digits are not rounded off.
+        ColumnTypes::Decimal { scale, .. } => {
+            let mut decimal = Decimal::from_str_exact(data.as_str().ok_or(DataError)?).map_err(|_| DataError)?.normalize();
+            if decimal.scale() > *scale { return Err(DataError) };
+            decimal.rescale(*scale);
+            let data = ColumnData::Decimal(decimal);
+            if !data.validate(column_type) { return Err(DataError) };
+            Ok(data)
+        }
+        ColumnTypes::Json => Ok(ColumnData::Json(data)),
+        ColumnTypes::Enum(values) => {
+            let value = data.as_str().ok_or(DataError)?;
+            let code = values.iter().position(|existing| existing == value).ok_or(DataError)?;
+            Ok(ColumnData::Enum(code as u16))
+        }
+        ColumnTypes::Vector(_) => {
+            let Value::Array(values) = data else { return Err(DataError) };
+            let vector = values
+                .iter()
+                .map(|value| value.as_f64().map(|value| value as f32).ok_or(DataError))
+                .collect::<Result<Vec<f32>, DataError>>()?;
+            let data = ColumnData::Vector(vector);
+            // Also catches numbers too large for 32 bits
+            if !data.validate(column_type) { return Err(DataError) };
+            Ok(data)
+        }
+        // Every element has to convert, one bad element fails the whole array
+        ColumnTypes::Array(element_type) => {
+            let Value::Array(elements) = data else { return Err(DataError) };
+            let elements = elements
+                .into_iter()
+                .map(|element| convert_data(element_type, element))
+                .collect::<Result<Vec<ColumnData>, DataError>>()?;
+            Ok(ColumnData::Array(elements))
+        }
+    }
+}
+
+/// Convert column data to JSON. Enums need the type of their column to find their values.
+pub fn convert_value(data: ColumnData, column_type: &ColumnTypes) -> Value {
+    match data {
+        ColumnData::String(data) => Value::String(data),
+        ColumnData::Int(number) => Value::Number(number.into()),
+        ColumnData::SignedInt(number) => Value::Number(number.into()),
+        ColumnData::Float(number) => Value::from(number),
+        ColumnData::Bool(boolean) => Value::Bool(boolean),
+        ColumnData::Timestamp(timestamp) => Value::String(timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        ColumnData::Date(date) => Value::String(date.format("%Y-%m-%d").to_string()),
+        ColumnData::Uuid(uuid) => Value::String(uuid.hyphenated().to_string()),
+        ColumnData::Bytes(bytes) => Value::String(BASE64.encode(bytes)),
+        ColumnData::Decimal(decimal) => Value::String(decimal.to_string()),
+        ColumnData::Json(document) => document,
+        ColumnData::Array(elements) => {
+            let element_type = match column_type {
+                ColumnTypes::Array(element_type) => element_type,
+                column_type => column_type,
+            };
+            Value::Array(elements.into_iter().map(|element| convert_value(element, element_type)).collect())
+        }
+        ColumnData::Enum(code) => match column_type {
+            ColumnTypes::Enum(values) => values.get(code as usize).map(|value| Value::String(value.clone())).unwrap_or(Value::Null),
+            _ => Value::Null,
+        },
+        // Through the shortest text that reads back as the same f32, so 0.1 stays 0.1
+        ColumnData::Vector(vector) => Value::Array(vector.into_iter().map(|value| Value::from(value.to_string().parse::<f64>().unwrap())).collect()),
+        ColumnData::Null => Value::Null,
     }
 }
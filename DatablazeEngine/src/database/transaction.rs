@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use datablaze_types::enums::{ColumnData, DatastoreVariants};
+use crate::database::column::Column;
+use crate::database::key::{KeyIndex, Keys};
+use crate::database::mvcc::{RowVersions, Timestamp};
+use crate::database::table::Table;
+use crate::datastore::datastore::{Datastore, Row, RowChange, RowId, SharedDatastore};
+use crate::datastore::exceptions::DatastoreError;
+
+/// Identifies an open transaction within its database
+pub type TransactionId = u64;
+
+/// Transactions that are not used for this long are rolled back, so an abandoned transaction
+/// does not keep its rows locked and their old versions around forever
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Changes of an open transaction. Nothing reaches the datastore until the transaction commits.
+pub struct Transaction {
+    /// The transaction reads the rows as they were committed when it began
+    pub snapshot: Timestamp,
+    // Rows written by the transaction, None for a deleted row
+    writes: BTreeMap<(String, RowId), Option<Row>>,
+    last_used: Instant,
+    /// Set once the transaction committed or rolled back, for requests that were already using it
+    pub ended: bool,
+}
+
+impl Transaction {
+    pub fn new(snapshot: Timestamp) -> Transaction {
+        Transaction { snapshot, writes: BTreeMap::new(), last_used: Instant::now(), ended: false }
+    }
+
+    pub fn touch(&mut self) {
+        self.last_used = Instant::now();
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.last_used.elapsed() > TRANSACTION_TIMEOUT
+    }
+
+    /// Rows of the table the transaction wrote, None for deleted ones
+    pub fn written(&self, table: &str) -> impl Iterator<Item = (RowId, Option<&Row>)> {
+        self.writes
+            .range((table.to_string(), RowId::MIN)..=(table.to_string(), RowId::MAX))
+            .map(|((_, row_id), row)| (*row_id, row.as_ref()))
+    }
+
+    pub fn take_changes(&mut self) -> Vec<RowChange> {
+        std::mem::take(&mut self.writes)
+            .into_iter()
+            .map(|((table, row_id), row)| match row {
+                Some(row) => RowChange::Put { table, row_id, row },
+                None => RowChange::Delete { table, row_id },
+            })
+            .collect()
+    }
+}
+
+/// Datastore as seen from inside a transaction: reads see the changes of the transaction on top
+/// of its snapshot, and writes are kept in the transaction. Only works on the table the versions
+/// belong to. The versions are only locked for a single select or scan, so a long read does not
+/// keep commits to the table waiting.
+pub struct TransactionView<'a> {
+    pub datastore: &'a SharedDatastore,
+    pub table: &'a Table,
+    pub versions: &'a RwLock<RowVersions>,
+    /// Key indexes of the table and of the tables it references, by table name
+    pub keys: &'a BTreeMap<&'a str, &'a [KeyIndex]>,
+    pub transaction: &'a mut Transaction,
+}
+
+impl Datastore for TransactionView<'_> {
+    fn insert(&mut self, table: &str, rows: Vec<Row>) -> Result<Vec<RowId>, DatastoreError> {
+        let row_ids = self.reserve_row_ids(table, rows.len())?;
+        let changes = row_ids
+            .iter()
+            .zip(rows)
+            .map(|(&row_id, row)| RowChange::Put { table: table.to_string(), row_id, row })
+            .collect();
+        self.apply(changes)?;
+        Ok(row_ids)
+    }
+
+    fn delete(&mut self, table: &str, row_id: RowId) -> Result<(), DatastoreError> {
+        self.select(table, row_id)?;
+        self.apply(vec![RowChange::Delete { table: table.to_string(), row_id }])
+    }
+
+    // Tables are not part of transactions
+    fn create(&mut self, table: &str, columns: &[Column], keys: &Keys) -> Result<(), DatastoreError> {
+        self.datastore.write().unwrap().create(table, columns, keys)
+    }
+
+    fn update(&mut self, table: &str, row_id: RowId, row: Row) -> Result<(), DatastoreError> {
+        self.select(table, row_id)?;
+        self.apply(vec![RowChange::Put { table: table.to_string(), row_id, row }])
+    }
+
+    fn select(&self, table: &str, row_id: RowId) -> Result<Row, DatastoreError> {
+        match self.transaction.writes.get(&(table.to_string(), row_id)) {
+            Some(Some(row)) => Ok(row.clone()),
+            Some(None) => Err(DatastoreError::RowNotFound(row_id)),
+            None => {
+                let latest = self.datastore.read().unwrap().select(table, row_id);
+                self.versions.read().unwrap().select(row_id, self.transaction.snapshot, latest)
+            }
+        }
+    }
+
+    fn scan(&self, table: &str) -> Result<Vec<(RowId, Row)>, DatastoreError> {
+        let latest = self.datastore.read().unwrap().scan(table)?;
+        let mut rows = self.versions.read().unwrap().scan(latest, self.transaction.snapshot);
+        let written = self.transaction.writes.range((table.to_string(), RowId::MIN)..=(table.to_string(), RowId::MAX));
+        for ((_, row_id), row) in written {
+            match row {
+                Some(row) => rows.insert(*row_id, row.clone()),
+                None => rows.remove(row_id),
+            };
+        }
+        Ok(rows.into_iter().collect())
+    }
+
+    fn get_type(&self) -> DatastoreVariants {
+        self.datastore.read().unwrap().get_type()
+    }
+
+    fn reserve_row_ids(&mut self, table: &str, count: usize) -> Result<Vec<RowId>, DatastoreError> {
+        self.datastore.write().unwrap().reserve_row_ids(table, count)
+    }
+
+    // Keys and references are checked before anything is written, so a failed check leaves the
+    // transaction as it was
+    fn apply(&mut self, changes: Vec<RowChange>) -> Result<(), DatastoreError> {
+        if let Some(RowChange::Put { table, .. } | RowChange::Delete { table, .. }) = changes.first() {
+            let changed: Vec<(RowId, Option<&Row>)> = changes
+                .iter()
+                .map(|change| match change {
+                    RowChange::Put { row_id, row, .. } => (*row_id, Some(row)),
+                    RowChange::Delete { row_id, .. } => (*row_id, None),
+                })
+                .collect();
+            let changed_ids: HashSet<RowId> = changed.iter().map(|(row_id, _)| *row_id).collect();
+            let earlier: Vec<(RowId, Option<&Row>)> = self.transaction
+                .written(table)
+                .filter(|(row_id, _)| !changed_ids.contains(row_id))
+                .collect();
+            for key in self.keys[table.as_str()] {
+                key.check(table, &changed, &earlier)?;
+            }
+            for (position, column) in self.table.columns.iter().enumerate() {
+                let Some(reference) = &column.references else { continue };
+                // The referenced rows as the transaction leaves them, which is the latest committed
+                // rows with its own writes on top
+                let referenced: Vec<(RowId, Option<&Row>)> = match reference.table == *table {
+                    true => earlier.iter().chain(&changed).copied().collect(),
+                    false => self.transaction.written(&reference.table).collect(),
+                };
+                let primary = self.keys[reference.table.as_str()][0].changed(&referenced);
+                for (_, row) in &changed {
+                    if let Some(data) = row.and_then(|row| row.get(position))
+                        && !matches!(data, ColumnData::Null)
+                        && !primary.contains(std::slice::from_ref(data))
+                    {
+                        return Err(DatastoreError::ReferenceNotFound { table: table.to_string(), column: column.name.to_string() });
+                    }
+                }
+            }
+        }
+        for change in changes {
+            match change {
+                RowChange::Put { table, row_id, row } => self.transaction.writes.insert((table, row_id), Some(row)),
+                RowChange::Delete { table, row_id } => self.transaction.writes.insert((table, row_id), None),
+            };
+        }
+        Ok(())
+    }
+
+    fn alter(&mut self, table: &str, columns: &[Column], keys: &Keys, rows: Vec<(RowId, Row)>) -> Result<(), DatastoreError> {
+        self.datastore.write().unwrap().alter(table, columns, keys, rows)
+    }
+}
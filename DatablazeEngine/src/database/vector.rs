@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::datastore::datastore::RowId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+// Stored as the discriminant in table records
+pub enum Metric {
+    /// One minus the cosine similarity, from 0 for the same direction to 2 for the opposite one
+    Cosine = 0,
+    /// Euclidean distance
+    L2 = 1,
+    /// Negative dot product, so larger products are nearer
+    Dot = 2,
+}
+
+impl Metric {
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => {
+                let norms = dot(a, a).sqrt() * dot(b, b).sqrt();
+                // A zero vector has no direction, it is as far from anything as a perpendicular one
+                if norms == 0.0 { 1.0 } else { 1.0 - dot(a, b) / norms }
+            }
+            Metric::L2 => a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt(),
+            Metric::Dot => -dot(a, b),
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Search for the rows with a vector column nearest to a vector
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Nearest {
+    pub column: String,
+    pub vector: Value,
+    /// Most rows to return
+    pub k: usize,
+    pub metric: Metric,
+}
+
+/// A row found by a nearest neighbour search, with its distance and the projected data by name
+pub type NearestRow = (RowId, f32, HashMap<String, Value>);
@@ -1,10 +1,42 @@
-﻿use datablaze_types::enums::DatastoreVariants;
+﻿use std::sync::RwLock;
+use datablaze_types::enums::{ColumnData, DatastoreVariants};
+use crate::database::column::Column;
+use crate::database::key::Keys;
+use crate::datastore::exceptions::DatastoreError;
+
+/// Identifies a row within its table. Assigned by the datastore on insert and never reused.
+pub type RowId = u64;
+/// Column data of a single row, in the column order of its table
+pub type Row = Vec<ColumnData>;
+/// Datastore of a database, shared by every request working on it
+pub type SharedDatastore = RwLock<Box<dyn Datastore + Send + Sync>>;
+
+/// Change to a single row, as recorded in the log and applied by a transaction
+#[derive(Debug, Clone)]
+pub enum RowChange {
+    /// The row was inserted or updated
+    Put { table: String, row_id: RowId, row: Row },
+    Delete { table: String, row_id: RowId },
+}
 
 pub trait Datastore {
-    fn insert(&mut self);
-    fn delete(&mut self);
-    fn create(&mut self);
-    fn update(&mut self);
-    fn select(&self);
+    /// Insert every row or, when any of them fails, none of them. Returns the new ids in order.
+    fn insert(&mut self, table: &str, rows: Vec<Row>) -> Result<Vec<RowId>, DatastoreError>;
+    fn delete(&mut self, table: &str, row_id: RowId) -> Result<(), DatastoreError>;
+    fn create(&mut self, table: &str, columns: &[Column], keys: &Keys) -> Result<(), DatastoreError>;
+    fn update(&mut self, table: &str, row_id: RowId, row: Row) -> Result<(), DatastoreError>;
+    fn select(&self, table: &str, row_id: RowId) -> Result<Row, DatastoreError>;
+    /// Every row of the table, in id order
+    fn scan(&self, table: &str) -> Result<Vec<(RowId, Row)>, DatastoreError>;
     fn get_type(&self) -> DatastoreVariants;
-}
\ No newline at end of file
+    /// Hand out ids for rows that are inserted later on, through apply
+    fn reserve_row_ids(&mut self, table: &str, count: usize) -> Result<Vec<RowId>, DatastoreError>;
+    /// Apply every change or, when any of them fails, none of them
+    fn apply(&mut self, changes: Vec<RowChange>) -> Result<(), DatastoreError>;
+    /// Replace the columns and keys of a table and all of its rows at once, the rows keeping their ids
+    fn alter(&mut self, table: &str, columns: &[Column], keys: &Keys, rows: Vec<(RowId, Row)>) -> Result<(), DatastoreError>;
+    /// Change the number of pages the datastore caches in memory, if it caches pages at all
+    fn set_cache_pages(&mut self, _pages: usize) -> Result<(), DatastoreError> {
+        Ok(())
+    }
+}
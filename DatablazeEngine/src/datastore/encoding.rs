@@ -0,0 +1,344 @@
+use std::str::FromStr;
+use chrono::{DateTime, Datelike, NaiveDate};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+use datablaze_types::enums::{ColumnData, ColumnTypes};
+use crate::database::column::Column;
+use crate::database::key::Keys;
+use crate::database::reference::{OnDelete, Reference};
+use crate::database::vector::Metric;
+use crate::datastore::exceptions::DatastoreError;
+
+const TAG_STRING: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_SIGNED_INT: u8 = 2;
+const TAG_FLOAT: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_TIMESTAMP: u8 = 5;
+const TAG_DATE: u8 = 6;
+const TAG_UUID: u8 = 7;
+const TAG_BYTES: u8 = 8;
+const TAG_NULL: u8 = 9;
+const TAG_DECIMAL: u8 = 10;
+const TAG_JSON: u8 = 11;
+const TAG_ARRAY: u8 = 12;
+const TAG_VECTOR: u8 = 13;
+const TAG_ENUM: u8 = 14;
+
+const COLUMN_NULLABLE: u8 = 1;
+const COLUMN_DEFAULT: u8 = 2;
+const COLUMN_VECTOR_INDEX: u8 = 4;
+const COLUMN_REFERENCES: u8 = 8;
+const LONG_COLUMN_TYPE: u8 = u8::MAX;
+
+pub fn encode_row(row: &[ColumnData], buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(row.len() as u16).to_le_bytes());
+    for data in row {
+        encode_data(data, buffer);
+    }
+}
+
+fn encode_data(data: &ColumnData, buffer: &mut Vec<u8>) {
+    match data {
+        ColumnData::String(string) => {
+            buffer.push(TAG_STRING);
+            buffer.extend_from_slice(&(string.len() as u32).to_le_bytes());
+            buffer.extend_from_slice(string.as_bytes());
+        }
+        ColumnData::Int(number) => {
+            buffer.push(TAG_INT);
+            buffer.extend_from_slice(&number.to_le_bytes());
+        }
+        ColumnData::SignedInt(number) => {
+            buffer.push(TAG_SIGNED_INT);
+            buffer.extend_from_slice(&number.to_le_bytes());
+        }
+        ColumnData::Float(number) => {
+            buffer.push(TAG_FLOAT);
+            buffer.extend_from_slice(&number.to_le_bytes());
+        }
+        ColumnData::Bool(boolean) => {
+            buffer.push(TAG_BOOL);
+            buffer.push(*boolean as u8);
+        }
+        // Seconds since the epoch and the nanoseconds within the second
+        ColumnData::Timestamp(timestamp) => {
+            buffer.push(TAG_TIMESTAMP);
+            buffer.extend_from_slice(&timestamp.timestamp().to_le_bytes());
+            buffer.extend_from_slice(&timestamp.timestamp_subsec_nanos().to_le_bytes());
+        }
+        ColumnData::Date(date) => {
+            buffer.push(TAG_DATE);
+            buffer.extend_from_slice(&date.num_days_from_ce().to_le_bytes());
+        }
+        ColumnData::Uuid(uuid) => {
+            buffer.push(TAG_UUID);
+            buffer.extend_from_slice(uuid.as_bytes());
+        }
+        ColumnData::Bytes(bytes) => {
+            buffer.push(TAG_BYTES);
+            buffer.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            buffer.extend_from_slice(bytes);
+        }
+        ColumnData::Decimal(decimal) => {
+            buffer.push(TAG_DECIMAL);
+            buffer.extend_from_slice(&decimal.serialize());
+        }
+        // Kept as JSON text
+        ColumnData::Json(document) => {
+            let text = document.to_string();
+            buffer.push(TAG_JSON);
+            buffer.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            buffer.extend_from_slice(text.as_bytes());
+        }
+        ColumnData::Array(elements) => {
+            buffer.push(TAG_ARRAY);
+            buffer.extend_from_slice(&(elements.len() as u32).to_le_bytes());
+            for element in elements {
+                encode_data(element, buffer);
+            }
+        }
+        ColumnData::Vector(vector) => {
+            buffer.push(TAG_VECTOR);
+            buffer.extend_from_slice(&(vector.len() as u32).to_le_bytes());
+            for value in vector {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        ColumnData::Enum(code) => {
+            buffer.push(TAG_ENUM);
+            buffer.extend_from_slice(&code.to_le_bytes());
+        }
+        ColumnData::Null => buffer.push(TAG_NULL),
+    }
+}
+
+pub fn decode_row(bytes: &[u8]) -> Result<Vec<ColumnData>, DatastoreError> {
+    let mut reader = Reader::new(bytes);
+    let n_columns = reader.read_u16()?;
+    let mut row = Vec::with_capacity(n_columns as usize);
+    for _ in 0..n_columns {
+        row.push(decode_data(&mut reader)?);
+    }
+    Ok(row)
+}
+
+fn decode_data(reader: &mut Reader) -> Result<ColumnData, DatastoreError> {
+    let data = match reader.read_u8()? {
+        TAG_STRING => {
+            let length = reader.read_u32()? as usize;
+            ColumnData::String(reader.read_string(length)?)
+        }
+        TAG_INT => ColumnData::Int(reader.read_u64()?),
+        TAG_SIGNED_INT => ColumnData::SignedInt(reader.read_u64()? as i64),
+        TAG_FLOAT => ColumnData::Float(f64::from_bits(reader.read_u64()?)),
+        TAG_BOOL => ColumnData::Bool(reader.read_u8()? != 0),
+        TAG_TIMESTAMP => {
+            let seconds = reader.read_u64()? as i64;
+            let nanoseconds = reader.read_u32()?;
+            let timestamp = DateTime::from_timestamp(seconds, nanoseconds)
+                .ok_or(DatastoreError::Corrupt(format!("Invalid timestamp {}", seconds)))?;
+            ColumnData::Timestamp(timestamp)
+        }
+        TAG_DATE => {
+            let days = reader.read_u32()? as i32;
+            let date = NaiveDate::from_num_days_from_ce_opt(days)
+                .ok_or(DatastoreError::Corrupt(format!("Invalid date {}", days)))?;
+            ColumnData::Date(date)
+        }
+        TAG_UUID => ColumnData::Uuid(Uuid::from_slice(reader.read_bytes(16)?).unwrap()),
+        TAG_BYTES => {
+            let length = reader.read_u32()? as usize;
+            ColumnData::Bytes(reader.read_bytes(length)?.to_vec())
+        }
+        TAG_DECIMAL => ColumnData::Decimal(Decimal::deserialize(reader.read_bytes(16)?.try_into().unwrap())),
+        TAG_JSON => {
+            let length = reader.read_u32()? as usize;
+            let document = serde_json::from_slice(reader.read_bytes(length)?)
+                .map_err(|_| DatastoreError::Corrupt("Invalid JSON document".to_string()))?;
+            ColumnData::Json(document)
+        }
+        TAG_ARRAY => {
+            let length = reader.read_u32()? as usize;
+            let elements = (0..length).map(|_| decode_data(reader)).collect::<Result<Vec<ColumnData>, DatastoreError>>()?;
+            ColumnData::Array(elements)
+        }
+        TAG_VECTOR => {
+            let length = reader.read_u32()? as usize;
+            let vector = (0..length).map(|_| reader.read_u32().map(f32::from_bits)).collect::<Result<Vec<f32>, DatastoreError>>()?;
+            ColumnData::Vector(vector)
+        }
+        TAG_ENUM => ColumnData::Enum(reader.read_u16()?),
+        TAG_NULL => ColumnData::Null,
+        tag => return Err(DatastoreError::Corrupt(format!("Unknown column data tag {}", tag))),
+    };
+    Ok(data)
+}
+
+pub fn encode_columns(columns: &[Column], buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(columns.len() as u16).to_le_bytes());
+    for column in columns {
+        let column_type = column.column_type.to_string();
+        buffer.extend_from_slice(&(column.name.len() as u16).to_le_bytes());
+        buffer.extend_from_slice(column.name.as_bytes());
+        // Enum types can be long, their length follows a byte that can not be a length otherwise
+        if column_type.len() < LONG_COLUMN_TYPE as usize {
+            buffer.push(column_type.len() as u8);
+        } else {
+            buffer.push(LONG_COLUMN_TYPE);
+            buffer.extend_from_slice(&(column_type.len() as u32).to_le_bytes());
+        }
+        buffer.extend_from_slice(column_type.as_bytes());
+    }
+    // Options of every column follow the names and types
+    for column in columns {
+        let mut flags = 0;
+        if column.nullable { flags |= COLUMN_NULLABLE };
+        if column.default.is_some() { flags |= COLUMN_DEFAULT };
+        if column.vector_index.is_some() { flags |= COLUMN_VECTOR_INDEX };
+        if column.references.is_some() { flags |= COLUMN_REFERENCES };
+        buffer.push(flags);
+        if let Some(default) = &column.default {
+            encode_data(default, buffer);
+        }
+        if let Some(metric) = column.vector_index {
+            buffer.push(metric as u8);
+        }
+        if let Some(reference) = &column.references {
+            buffer.extend_from_slice(&(reference.table.len() as u16).to_le_bytes());
+            buffer.extend_from_slice(reference.table.as_bytes());
+            buffer.push(reference.on_delete as u8);
+        }
+    }
+}
+
+pub fn decode_columns(reader: &mut Reader) -> Result<Vec<Column>, DatastoreError> {
+    let n_columns = reader.read_u16()?;
+    let mut columns = Vec::with_capacity(n_columns as usize);
+    for _ in 0..n_columns {
+        let name_length = reader.read_u16()? as usize;
+        let name = reader.read_string(name_length)?;
+        let type_length = match reader.read_u8()? {
+            LONG_COLUMN_TYPE => reader.read_u32()? as usize,
+            type_length => type_length as usize,
+        };
+        let column_type = reader.read_string(type_length)?;
+        let column_type = ColumnTypes::from_str(&column_type)
+            .map_err(|_| DatastoreError::Corrupt(format!("Unknown column type {}", column_type)))?;
+        columns.push(Column::new(name, column_type));
+    }
+    for column in columns.iter_mut() {
+        let flags = reader.read_u8()?;
+        column.nullable = flags & COLUMN_NULLABLE != 0;
+        if flags & COLUMN_DEFAULT != 0 {
+            column.default = Some(decode_data(reader)?);
+        }
+        if flags & COLUMN_VECTOR_INDEX != 0 {
+            let metric = match reader.read_u8()? {
+                0 => Metric::Cosine,
+                1 => Metric::L2,
+                2 => Metric::Dot,
+                metric => return Err(DatastoreError::Corrupt(format!("Unknown metric {}", metric))),
+            };
+            column.vector_index = Some(metric);
+        }
+        if flags & COLUMN_REFERENCES != 0 {
+            let table_length = reader.read_u16()? as usize;
+            let table = reader.read_string(table_length)?;
+            let on_delete = match reader.read_u8()? {
+                0 => OnDelete::Restrict,
+                1 => OnDelete::Cascade,
+                2 => OnDelete::SetNull,
+                on_delete => return Err(DatastoreError::Corrupt(format!("Unknown delete action {}", on_delete))),
+            };
+            column.references = Some(Reference { table, on_delete });
+        }
+    }
+    Ok(columns)
+}
+
+/// Keys follow the options of the columns
+pub fn encode_keys(keys: &Keys, buffer: &mut Vec<u8>) {
+    encode_names(&keys.primary, buffer);
+    buffer.extend_from_slice(&(keys.unique.len() as u16).to_le_bytes());
+    for key in &keys.unique {
+        encode_names(key, buffer);
+    }
+}
+
+pub fn decode_keys(reader: &mut Reader) -> Result<Keys, DatastoreError> {
+    let primary = decode_names(reader)?;
+    let n_unique = reader.read_u16()?;
+    let unique = (0..n_unique).map(|_| decode_names(reader)).collect::<Result<Vec<Vec<String>>, DatastoreError>>()?;
+    Ok(Keys { primary, unique })
+}
+
+fn encode_names(names: &[String], buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(names.len() as u16).to_le_bytes());
+    for name in names {
+        buffer.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        buffer.extend_from_slice(name.as_bytes());
+    }
+}
+
+fn decode_names(reader: &mut Reader) -> Result<Vec<String>, DatastoreError> {
+    let n_names = reader.read_u16()?;
+    (0..n_names)
+        .map(|_| {
+            let length = reader.read_u16()? as usize;
+            reader.read_string(length)
+        })
+        .collect()
+}
+
+/// Cursor over a byte slice that turns running out of bytes into a corruption error
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, position: 0 }
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], DatastoreError> {
+        if self.position + length > self.bytes.len() {
+            return Err(DatastoreError::Corrupt("Unexpected end of record".to_string()));
+        }
+        let bytes = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, DatastoreError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, DatastoreError> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, DatastoreError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, DatastoreError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    pub fn remaining(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.position..];
+        self.position = self.bytes.len();
+        bytes
+    }
+
+    pub fn read_string(&mut self, length: usize) -> Result<String, DatastoreError> {
+        String::from_utf8(self.read_bytes(length)?.to_vec())
+            .map_err(|_| DatastoreError::Corrupt("Invalid UTF-8 in record".to_string()))
+    }
+}
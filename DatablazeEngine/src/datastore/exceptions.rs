@@ -0,0 +1,63 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use datablaze_btree::exceptions::BTreeError;
+use datablaze_btree::node::{BTREE_MAX_KEY_SIZE, BTREE_MAX_VAL_SIZE};
+
+#[derive(Debug)]
+pub enum DatastoreError {
+    Io(io::Error),
+    InvalidName(String),
+    TableExists(String),
+    TableNotFound(String),
+    RowNotFound(u64),
+    /// No row of the table has the primary key looked up
+    KeyNotFound(String),
+    /// Another row of the table already has the data of a primary or unique key
+    DuplicateKey { table: String, columns: Vec<String> },
+    /// A column of the table references a row that does not exist
+    ReferenceNotFound { table: String, column: String },
+    /// Rows of another table still reference rows of the table
+    Referenced { table: String, by: String },
+    /// A row, or the key it is stored under, is larger than the tree holds
+    RecordTooLarge { size: usize, max: usize },
+    Corrupt(String),
+}
+
+impl Display for DatastoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatastoreError::Io(e) => write!(f, "I/O error: {}", e),
+            DatastoreError::InvalidName(name) => write!(f, "Invalid name: {}", name),
+            DatastoreError::TableExists(name) => write!(f, "Table already exists: {}", name),
+            DatastoreError::TableNotFound(name) => write!(f, "Table not found: {}", name),
+            DatastoreError::RowNotFound(index) => write!(f, "Row not found: {}", index),
+            DatastoreError::KeyNotFound(table) => write!(f, "Key not found in table: {}", table),
+            DatastoreError::DuplicateKey { table, columns } => {
+                write!(f, "Duplicate key ({}) in table {}", columns.join(", "), table)
+            }
+            DatastoreError::ReferenceNotFound { table, column } => {
+                write!(f, "Column {} of table {} references a row that does not exist", column, table)
+            }
+            DatastoreError::Referenced { table, by } => write!(f, "Rows of table {} are referenced by table {}", table, by),
+            DatastoreError::RecordTooLarge { size, max } => write!(f, "Record of {} bytes exceeds the maximum of {} bytes", size, max),
+            DatastoreError::Corrupt(reason) => write!(f, "Corrupt datastore: {}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for DatastoreError {
+    fn from(e: io::Error) -> Self {
+        DatastoreError::Io(e)
+    }
+}
+
+impl From<BTreeError> for DatastoreError {
+    fn from(e: BTreeError) -> Self {
+        match e {
+            BTreeError::Io(e) => DatastoreError::Io(e),
+            BTreeError::KeyTooLarge(size) => DatastoreError::RecordTooLarge { size, max: BTREE_MAX_KEY_SIZE },
+            BTreeError::ValueTooLarge(size) => DatastoreError::RecordTooLarge { size, max: BTREE_MAX_VAL_SIZE },
+            e => DatastoreError::Corrupt(e.to_string()),
+        }
+    }
+}
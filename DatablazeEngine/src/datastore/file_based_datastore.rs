@@ -1,31 +1,358 @@
-﻿use datablaze_types::enums::DatastoreVariants;
-use crate::datastore::datastore::Datastore;
+﻿use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use datablaze_btree::btree::BTree;
+use datablaze_btree::node::BTREE_MAX_VAL_SIZE;
+use datablaze_types::enums::DatastoreVariants;
+use crate::database::column::Column;
+use crate::database::key::Keys;
+use crate::datastore::datastore::{Datastore, Row, RowChange, RowId};
+use crate::datastore::encoding::{decode_columns, decode_keys, decode_row, encode_columns, encode_keys, encode_row, Reader};
+use crate::datastore::exceptions::DatastoreError;
+use crate::datastore::write_ahead_log::WriteAheadLog;
+
+const TREE_FILE_NAME: &str = "data.db";
+const LOG_FILE_NAME: &str = "wal.log";
+// Once the log grows past this size the tree is committed and the log is emptied
+const CHECKPOINT_LOG_SIZE: u64 = 16 * 1024 * 1024;
+
+// Every table is stored in the tree of its database under two kinds of keys:
+// table/<table> -> [next row id: u64][columns][keys]
+// row/<table>/<row id: u64> -> encoded row
+// Row ids are stored big endian, so the rows of a table sort by id.
+const TABLE_KEY_PREFIX: &[u8] = b"table/";
+const ROW_KEY_PREFIX: &[u8] = b"row/";
+
+struct TableInfo {
+    columns: Vec<Column>,
+    keys: Keys,
+    next_row_id: RowId,
+    // Whether next_row_id changed since the table key was last written
+    dirty: bool,
+}
 
 pub struct FileBasedDatastore {
-    
+    directory: PathBuf,
+    tree: BTree,
+    tables: HashMap<String, TableInfo>,
+    log: WriteAheadLog,
+    // Set when a failed change could not be undone. The tree may hold part of it, so nothing is
+    // read or written anymore, and the next start replays the log into the committed tree.
+    failed: bool,
+}
+
+impl FileBasedDatastore {
+    /// Open the database stored in a directory, caching at most the given number of pages
+    pub fn open(directory: PathBuf, cache_pages: usize) -> Result<FileBasedDatastore, DatastoreError> {
+        fs::create_dir_all(&directory)?;
+        let tree = BTree::open(&directory.join(TREE_FILE_NAME), cache_pages)?;
+        let (log, records) = WriteAheadLog::open(&directory.join(LOG_FILE_NAME))?;
+
+        let mut tables = HashMap::new();
+        for item in tree.prefix(TABLE_KEY_PREFIX)? {
+            let (key, value) = item?;
+            let name = String::from_utf8_lossy(&key[TABLE_KEY_PREFIX.len()..]).to_string();
+            if value.len() < 8 {
+                return Err(DatastoreError::Corrupt(format!("Table {} has no header", name)));
+            }
+            let next_row_id = u64::from_le_bytes(value[0..8].try_into().unwrap());
+            let mut reader = Reader::new(&value[8..]);
+            let columns = decode_columns(&mut reader)?;
+            let keys = decode_keys(&mut reader)?;
+            tables.insert(name, TableInfo { columns, keys, next_row_id, dirty: false });
+        }
+
+        let mut datastore = FileBasedDatastore { directory, tree, tables, log, failed: false };
+        // A log with records in it means the last shutdown did not checkpoint
+        if !records.is_empty() {
+            datastore.replay(records)?;
+            datastore.checkpoint()?;
+        }
+        Ok(datastore)
+    }
+
+    /// Whether the directory holds a database, rather than anything else that ended up next to
+    /// the databases
+    pub fn exists(directory: &Path) -> bool {
+        directory.join(TREE_FILE_NAME).is_file()
+    }
+
+    /// Names, columns and keys of every table found on disk
+    pub fn tables(&self) -> Vec<(String, Vec<Column>, Keys)> {
+        self.tables
+            .iter()
+            .map(|(name, table)| (name.to_string(), table.columns.clone(), table.keys.clone()))
+            .collect()
+    }
+
+    /// Make every change in the log durable in the tree, then empty the log
+    pub fn checkpoint(&mut self) -> Result<(), DatastoreError> {
+        self.check_failed()?;
+        for (name, table) in self.tables.iter_mut().filter(|(_, table)| table.dirty) {
+            self.tree.insert(&table_key(name), &encode_table(table)?)?;
+            table.dirty = false;
+        }
+        self.tree.commit()?;
+        self.log.truncate()
+    }
+
+    // Records are applied as puts and deletes of row ids, so replaying a change that already
+    // reached the tree before the crash leaves the same result
+    fn replay(&mut self, records: Vec<RowChange>) -> Result<(), DatastoreError> {
+        for record in records {
+            match record {
+                RowChange::Put { table, row_id, row } => {
+                    let table_info = table_info_mut(&mut self.tables, &table)?;
+                    if row_id >= table_info.next_row_id {
+                        table_info.next_row_id = row_id + 1;
+                        table_info.dirty = true;
+                    }
+                    self.tree.insert(&row_key(&table, row_id), &encode_value(&row)?)?;
+                }
+                RowChange::Delete { table, row_id } => {
+                    table_info_mut(&mut self.tables, &table)?;
+                    self.tree.delete(&row_key(&table, row_id))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Go back to how things were before a change that failed halfway: the committed tree with
+    // the entries logged before the change replayed on top, and the log cut back to them
+    fn undo(&mut self, logged: u64) {
+        let result = self.tree
+            .rollback()
+            .map_err(DatastoreError::from)
+            .and_then(|_| self.log.cut(logged))
+            .and_then(|records| self.replay(records));
+        if let Err(e) = result {
+            log::error!("Failed to undo a change to {}: {}", self.directory.display(), e);
+            self.failed = true;
+        }
+    }
+
+    fn check_failed(&self) -> Result<(), DatastoreError> {
+        if self.failed {
+            return Err(DatastoreError::Corrupt(format!("{} failed to undo a change and has to be opened again", self.directory.display())));
+        }
+        Ok(())
+    }
+
+    // Only called once a change is in the log and in the tree, so a failed checkpoint does not
+    // fail the change. The log keeps the change and the next checkpoint tries again.
+    fn checkpoint_if_needed(&mut self) {
+        if self.log.size() >= CHECKPOINT_LOG_SIZE
+            && let Err(e) = self.checkpoint()
+        {
+            log::error!("Failed to checkpoint {}: {}", self.directory.display(), e);
+        }
+    }
+
+    fn row_value(&self, table: &str, row_id: RowId) -> Result<Vec<u8>, DatastoreError> {
+        self.check_failed()?;
+        if !self.tables.contains_key(table) {
+            return Err(DatastoreError::TableNotFound(table.to_string()));
+        }
+        self.tree.lookup(&row_key(table, row_id))?.ok_or(DatastoreError::RowNotFound(row_id))
+    }
+}
+
+// Takes the map rather than the datastore, so the tree can be borrowed alongside the table
+fn table_info_mut<'a>(tables: &'a mut HashMap<String, TableInfo>, table: &str) -> Result<&'a mut TableInfo, DatastoreError> {
+    tables.get_mut(table).ok_or(DatastoreError::TableNotFound(table.to_string()))
+}
+
+fn table_key(table: &str) -> Vec<u8> {
+    [TABLE_KEY_PREFIX, table.as_bytes()].concat()
 }
 
+fn row_key_prefix(table: &str) -> Vec<u8> {
+    [ROW_KEY_PREFIX, table.as_bytes(), b"/"].concat()
+}
+
+fn row_key(table: &str, row_id: RowId) -> Vec<u8> {
+    [row_key_prefix(table).as_slice(), &row_id.to_be_bytes()].concat()
+}
+
+fn encode_table(table: &TableInfo) -> Result<Vec<u8>, DatastoreError> {
+    let mut value = table.next_row_id.to_le_bytes().to_vec();
+    encode_columns(&table.columns, &mut value);
+    encode_keys(&table.keys, &mut value);
+    check_value_size(value)
+}
+
+fn encode_value(row: &Row) -> Result<Vec<u8>, DatastoreError> {
+    let mut value = Vec::new();
+    encode_row(row, &mut value);
+    check_value_size(value)
+}
+
+// Checked up front, so a change that does not fit is rejected before it is logged
+fn check_value_size(value: Vec<u8>) -> Result<Vec<u8>, DatastoreError> {
+    if value.len() > BTREE_MAX_VAL_SIZE {
+        return Err(DatastoreError::RecordTooLarge { size: value.len(), max: BTREE_MAX_VAL_SIZE });
+    }
+    Ok(value)
+}
+
+impl Drop for FileBasedDatastore {
+    fn drop(&mut self) {
+        if let Err(e) = self.checkpoint() {
+            log::error!("Failed to checkpoint {}: {}", self.directory.display(), e);
+        }
+    }
+}
+
+/// Database and table names end up in file names and tree keys, so only allow a safe subset of
+/// characters
+pub fn validate_name(name: &str) -> Result<(), DatastoreError> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid { Ok(()) } else { Err(DatastoreError::InvalidName(name.to_string())) }
+}
+
+// Every change is logged and synced before it touches the tree, so an acknowledged change
+// survives a crash even though the tree itself is only committed at checkpoints
 impl Datastore for FileBasedDatastore {
-    fn insert(&mut self) {
-        todo!()
+    fn insert(&mut self, table: &str, rows: Vec<Row>) -> Result<Vec<RowId>, DatastoreError> {
+        let row_ids = self.reserve_row_ids(table, rows.len())?;
+        let changes = row_ids
+            .iter()
+            .zip(rows)
+            .map(|(&row_id, row)| RowChange::Put { table: table.to_string(), row_id, row })
+            .collect();
+        self.apply(changes)?;
+        Ok(row_ids)
+    }
+
+    fn delete(&mut self, table: &str, row_id: RowId) -> Result<(), DatastoreError> {
+        self.row_value(table, row_id)?;
+        self.apply(vec![RowChange::Delete { table: table.to_string(), row_id }])
     }
 
-    fn delete(&mut self) {
-        todo!()
+    fn create(&mut self, table: &str, columns: &[Column], keys: &Keys) -> Result<(), DatastoreError> {
+        self.check_failed()?;
+        validate_name(table)?;
+        if self.tables.contains_key(table) {
+            return Err(DatastoreError::TableExists(table.to_string()));
+        }
+        let table_info = TableInfo { columns: columns.to_vec(), keys: keys.clone(), next_row_id: 0, dirty: false };
+        self.tree.insert(&table_key(table), &encode_table(&table_info)?)?;
+        self.tables.insert(table.to_string(), table_info);
+        // Tables are not logged, so make the new one durable right away
+        self.checkpoint()
     }
 
-    fn create(&mut self) {
-        todo!()
+    fn update(&mut self, table: &str, row_id: RowId, row: Row) -> Result<(), DatastoreError> {
+        self.row_value(table, row_id)?;
+        self.apply(vec![RowChange::Put { table: table.to_string(), row_id, row }])
     }
 
-    fn update(&mut self) {
-        todo!()
+    fn select(&self, table: &str, row_id: RowId) -> Result<Row, DatastoreError> {
+        decode_row(&self.row_value(table, row_id)?)
     }
 
-    fn select(&self) {
-        todo!()
+    fn scan(&self, table: &str) -> Result<Vec<(RowId, Row)>, DatastoreError> {
+        self.check_failed()?;
+        if !self.tables.contains_key(table) {
+            return Err(DatastoreError::TableNotFound(table.to_string()));
+        }
+        let prefix = row_key_prefix(table);
+        let mut rows = Vec::new();
+        for item in self.tree.prefix(&prefix)? {
+            let (key, value) = item?;
+            let row_id = key[prefix.len()..]
+                .try_into()
+                .map(RowId::from_be_bytes)
+                .map_err(|_| DatastoreError::Corrupt(format!("Invalid row key in table {}", table)))?;
+            rows.push((row_id, decode_row(&value)?));
+        }
+        Ok(rows)
     }
+
     fn get_type(&self) -> DatastoreVariants {
         DatastoreVariants::FileBased
     }
-}
\ No newline at end of file
+
+    // Reserved ids are not logged. After a crash the ids of changes that never made it to the log
+    // may be handed out again, but no row was ever stored under them.
+    fn reserve_row_ids(&mut self, table: &str, count: usize) -> Result<Vec<RowId>, DatastoreError> {
+        self.check_failed()?;
+        let table_info = table_info_mut(&mut self.tables, table)?;
+        let row_ids = (table_info.next_row_id..).take(count).collect();
+        table_info.next_row_id += count as RowId;
+        table_info.dirty = true;
+        Ok(row_ids)
+    }
+
+    // All changes are logged as one entry, so a crash halfway through replays all of them. A
+    // change that fails halfway is undone, in the tree as well as in the log.
+    fn apply(&mut self, changes: Vec<RowChange>) -> Result<(), DatastoreError> {
+        self.check_failed()?;
+        let mut writes = Vec::with_capacity(changes.len());
+        for change in &changes {
+            match change {
+                RowChange::Put { table, row_id, row } => {
+                    table_info_mut(&mut self.tables, table)?;
+                    writes.push((row_key(table, *row_id), Some(encode_value(row)?)));
+                }
+                RowChange::Delete { table, row_id } => {
+                    table_info_mut(&mut self.tables, table)?;
+                    writes.push((row_key(table, *row_id), None));
+                }
+            }
+        }
+        let logged = self.log.size();
+        let result = self.log.log(&changes).and_then(|_| {
+            for (key, value) in writes {
+                match value {
+                    Some(value) => self.tree.insert(&key, &value)?,
+                    None => {
+                        self.tree.delete(&key)?;
+                    }
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            self.undo(logged);
+            return Err(e);
+        }
+        self.checkpoint_if_needed();
+        Ok(())
+    }
+
+    // Not logged. Committing the tree right before and right after the rewrite makes it one
+    // commit, a crash leaves either the old or the new table. A rewrite that fails halfway goes
+    // back to the commit right before it.
+    fn alter(&mut self, table: &str, columns: &[Column], keys: &Keys, rows: Vec<(RowId, Row)>) -> Result<(), DatastoreError> {
+        let table_info = self.tables.get(table).ok_or(DatastoreError::TableNotFound(table.to_string()))?;
+        let altered = TableInfo { columns: columns.to_vec(), keys: keys.clone(), next_row_id: table_info.next_row_id, dirty: false };
+        let table_value = encode_table(&altered)?;
+        let values = rows
+            .iter()
+            .map(|(row_id, row)| Ok((row_key(table, *row_id), encode_value(row)?)))
+            .collect::<Result<Vec<(Vec<u8>, Vec<u8>)>, DatastoreError>>()?;
+        self.checkpoint()?;
+        // The altered table only replaces the old one once its rows are committed
+        let result = values
+            .iter()
+            .chain([&(table_key(table), table_value)])
+            .try_for_each(|(key, value)| self.tree.insert(key, value))
+            .and_then(|_| self.tree.commit());
+        if let Err(e) = result {
+            if let Err(rollback) = self.tree.rollback() {
+                log::error!("Failed to roll back {}: {}", self.directory.display(), rollback);
+                self.failed = true;
+            }
+            return Err(e.into());
+        }
+        self.tables.insert(table.to_string(), altered);
+        Ok(())
+    }
+
+    fn set_cache_pages(&mut self, pages: usize) -> Result<(), DatastoreError> {
+        Ok(self.tree.set_cache_pages(pages)?)
+    }
+}
@@ -1,31 +1,98 @@
-﻿use datablaze_types::enums::DatastoreVariants;
-use crate::datastore::datastore::Datastore;
+﻿use std::collections::{BTreeMap, HashMap};
+use datablaze_types::enums::DatastoreVariants;
+use crate::database::column::Column;
+use crate::database::key::Keys;
+use crate::datastore::datastore::{Datastore, Row, RowChange, RowId};
+use crate::datastore::exceptions::DatastoreError;
 
+#[derive(Default)]
+struct TableRows {
+    rows: BTreeMap<RowId, Row>,
+    next_row_id: RowId,
+}
+
+#[derive(Default)]
 pub struct InMemoryDatastore {
-    
+    tables: HashMap<String, TableRows>,
+}
+
+impl InMemoryDatastore {
+    fn table(&self, table: &str) -> Result<&TableRows, DatastoreError> {
+        self.tables.get(table).ok_or(DatastoreError::TableNotFound(table.to_string()))
+    }
+
+    fn table_mut(&mut self, table: &str) -> Result<&mut TableRows, DatastoreError> {
+        self.tables.get_mut(table).ok_or(DatastoreError::TableNotFound(table.to_string()))
+    }
 }
 
 impl Datastore for InMemoryDatastore {
-    fn insert(&mut self) {
-        todo!()
+    fn insert(&mut self, table: &str, rows: Vec<Row>) -> Result<Vec<RowId>, DatastoreError> {
+        let row_ids = self.reserve_row_ids(table, rows.len())?;
+        let table_rows = self.table_mut(table)?;
+        table_rows.rows.extend(row_ids.iter().copied().zip(rows));
+        Ok(row_ids)
+    }
+
+    fn delete(&mut self, table: &str, row_id: RowId) -> Result<(), DatastoreError> {
+        self.table_mut(table)?.rows.remove(&row_id).ok_or(DatastoreError::RowNotFound(row_id))?;
+        Ok(())
     }
 
-    fn delete(&mut self) {
-        todo!()
+    fn create(&mut self, table: &str, _columns: &[Column], _keys: &Keys) -> Result<(), DatastoreError> {
+        if self.tables.contains_key(table) {
+            return Err(DatastoreError::TableExists(table.to_string()));
+        }
+        self.tables.insert(table.to_string(), TableRows::default());
+        Ok(())
     }
 
-    fn create(&mut self) {
-        todo!()
+    fn update(&mut self, table: &str, row_id: RowId, row: Row) -> Result<(), DatastoreError> {
+        let existing = self.table_mut(table)?.rows.get_mut(&row_id).ok_or(DatastoreError::RowNotFound(row_id))?;
+        *existing = row;
+        Ok(())
     }
 
-    fn update(&mut self) {
-        todo!()
+    fn select(&self, table: &str, row_id: RowId) -> Result<Row, DatastoreError> {
+        self.table(table)?.rows.get(&row_id).cloned().ok_or(DatastoreError::RowNotFound(row_id))
     }
 
-    fn select(&self) {
-        todo!()
+    fn scan(&self, table: &str) -> Result<Vec<(RowId, Row)>, DatastoreError> {
+        Ok(self.table(table)?.rows.iter().map(|(row_id, row)| (*row_id, row.clone())).collect())
     }
+
     fn get_type(&self) -> DatastoreVariants {
         DatastoreVariants::InMemory
     }
-}
\ No newline at end of file
+
+    fn reserve_row_ids(&mut self, table: &str, count: usize) -> Result<Vec<RowId>, DatastoreError> {
+        let table_rows = self.table_mut(table)?;
+        let row_ids = (table_rows.next_row_id..).take(count).collect();
+        table_rows.next_row_id += count as RowId;
+        Ok(row_ids)
+    }
+
+    // Only a missing table can fail a change, so check every table before changing anything
+    fn apply(&mut self, changes: Vec<RowChange>) -> Result<(), DatastoreError> {
+        for change in &changes {
+            let (RowChange::Put { table, .. } | RowChange::Delete { table, .. }) = change;
+            self.table(table)?;
+        }
+        for change in changes {
+            match change {
+                RowChange::Put { table, row_id, row } => {
+                    self.table_mut(&table)?.rows.insert(row_id, row);
+                }
+                RowChange::Delete { table, row_id } => {
+                    self.table_mut(&table)?.rows.remove(&row_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn alter(&mut self, table: &str, _columns: &[Column], _keys: &Keys, rows: Vec<(RowId, Row)>) -> Result<(), DatastoreError> {
+        self.table_mut(table)?.rows = rows.into_iter().collect();
+        Ok(())
+    }
+}
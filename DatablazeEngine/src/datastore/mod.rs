@@ -1,3 +1,7 @@
 ﻿pub mod in_memory_datastore;
+#[allow(clippy::module_inception)]
 pub mod datastore;
-pub mod file_based_datastore;
\ No newline at end of file
+pub mod file_based_datastore;
+pub mod exceptions;
+pub mod encoding;
+pub mod write_ahead_log;
\ No newline at end of file
@@ -0,0 +1,169 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::datastore::datastore::{RowChange, RowId};
+use crate::datastore::encoding::{decode_row, encode_row, Reader};
+use crate::datastore::exceptions::DatastoreError;
+
+// Every entry is framed as [length: u32][checksum: u32][records], the checksum covering the
+// records. An entry holds the records of one change, each as [length: u32][record], so a change
+// that touches many rows is either replayed whole or not at all.
+const ENTRY_HEADER: usize = 4 + 4;
+const RECORD_PUT: u8 = 1;
+const RECORD_DELETE: u8 = 2;
+
+/// Log of row changes that is synced to disk before the change is applied to the tree
+pub struct WriteAheadLog {
+    file: File,
+    size: u64,
+}
+
+impl WriteAheadLog {
+    /// Open the log and return every complete record in it. A torn entry at the end, left by a
+    /// crash halfway through an append, was never acknowledged and is cut off. A bad entry with
+    /// more entries after it fails the open, since those were acknowledged.
+    pub fn open(path: &Path) -> Result<(WriteAheadLog, Vec<RowChange>), DatastoreError> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let (records, position) = decode_entries(&bytes)?;
+        if position < bytes.len() {
+            file.set_len(position as u64)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(position as u64))?;
+        Ok((WriteAheadLog { file, size: position as u64 }, records))
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Append the records of a single change and sync them to disk
+    pub fn log(&mut self, records: &[RowChange]) -> Result<(), DatastoreError> {
+        let mut payload = Vec::new();
+        for record in records {
+            let mut encoded = Vec::new();
+            encode_record(record, &mut encoded);
+            payload.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            payload.extend_from_slice(&encoded);
+        }
+        self.append(&payload)
+    }
+
+    /// Cut the log back to an earlier size, dropping the entries appended since, and return the
+    /// records left in it
+    pub fn cut(&mut self, size: u64) -> Result<Vec<RowChange>, DatastoreError> {
+        self.file.set_len(size)?;
+        self.file.sync_all()?;
+        self.size = size;
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut bytes = Vec::new();
+        self.file.read_to_end(&mut bytes)?;
+        Ok(decode_entries(&bytes)?.0)
+    }
+
+    /// Empty the log once everything in it is safely committed to the tree
+    pub fn truncate(&mut self) -> Result<(), DatastoreError> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_all()?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn append(&mut self, payload: &[u8]) -> Result<(), DatastoreError> {
+        let mut entry = Vec::with_capacity(ENTRY_HEADER + payload.len());
+        entry.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        entry.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+        entry.extend_from_slice(payload);
+        self.file.write_all(&entry)?;
+        self.file.sync_data()?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+}
+
+// Records of every complete entry, and the position the entries end at. Only the last entry can
+// be torn by a crash, an entry that fails its checksum anywhere else means the log is corrupt.
+fn decode_entries(bytes: &[u8]) -> Result<(Vec<RowChange>, usize), DatastoreError> {
+    let mut records = Vec::new();
+    let mut position = 0;
+    while position + ENTRY_HEADER <= bytes.len() {
+        let length = u32::from_le_bytes(bytes[position..position + 4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().unwrap());
+        let start = position + ENTRY_HEADER;
+        // An entry running past the end is the torn tail, unless its length is damaged and
+        // complete entries follow it
+        if start + length > bytes.len() {
+            if entry_follows(bytes, position + 1) {
+                return Err(DatastoreError::Corrupt(format!("Log entry at {} has a bad length", position)));
+            }
+            break;
+        }
+        if crc32fast::hash(&bytes[start..start + length]) != checksum {
+            if start + length == bytes.len() {
+                break;
+            }
+            return Err(DatastoreError::Corrupt(format!("Log entry at {} fails its checksum", position)));
+        }
+        records.extend(decode_entry(&bytes[start..start + length])?);
+        position = start + length;
+    }
+    Ok((records, position))
+}
+
+// Whether a complete entry starts anywhere from the position on. Entries hold at least one
+// record, so the zeros a crash may leave at the end do not count as one.
+fn entry_follows(bytes: &[u8], from: usize) -> bool {
+    (from..bytes.len().saturating_sub(ENTRY_HEADER)).any(|position| {
+        let length = u32::from_le_bytes(bytes[position..position + 4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(bytes[position + 4..position + 8].try_into().unwrap());
+        let start = position + ENTRY_HEADER;
+        let Some(payload) = bytes.get(start..start.saturating_add(length)) else { return false };
+        length > 0 && decode_entry(payload).is_ok() && crc32fast::hash(payload) == checksum
+    })
+}
+
+fn encode_record(record: &RowChange, buffer: &mut Vec<u8>) {
+    match record {
+        RowChange::Put { table, row_id, row } => {
+            buffer.push(RECORD_PUT);
+            encode_target(table, *row_id, buffer);
+            encode_row(row, buffer);
+        }
+        RowChange::Delete { table, row_id } => {
+            buffer.push(RECORD_DELETE);
+            encode_target(table, *row_id, buffer);
+        }
+    }
+}
+
+fn encode_target(table: &str, row_id: RowId, buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(table.len() as u16).to_le_bytes());
+    buffer.extend_from_slice(table.as_bytes());
+    buffer.extend_from_slice(&row_id.to_le_bytes());
+}
+
+fn decode_entry(bytes: &[u8]) -> Result<Vec<RowChange>, DatastoreError> {
+    let mut reader = Reader::new(bytes);
+    let mut records = Vec::new();
+    while !reader.is_at_end() {
+        let length = reader.read_u32()? as usize;
+        records.push(decode_record(reader.read_bytes(length)?)?);
+    }
+    Ok(records)
+}
+
+fn decode_record(bytes: &[u8]) -> Result<RowChange, DatastoreError> {
+    let mut reader = Reader::new(bytes);
+    let kind = reader.read_u8()?;
+    let table_length = reader.read_u16()? as usize;
+    let table = reader.read_string(table_length)?;
+    let row_id = reader.read_u64()?;
+    match kind {
+        RECORD_PUT => Ok(RowChange::Put { table, row_id, row: decode_row(reader.remaining())? }),
+        RECORD_DELETE => Ok(RowChange::Delete { table, row_id }),
+        kind => Err(DatastoreError::Corrupt(format!("Unknown log record kind {}", kind))),
+    }
+}
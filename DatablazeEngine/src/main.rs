@@ -1,19 +1,30 @@
 pub use datablaze_types::enums::*;
 
+mod config;
 mod datastore;
 mod network;
 mod database;
+#[cfg(test)]
 mod tests;
 
+use config::EngineConfig;
+use network::model::Server;
 use network::route::create_router;
 
 
 #[tokio::main]
 async fn main() {
 
-    let app = create_router();
+    let server = Server::load(EngineConfig::from_env()).expect("Failed to load databases");
+    let app = create_router(server);
 
     println!("🚀 Server started successfully");
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app).with_graceful_shutdown(shutdown_signal()).await.unwrap();
+    // Dropping the server checkpoints file based databases, so the next start has nothing to replay
+    println!("Server stopped");
+}
+
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c().await.expect("Failed to listen for shutdown signal");
 }
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use std::str::FromStr;
+﻿use std::str::FromStr;
+use std::sync::Arc;
 use axum::{
     extract::{Query, State},
     http::StatusCode,
@@ -9,13 +9,18 @@ use axum::{
 use axum::extract::Path;
 use axum_macros::debug_handler;
 use datablaze_types::enums::{ColumnTypes, DatastoreVariants};
+use crate::database::alter::Alteration;
 use crate::database::column::Column;
 use crate::database::database::Database;
+use crate::database::exceptions::{TableError, TransactionError};
+use crate::database::key::Keys;
+use crate::database::transaction::TransactionId;
 use crate::database::table::Table;
+use crate::datastore::exceptions::DatastoreError;
 use crate::network::{
-    model::{ServerState},
+    model::{Server, ServerState},
 };
-use crate::network::model::{ColumnResponseModel, DatabaseCreateRequestModel, DatabaseGetQueryModel, DatabaseResponseModel, RowCreateRequestModel, RowGetQueryModel, RowUpdateRequestModel, TableCreateRequestModel, TableGetQueryModel, TableResponseModel};
+use crate::network::model::{AggregateQueryModel, AggregateResponseModel, ColumnCreateRequestModel, ColumnQueryModel, ColumnResponseModel, ColumnUpdateRequestModel, DatabaseCreateRequestModel, DatabaseGetQueryModel, DatabaseResponseModel, RowCreateRequestModel, RowCreateResponseModel, RowGetQueryModel, RowKeyRequestModel, NearestRequestModel, NearestResponseModel, NearestRowModel, QueryRequestModel, QueryResponseModel, QueryRowModel, RowUpdateRequestModel, TableCreateRequestModel, TableGetQueryModel, TableResponseModel, TransactionQueryModel, TransactionResponseModel};
 
 pub async fn health_checker_handler() -> impl IntoResponse {
     StatusCode::OK
@@ -27,13 +32,18 @@ pub async fn database_get_handler(
     State(db): State<ServerState>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     
-    let state = db.lock().await;
-    let maybe_result = state.databases.iter().find(|db| db.name == opts.name);
+    let maybe_result = db.database(&opts.name);
     let db = match maybe_result {
         Some(db) => {db}
         None => {return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))}
     };
-    let json_response = DatabaseResponseModel { name: db.name.to_string(), datastore: db.datastore.get_type().to_string() };
+    let database = db.clone();
+    let datastore = blocking(move || database.datastore_type()).await;
+    let json_response = DatabaseResponseModel {
+        name: db.name.to_string(),
+        datastore: datastore.to_string(),
+        keep_schema: db.keep_schema,
+    };
     Ok((StatusCode::OK, Json(json_response)))
 }
 
@@ -43,13 +53,55 @@ pub async fn database_create_handler(
     Json(body): Json<DatabaseCreateRequestModel>,
 ) -> impl IntoResponse {
     
-    let mut state = db.lock().await;
     let datastore = match DatastoreVariants::from_str(&body.datastore) {
         Ok(datastore) => {datastore}
         Err(_) => {return StatusCode::BAD_REQUEST}
     };
-    let db = Database::new(body.name.clone(), datastore);
-    state.databases.push(db);
+    if db.database(&body.name).is_some() || !db.creating.lock().unwrap().insert(body.name.clone()) {
+        return StatusCode::CONFLICT
+    }
+    // Opening a database creates its directory and replays its log, which blocks
+    let name = body.name.clone();
+    let server = db.clone();
+    tokio::task::spawn_blocking(move || create_database(&server, body, datastore))
+        .await
+        .unwrap_or_else(|_| {
+            db.creating.lock().unwrap().remove(&name);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+fn create_database(db: &Server, body: DatabaseCreateRequestModel, datastore: DatastoreVariants) -> StatusCode {
+    let name = body.name.clone();
+    let status = open_database(db, body, datastore);
+    db.creating.lock().unwrap().remove(&name);
+    // Whether or not the database was added, the caches get split again
+    if let Err(e) = db.share_cache() {
+        log::error!("Failed to resize the page caches: {}", e);
+    }
+    status
+}
+
+// The databases are only write locked to add the database once it is open
+fn open_database(db: &Server, body: DatabaseCreateRequestModel, datastore: DatastoreVariants) -> StatusCode {
+    let cache_pages = match db.share_cache() {
+        Ok(cache_pages) => {cache_pages}
+        Err(e) => {return table_error_status(&e)}
+    };
+    let database = match Database::new(body.name, datastore, body.keep_schema, &db.config, cache_pages) {
+        Ok(database) => {database}
+        Err(e) => {return table_error_status(&e)}
+    };
+    let keep_schema = database.keep_schema;
+    let mut databases = db.databases.write().unwrap();
+    if databases.iter().any(|existing| existing.name == database.name) {
+        return StatusCode::CONFLICT
+    }
+    databases.push(Arc::new(database));
+    drop(databases);
+    if keep_schema {
+        save_catalog(db);
+    }
 
     StatusCode::CREATED
 }
@@ -60,31 +112,87 @@ pub async fn table_create_handler(
     Json(body): Json<TableCreateRequestModel>,
 ) -> impl IntoResponse {
     
-    let mut state = db.lock().await;
-    
-    let maybe_database = state
-        .databases
-        .iter_mut()
-        .find(|db| db.name == db_name);
+    let maybe_database = db.database(&db_name);
     let database = match maybe_database {
         Some(db) => {db}
         None => {return StatusCode::NOT_FOUND}
     };
     
-    let mut columns = HashMap::new();
-    for request_column in body.columns.iter() {
-        let maybe_column_type = ColumnTypes::from_str(request_column.column_type.as_str());
-        let column_type = match maybe_column_type {
-            Ok(column_type) => {column_type}
-            Err(_) => {return StatusCode::BAD_REQUEST}
+    let mut columns = Vec::new();
+    for request_column in body.columns {
+        match build_column(request_column) {
+            Some(column) => {columns.push(column)}
+            None => {return StatusCode::BAD_REQUEST}
         };
-        columns.insert(request_column.name.to_string(), Column::new(request_column.name.clone(), column_type));
     }
-    let table = Table::new(body.name, columns);
-    
-    database.tables.push(table);
+    let keys = Keys { primary: body.primary_key, unique: body.unique };
+    let table = match Table::with_keys(body.name, columns, keys) {
+        Ok(table) => {table}
+        Err(_) => {return StatusCode::BAD_REQUEST}
+    };
     
-    StatusCode::CREATED
+    blocking(move || {
+        if let Err(e) = database.add_table(table) {
+            return table_error_status(&e)
+        }
+        if database.keep_schema {
+            save_catalog(&db);
+        }
+
+        StatusCode::CREATED
+    }).await
+}
+
+pub async fn column_add_handler(
+    Path((database_name, table_name)): Path<(String, String)>,
+    State(db): State<ServerState>,
+    Json(body): Json<ColumnCreateRequestModel>,
+) -> impl IntoResponse {
+
+    let maybe_database = db.database(&database_name);
+    let database = match maybe_database {
+        Some(db) => {db}
+        None => {return StatusCode::NOT_FOUND}
+    };
+    let column = match build_column(body) {
+        Some(column) => {column}
+        None => {return StatusCode::BAD_REQUEST}
+    };
+    alter_table(db, database, table_name, Alteration::Add(column), StatusCode::CREATED).await
+}
+
+pub async fn column_update_handler(
+    opts: Query<ColumnQueryModel>,
+    Path((database_name, table_name)): Path<(String, String)>,
+    State(db): State<ServerState>,
+    Json(body): Json<ColumnUpdateRequestModel>,
+) -> impl IntoResponse {
+
+    let maybe_database = db.database(&database_name);
+    let database = match maybe_database {
+        Some(db) => {db}
+        None => {return StatusCode::NOT_FOUND}
+    };
+    let column_type = match body.column_type.map(|column_type| ColumnTypes::from_str(&column_type)).transpose() {
+        Ok(column_type) => {column_type}
+        Err(_) => {return StatusCode::BAD_REQUEST}
+    };
+    let alteration = Alteration::Change { column: opts.name.clone(), name: body.name, column_type };
+    alter_table(db, database, table_name, alteration, StatusCode::ACCEPTED).await
+}
+
+pub async fn column_delete_handler(
+    opts: Query<ColumnQueryModel>,
+    Path((database_name, table_name)): Path<(String, String)>,
+    State(db): State<ServerState>,
+) -> impl IntoResponse {
+
+    let maybe_database = db.database(&database_name);
+    let database = match maybe_database {
+        Some(db) => {db}
+        None => {return StatusCode::NOT_FOUND}
+    };
+    alter_table(db, database, table_name, Alteration::Drop(opts.name.clone()), StatusCode::ACCEPTED).await
 }
 
 #[debug_handler]
@@ -94,18 +202,29 @@ pub async fn table_get_handler(
     State(db): State<ServerState>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
 
-    let state = db.lock().await;
-    
-    let maybe_database = state.databases.iter().find(|db| db.name == database_name);
+    let maybe_database = db.database(&database_name);
     if let Some(database) = maybe_database {
-        let maybe_table = database.tables.iter().find(|table| table.name == opts.name);
+        let tables = blocking(move || database.tables()).await;
+        let maybe_table = tables.into_iter().find(|table| table.name == opts.name);
         if let Some(table) = maybe_table {
             let columns: Vec<ColumnResponseModel> = table
                 .columns
-                .values()
-                .map(|column| ColumnResponseModel { name: column.name.clone(), column_type: column.column_type.to_string() })
+                .iter()
+                .map(|column| ColumnResponseModel {
+                    name: column.name.clone(),
+                    column_type: column.column_type.to_string(),
+                    nullable: column.nullable,
+                    default: column.default_value(),
+                    vector_index: column.vector_index,
+                    references: column.references.clone(),
+                })
                 .collect();
-            let json_response = TableResponseModel { name: table.name.to_string(), columns };
+            let json_response = TableResponseModel {
+                name: table.name.to_string(),
+                columns,
+                primary_key: table.keys.primary,
+                unique: table.keys.unique,
+            };
             Ok((StatusCode::OK, Json(json_response)))
         } else {
             Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Table not found" }))))
@@ -117,24 +236,20 @@ pub async fn table_get_handler(
 
 pub async fn row_create_handler(
     Path((database_name, table_name)): Path<(String, String)>,
+    opts: Query<TransactionQueryModel>,
     State(db): State<ServerState>,
     Json(body): Json<RowCreateRequestModel>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
 
-    let mut state = db.lock().await;
-    
-    let maybe_database = state.databases.iter_mut().find(|db| db.name == database_name);
+    let maybe_database = db.database(&database_name);
     if let Some(database) = maybe_database {
-        let maybe_table = database.tables.iter_mut().find(|table| table.name == table_name);
-        if let Some(table) = maybe_table {
-            for row in body.data.into_iter() {
-                if let Err(e) = table.add_row(row) {
-                    return StatusCode::BAD_REQUEST;
-                }
-            }
+        match blocking(move || database.add_rows(&table_name, body.data, opts.transaction)).await {
+            Ok(ids) => Ok((StatusCode::CREATED, Json(RowCreateResponseModel { ids }))),
+            Err(e) => Err((table_error_status(&e), Json(serde_json::json!({ "error": e.to_string() })))),
         }
+    } else {
+        Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))
     }
-    StatusCode::CREATED
 }
 
 pub async fn row_update_handler(
@@ -144,16 +259,13 @@ pub async fn row_update_handler(
     Json(body): Json<RowUpdateRequestModel>,
 ) -> impl IntoResponse {
 
-    let mut state = db.lock().await;
-
-    let maybe_database = state.databases.iter_mut().find(|db| db.name == database_name);
-    if let Some(database) = maybe_database {
-        let maybe_table = database.tables.iter_mut().find(|table| table.name == table_name);
-        if let Some(table) = maybe_table {
-            if let Err(e) = table.update_row(body.data, opts.index) {
-                return StatusCode::BAD_REQUEST;
-            }
-        }
+    let maybe_database = db.database(&database_name);
+    let database = match maybe_database {
+        Some(db) => {db}
+        None => {return StatusCode::NOT_FOUND}
+    };
+    if let Err(e) = blocking(move || database.update_row(&table_name, body.data, opts.id, opts.transaction)).await {
+        return table_error_status(&e);
     }
     StatusCode::ACCEPTED
 }
@@ -164,16 +276,13 @@ pub async fn row_delete_handler(
     State(db): State<ServerState>,
 ) -> impl IntoResponse {
 
-    let mut state = db.lock().await;
-
-    let maybe_database = state.databases.iter_mut().find(|db| db.name == database_name);
-    if let Some(database) = maybe_database {
-        let maybe_table = database.tables.iter_mut().find(|table| table.name == table_name);
-        if let Some(table) = maybe_table {
-            if let Err(e) = table.delete_row(opts.index) {
-                return StatusCode::BAD_REQUEST;
-            }
-        }
+    let maybe_database = db.database(&database_name);
+    let database = match maybe_database {
+        Some(db) => {db}
+        None => {return StatusCode::NOT_FOUND}
+    };
+    if let Err(e) = blocking(move || database.delete_row(&table_name, opts.id, opts.transaction)).await {
+        return table_error_status(&e);
     }
     StatusCode::ACCEPTED
 }
@@ -185,21 +294,202 @@ pub async fn row_get_handler(
     State(db): State<ServerState>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
 
-    let state = db.lock().await;
+    let maybe_database = db.database(&database_name);
+    if let Some(database) = maybe_database {
+        match blocking(move || database.get_row(&table_name, opts.id, opts.transaction)).await {
+            Ok(row) => Ok((StatusCode::OK, Json(row))),
+            Err(e) => Err((table_error_status(&e), Json(serde_json::json!({ "error": e.to_string() })))),
+        }
+    } else {
+        Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))
+    }
+}
+
+#[debug_handler]
+pub async fn row_get_by_key_handler(
+    Path((database_name, table_name)): Path<(String, String)>,
+    opts: Query<TransactionQueryModel>,
+    State(db): State<ServerState>,
+    Json(body): Json<RowKeyRequestModel>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
 
-    let maybe_database = state.databases.iter().find(|db| db.name == database_name);
+    let maybe_database = db.database(&database_name);
     if let Some(database) = maybe_database {
-        let maybe_table = database.tables.iter().find(|table| table.name == table_name);
-        if let Some(table) = maybe_table {
-            if let Ok(row) = table.get_row(opts.index) {
-                Ok((StatusCode::OK, Json(row)))
-            } else {
-                Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Row not found" }))))
+        match blocking(move || database.get_row_by_key(&table_name, body.key, opts.transaction)).await {
+            Ok((id, data)) => Ok((StatusCode::OK, Json(QueryRowModel { id, data }))),
+            Err(e) => Err((table_error_status(&e), Json(serde_json::json!({ "error": e.to_string() })))),
+        }
+    } else {
+        Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))
+    }
+}
+
+#[debug_handler]
+pub async fn aggregate_handler(
+    opts: Query<AggregateQueryModel>,
+    Path((database_name, table_name)): Path<(String, String)>,
+    State(db): State<ServerState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+
+    let maybe_database = db.database(&database_name);
+    if let Some(database) = maybe_database {
+        match blocking(move || database.aggregate(&table_name, &opts.column, opts.function, opts.transaction)).await {
+            Ok(value) => Ok((StatusCode::OK, Json(AggregateResponseModel { value }))),
+            Err(e) => Err((table_error_status(&e), Json(serde_json::json!({ "error": e.to_string() })))),
+        }
+    } else {
+        Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))
+    }
+}
+
+#[debug_handler]
+pub async fn query_handler(
+    opts: Query<TransactionQueryModel>,
+    Path((database_name, table_name)): Path<(String, String)>,
+    State(db): State<ServerState>,
+    Json(body): Json<QueryRequestModel>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+
+    let maybe_database = db.database(&database_name);
+    if let Some(database) = maybe_database {
+        match blocking(move || database.query(&table_name, body.filters, body.select, body.unnest.as_deref(), opts.transaction)).await {
+            Ok(rows) => {
+                let rows = rows.into_iter().map(|(id, data)| QueryRowModel { id, data }).collect();
+                Ok((StatusCode::OK, Json(QueryResponseModel { rows })))
             }
-        } else {
-            Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Table not found" }))))
+            Err(e) => Err((table_error_status(&e), Json(serde_json::json!({ "error": e.to_string() })))),
         }
     } else {
         Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))
     }
 }
+
+#[debug_handler]
+pub async fn nearest_handler(
+    opts: Query<TransactionQueryModel>,
+    Path((database_name, table_name)): Path<(String, String)>,
+    State(db): State<ServerState>,
+    Json(body): Json<NearestRequestModel>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+
+    let maybe_database = db.database(&database_name);
+    if let Some(database) = maybe_database {
+        match blocking(move || database.nearest(&table_name, body.nearest, body.approximate, body.filters, body.select, opts.transaction)).await {
+            Ok(rows) => {
+                let rows = rows.into_iter().map(|(id, distance, data)| NearestRowModel { id, distance, data }).collect();
+                Ok((StatusCode::OK, Json(NearestResponseModel { rows })))
+            }
+            Err(e) => Err((table_error_status(&e), Json(serde_json::json!({ "error": e.to_string() })))),
+        }
+    } else {
+        Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))
+    }
+}
+
+#[debug_handler]
+pub async fn transaction_begin_handler(
+    Path(database_name): Path<String>,
+    State(db): State<ServerState>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+
+    let maybe_database = db.database(&database_name);
+    if let Some(database) = maybe_database {
+        let id = blocking(move || database.begin()).await;
+        Ok((StatusCode::CREATED, Json(TransactionResponseModel { id })))
+    } else {
+        Err((StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "Database not found" }))))
+    }
+}
+
+pub async fn transaction_commit_handler(
+    Path((database_name, transaction_id)): Path<(String, TransactionId)>,
+    State(db): State<ServerState>,
+) -> impl IntoResponse {
+
+    let maybe_database = db.database(&database_name);
+    let database = match maybe_database {
+        Some(db) => {db}
+        None => {return StatusCode::NOT_FOUND}
+    };
+    if let Err(e) = blocking(move || database.commit(transaction_id)).await {
+        return table_error_status(&e);
+    }
+    StatusCode::OK
+}
+
+pub async fn transaction_rollback_handler(
+    Path((database_name, transaction_id)): Path<(String, TransactionId)>,
+    State(db): State<ServerState>,
+) -> impl IntoResponse {
+
+    let maybe_database = db.database(&database_name);
+    let database = match maybe_database {
+        Some(db) => {db}
+        None => {return StatusCode::NOT_FOUND}
+    };
+    if let Err(e) = blocking(move || database.rollback(transaction_id)).await {
+        return table_error_status(&e);
+    }
+    StatusCode::OK
+}
+
+fn build_column(request_column: ColumnCreateRequestModel) -> Option<Column> {
+    let column_type = ColumnTypes::from_str(request_column.column_type.as_str()).ok()?;
+    Column::with_options(request_column.name, column_type, request_column.nullable, request_column.default, request_column.vector_index, request_column.references).ok()
+}
+
+async fn alter_table(db: ServerState, database: Arc<Database>, table_name: String, alteration: Alteration, status: StatusCode) -> StatusCode {
+    blocking(move || {
+        if let Err(e) = database.alter_table(&table_name, alteration) {
+            return table_error_status(&e)
+        }
+        if database.keep_schema {
+            save_catalog(&db);
+        }
+        status
+    }).await
+}
+
+// Database calls wait on locks and on the disk, so they run on the blocking threads and leave the
+// async workers to other requests. A panic carries on into the handler.
+async fn blocking<T: Send + 'static>(operation: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(operation).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+// The change is done by the time the catalog is written, so a catalog that fails to save does
+// not fail the request. Every save writes the whole catalog, the next one catches up.
+fn save_catalog(db: &Server) {
+    if let Err(e) = db.save_catalog() {
+        log::error!("Failed to save the catalog: {}", e);
+    }
+}
+
+fn table_error_status(error: &TableError) -> StatusCode {
+    match error {
+        TableError::Data(_) => StatusCode::BAD_REQUEST,
+        TableError::Datastore(e) => datastore_error_status(e),
+        TableError::Transaction(TransactionError::NotFound(_)) => StatusCode::NOT_FOUND,
+        TableError::Transaction(TransactionError::Conflict { .. }) => StatusCode::CONFLICT,
+        TableError::Transaction(TransactionError::TableInUse(_)) => StatusCode::CONFLICT,
+    }
+}
+
+fn datastore_error_status(error: &DatastoreError) -> StatusCode {
+    match error {
+        DatastoreError::InvalidName(_) => StatusCode::BAD_REQUEST,
+        DatastoreError::RecordTooLarge { .. } => StatusCode::BAD_REQUEST,
+        DatastoreError::TableExists(_) => StatusCode::CONFLICT,
+        DatastoreError::TableNotFound(_) => StatusCode::NOT_FOUND,
+        DatastoreError::RowNotFound(_) => StatusCode::NOT_FOUND,
+        DatastoreError::KeyNotFound(_) => StatusCode::NOT_FOUND,
+        DatastoreError::DuplicateKey { .. } => StatusCode::CONFLICT,
+        DatastoreError::ReferenceNotFound { .. } | DatastoreError::Referenced { .. } => StatusCode::CONFLICT,
+        DatastoreError::Io(_) | DatastoreError::Corrupt(_) => {
+            log::error!("Datastore failure: {}", error);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
@@ -1,17 +1,102 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 use serde_json::Value;
-use tokio::sync::Mutex;
+use datablaze_types::enums::DatastoreVariants;
+use crate::config::EngineConfig;
+use crate::database::aggregate::AggregateFunction;
+use crate::database::catalog::Catalog;
 use crate::database::database::Database;
+use crate::database::exceptions::TableError;
+use crate::database::query::{Filter, Projection};
+use crate::database::reference::Reference;
+use crate::database::transaction::TransactionId;
+use crate::database::vector::{Metric, Nearest};
+use crate::datastore::datastore::RowId;
+use crate::datastore::exceptions::DatastoreError;
+use crate::datastore::file_based_datastore::{validate_name, FileBasedDatastore};
 
 #[allow(non_snake_case)]
 #[derive(Default)]
 pub struct Server {
-    pub databases: Vec<Database>,
+    /// Only locked to look up or add a database, every database guards its own rows
+    pub databases: RwLock<Vec<Arc<Database>>>,
+    pub config: EngineConfig,
+    /// Names of the databases being opened for a request to create them, so no other request
+    /// opens the same directory meanwhile
+    pub creating: Mutex<HashSet<String>>,
+    // Keeps two requests from writing the catalog file at the same time
+    catalog_lock: Mutex<()>,
 }
 
-pub type ServerState = Arc<Mutex<Server>>;
+impl Server {
+    /// Start a server with every database in the catalog of the data directory
+    pub fn load(config: EngineConfig) -> Result<Server, TableError> {
+        let mut databases = Vec::new();
+        for entry in Catalog::load(&config.data_dir)?.databases {
+            let cache_pages = split_cache(&databases, 1, &config)?;
+            databases.push(Arc::new(Database::restore(entry, &config, cache_pages)?));
+        }
+        // A database directory missing from the catalog holds a file based database that was
+        // created before the catalog existed, or right before a crash. Other directories are left
+        // alone.
+        let mut recovered = false;
+        if config.data_dir.exists() {
+            for entry in fs::read_dir(&config.data_dir).map_err(DatastoreError::from)? {
+                let path = entry.map_err(DatastoreError::from)?.path();
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                let is_database = validate_name(&name).is_ok() && FileBasedDatastore::exists(&path);
+                if is_database && !databases.iter().any(|database| database.name == name) {
+                    let cache_pages = split_cache(&databases, 1, &config)?;
+                    databases.push(Arc::new(Database::open(name, &config, cache_pages)?));
+                    recovered = true;
+                }
+            }
+        }
+        split_cache(&databases, 0, &config)?;
+        let server = Server { databases: RwLock::new(databases), config, creating: Mutex::new(HashSet::new()), catalog_lock: Mutex::new(()) };
+        if recovered {
+            server.save_catalog()?;
+        }
+        Ok(server)
+    }
+
+    /// Write every database that keeps its schema to the catalog
+    pub fn save_catalog(&self) -> Result<(), DatastoreError> {
+        let _catalog = self.catalog_lock.lock().unwrap();
+        let catalog = Catalog::from_databases(&self.databases.read().unwrap());
+        catalog.save(&self.config.data_dir)
+    }
+
+    pub fn database(&self, name: &str) -> Option<Arc<Database>> {
+        self.databases.read().unwrap().iter().find(|database| database.name == name).cloned()
+    }
+
+    /// Split the cache between the open file based databases and the databases being created.
+    /// Returns the number of pages a database being created may cache.
+    pub fn share_cache(&self) -> Result<usize, TableError> {
+        let databases = self.databases.read().unwrap().clone();
+        let creating = self.creating.lock().unwrap().len();
+        split_cache(&databases, creating, &self.config)
+    }
+}
+
+// Shrink or grow the caches of the open file based databases to their share once the given number
+// of databases opens next to them, and return that share
+fn split_cache(databases: &[Arc<Database>], opening: usize, config: &EngineConfig) -> Result<usize, TableError> {
+    let file_based = databases
+        .iter()
+        .filter(|database| matches!(database.datastore_type(), DatastoreVariants::FileBased))
+        .collect::<Vec<&Arc<Database>>>();
+    let cache_pages = config.cache_pages(file_based.len() + opening);
+    for database in file_based {
+        database.set_cache_pages(cache_pages)?;
+    }
+    Ok(cache_pages)
+}
+
+pub type ServerState = Arc<Server>;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DatabaseGetQueryModel {
@@ -21,34 +106,74 @@ pub struct DatabaseGetQueryModel {
 pub struct DatabaseResponseModel {
     pub name: String,
     pub datastore: String,
+    pub keep_schema: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DatabaseCreateRequestModel {
     pub name: String,
     pub datastore: String,
+    /// Keep an in memory database and its tables across restarts, without their rows. File
+    /// based databases always keep their schema.
+    #[serde(default)]
+    pub keep_schema: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TableGetQueryModel {
     pub name: String,
 }
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct TableCreateRequestModel {
     pub name: String,
     pub columns: Vec<ColumnCreateRequestModel>,
+    /// Columns identifying a row, which can not be nullable
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+    /// Sets of columns no two rows can share the values of, rows with a null in a set excepted
+    #[serde(default)]
+    pub unique: Vec<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct ColumnCreateRequestModel {
     pub name: String,
     pub column_type: String,
+    #[serde(default)]
+    pub nullable: bool,
+    /// Stored for rows that leave the column out. Without a default such rows get null, or are
+    /// rejected when the column is not nullable.
+    #[serde(default)]
+    pub default: Option<Value>,
+    /// Keep an approximate nearest neighbour index with this metric, vector columns only
+    #[serde(default)]
+    pub vector_index: Option<Metric>,
+    /// Hold the primary key of a row of another table, or of this one. The column has to have the
+    /// type of that key.
+    #[serde(default)]
+    pub references: Option<Reference>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ColumnQueryModel {
+    pub name: String,
+}
+
+/// Rename a column, change its type or both
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct ColumnUpdateRequestModel {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub column_type: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TableResponseModel {
     pub name: String,
     pub columns: Vec<ColumnResponseModel>,
+    pub primary_key: Vec<String>,
+    pub unique: Vec<Vec<String>>,
 }
 
 
@@ -56,6 +181,10 @@ pub struct TableResponseModel {
 pub struct ColumnResponseModel {
     pub name: String,
     pub column_type: String,
+    pub nullable: bool,
+    pub default: Option<Value>,
+    pub vector_index: Option<Metric>,
+    pub references: Option<Reference>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -68,7 +197,95 @@ pub struct RowUpdateRequestModel {
     pub data: HashMap<String, Value>
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RowCreateResponseModel {
+    /// Ids of the new rows, in the order they were sent
+    pub ids: Vec<RowId>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RowGetQueryModel {
-    pub index: u64,
+    pub id: RowId,
+    /// Transaction to read or change the row in, none to work on committed rows directly
+    pub transaction: Option<TransactionId>,
+}
+
+/// Primary key of a row, with the data of every key column by name
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RowKeyRequestModel {
+    pub key: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AggregateQueryModel {
+    pub column: String,
+    pub function: AggregateFunction,
+    pub transaction: Option<TransactionId>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AggregateResponseModel {
+    /// Null when there was nothing to aggregate
+    pub value: Value,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QueryRequestModel {
+    /// Rows have to match every filter
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    /// What to return of every row, all columns when left out
+    #[serde(default)]
+    pub select: Vec<Projection>,
+    /// Array column to turn into a row per element
+    #[serde(default)]
+    pub unnest: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QueryRowModel {
+    pub id: RowId,
+    pub data: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QueryResponseModel {
+    pub rows: Vec<QueryRowModel>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NearestRequestModel {
+    #[serde(flatten)]
+    pub nearest: Nearest,
+    /// Search the index on the column instead of every row, which is faster on large tables but
+    /// may miss some of the nearest rows
+    #[serde(default)]
+    pub approximate: bool,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub select: Vec<Projection>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NearestRowModel {
+    pub id: RowId,
+    pub distance: f32,
+    pub data: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NearestResponseModel {
+    /// Nearest first
+    pub rows: Vec<NearestRowModel>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TransactionQueryModel {
+    pub transaction: Option<TransactionId>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TransactionResponseModel {
+    pub id: TransactionId,
 }
@@ -4,16 +4,15 @@ use axum::{
     Router,
 };
 use axum::routing::{delete, patch};
-use tokio::sync::Mutex;
 use crate::network::{
     model::Server,
     handler::*
 };
 use crate::network::handler::table_create_handler;
 
-pub fn create_router() -> Router {
+pub fn create_router(server: Server) -> Router {
 
-    let state = Arc::new(Mutex::new(Server::default()));
+    let state = Arc::new(server);
 
     Router::new()
         .route("/api/health", get(health_checker_handler))
@@ -21,9 +20,19 @@ pub fn create_router() -> Router {
         .route("/api/database", get(database_get_handler))
         .route("/api/database/{database_name}/table", post(table_create_handler))
         .route("/api/database/{database_name}/table", get(table_get_handler))
+        .route("/api/database/{database_name}/table/{table_name}/column", post(column_add_handler))
+        .route("/api/database/{database_name}/table/{table_name}/column", patch(column_update_handler))
+        .route("/api/database/{database_name}/table/{table_name}/column", delete(column_delete_handler))
         .route("/api/database/{database_name}/table/{table_name}/row", post(row_create_handler))
         .route("/api/database/{database_name}/table/{table_name}/row", get(row_get_handler))
         .route("/api/database/{database_name}/table/{table_name}/row", patch(row_update_handler))
         .route("/api/database/{database_name}/table/{table_name}/row", delete(row_delete_handler))
+        .route("/api/database/{database_name}/table/{table_name}/row/key", post(row_get_by_key_handler))
+        .route("/api/database/{database_name}/table/{table_name}/aggregate", get(aggregate_handler))
+        .route("/api/database/{database_name}/table/{table_name}/query", post(query_handler))
+        .route("/api/database/{database_name}/table/{table_name}/nearest", post(nearest_handler))
+        .route("/api/database/{database_name}/transaction", post(transaction_begin_handler))
+        .route("/api/database/{database_name}/transaction/{transaction_id}/commit", post(transaction_commit_handler))
+        .route("/api/database/{database_name}/transaction/{transaction_id}/rollback", post(transaction_rollback_handler))
         .with_state(state)
 }
\ No newline at end of file
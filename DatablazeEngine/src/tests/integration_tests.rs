@@ -2,22 +2,38 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::io::Write;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
     use axum::{http::StatusCode};
-    use serde_json::Value;
+    use axum::body::Body;
+    use axum::routing::RouterIntoService;
+    use serde_json::{json, Value};
+    use crate::config::EngineConfig;
+    use datablaze_btree::node::{BTREE_MAX_KEY_SIZE, BTREE_PAGE_SIZE};
+    use datablaze_types::enums::ColumnTypes;
+    use crate::database::column::Column;
+    use crate::database::database::Database;
+    use crate::database::key::Keys;
+    use crate::database::reference::{OnDelete, Reference};
+    use crate::database::table::Table;
+    use crate::DatastoreVariants;
     use crate::network::model::*;
     use crate::network::route::create_router;
     use crate::tests::support::*;
 
     #[tokio::test]
     async fn create_database() {
-        let mut app = create_router().into_service();
+        let mut app = create_router(Server::default()).into_service();
 
         let db_name = "testDB";
         let db_datastore = "in_memory";
 
         let body = DatabaseCreateRequestModel {
             name: db_name.to_string(),
-            datastore: "in_memory".to_string()
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
         };
         let response = request_create_database(body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
@@ -32,7 +48,7 @@ mod tests {
 
     #[tokio::test]
     async fn create_table() {
-        let mut app = create_router().into_service();
+        let mut app = create_router(Server::default()).into_service();
 
         let db_name = "testDB";
         let table_name = "testTable";
@@ -41,7 +57,8 @@ mod tests {
 
         let body = DatabaseCreateRequestModel {
             name: db_name.to_string(),
-            datastore: "in_memory".to_string()
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
         };
         let response = request_create_database(body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
@@ -52,8 +69,10 @@ mod tests {
                 ColumnCreateRequestModel {
                     name: column_name.to_string(),
                     column_type: column_type.to_string(),
+                    ..Default::default()
                 }
             ],
+            ..Default::default()
         };
         let response = request_create_table(db_name.to_string(), body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
@@ -70,7 +89,7 @@ mod tests {
 
     #[tokio::test]
     async fn create_row() {
-        let mut app = create_router().into_service();
+        let mut app = create_router(Server::default()).into_service();
 
         let db_name = "testDB";
         let table_name = "testTable";
@@ -83,7 +102,8 @@ mod tests {
 
         let body = DatabaseCreateRequestModel {
             name: db_name.to_string(),
-            datastore: "in_memory".to_string()
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
         };
         let response = request_create_database(body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
@@ -94,12 +114,15 @@ mod tests {
                 ColumnCreateRequestModel {
                     name: column_name_one.to_string(),
                     column_type: column_type_one.to_string(),
+                    ..Default::default()
                 },
                 ColumnCreateRequestModel {
                     name: column_name_two.to_string(),
                     column_type: column_type_two.to_string(),
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
         let response = request_create_table(db_name.to_string(), body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
@@ -110,10 +133,12 @@ mod tests {
                 (column_name_two.to_string(), Value::Number(column_value_two.into())),
             ]))
         };
-        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, &mut app).await;
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+        assert_eq!(ids.len(), 1);
 
-        let response = request_get_row(db_name.to_string(), table_name, 0, &mut app).await;
+        let response = request_get_row(db_name.to_string(), table_name, ids[0], None, &mut app).await;
         assert_eq!(response.status(), StatusCode::OK, "Failed to get row");
 
         let parsed = parse_body::<HashMap<String, Value>>(response).await;
@@ -135,10 +160,11 @@ mod tests {
 
         let body = DatabaseCreateRequestModel {
             name: db_name.to_string(),
-            datastore: "in_memory".to_string()
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
         };
 
-        let mut app = create_router().into_service();
+        let mut app = create_router(Server::default()).into_service();
         let response = request_create_database(body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
 
@@ -148,8 +174,10 @@ mod tests {
                 ColumnCreateRequestModel {
                     name: column_name_one.to_string(),
                     column_type: column_type_one.to_string(),
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
         let response = request_create_table(db_name.to_string(), body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
@@ -167,10 +195,11 @@ mod tests {
                 ])
             )
         };
-        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, &mut app).await;
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
 
-        let response = request_get_row(db_name.to_string(), table_name, 1, &mut app).await;
+        let response = request_get_row(db_name.to_string(), table_name, ids[1], None, &mut app).await;
         assert_eq!(response.status(), StatusCode::OK, "Failed to get row");
 
         let parsed = parse_body::<HashMap<String, Value>>(response).await;
@@ -181,10 +210,10 @@ mod tests {
                     (column_name_one.to_string(), Value::String(column_value_two_updated.to_string()))])
         };
 
-        let response = request_update_row(db_name.to_string(), table_name.to_string(), 1, body, &mut app).await;
+        let response = request_update_row(db_name.to_string(), table_name.to_string(), ids[1], body, None, &mut app).await;
         assert_eq!(response.status(), StatusCode::ACCEPTED, "Failed to update row");
 
-        let response = request_get_row(db_name.to_string(), table_name, 1, &mut app).await;
+        let response = request_get_row(db_name.to_string(), table_name, ids[1], None, &mut app).await;
         assert_eq!(response.status(), StatusCode::OK, "Failed to get row");
         
         let parsed = parse_body::<HashMap<String, Value>>(response).await;
@@ -204,10 +233,11 @@ mod tests {
 
         let body = DatabaseCreateRequestModel {
             name: db_name.to_string(),
-            datastore: "in_memory".to_string()
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
         };
 
-        let mut app = create_router().into_service();
+        let mut app = create_router(Server::default()).into_service();
         let response = request_create_database(body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
 
@@ -217,8 +247,10 @@ mod tests {
                 ColumnCreateRequestModel {
                     name: column_name_one.to_string(),
                     column_type: column_type_one.to_string(),
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
         let response = request_create_table(db_name.to_string(), body, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
@@ -236,22 +268,2050 @@ mod tests {
                 ])
             )
         };
-        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, &mut app).await;
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
         assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
 
-        let response = request_get_row(db_name.to_string(), table_name, 1, &mut app).await;
+        let response = request_get_row(db_name.to_string(), table_name, ids[1], None, &mut app).await;
         assert_eq!(response.status(), StatusCode::OK, "Failed to get row");
 
         let parsed = parse_body::<HashMap<String, Value>>(response).await;
         assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), column_value_two);
 
-        let response = request_delete_row(db_name.to_string(), table_name.to_string(), 1, &mut app).await;
-        assert_eq!(response.status(), StatusCode::ACCEPTED, "Failed to update row");
+        let response = request_delete_row(db_name.to_string(), table_name.to_string(), ids[1], None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED, "Failed to delete row");
+
+        let response = request_get_row(db_name.to_string(), table_name, ids[1], None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
 
-        let response = request_get_row(db_name.to_string(), table_name, 1, &mut app).await;
+        // Deleting a row leaves the ids of the other rows alone
+        let response = request_get_row(db_name.to_string(), table_name, ids[2], None, &mut app).await;
         assert_eq!(response.status(), StatusCode::OK, "Failed to get row");
 
         let parsed = parse_body::<HashMap<String, Value>>(response).await;
         assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), column_value_three);
+
+        // Ids are never handed out again
+        let body = RowCreateRequestModel {
+            data: vec!(HashMap::from([(column_name_one.to_string(), Value::String(column_value_two.to_string()))]))
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        let new_ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+        assert!(!ids.contains(&new_ids[0]));
+    }
+
+    #[tokio::test]
+    async fn file_based_database_survives_restart() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let db_datastore = "file_based";
+        let table_name = "testTable";
+        let column_name_one = "testColumnOne";
+        let column_name_two = "testColumnTwo";
+
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: db_datastore.to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel {
+                    name: column_name_one.to_string(),
+                    column_type: "string".to_string(),
+                    ..Default::default()
+                },
+                ColumnCreateRequestModel {
+                    name: column_name_two.to_string(),
+                    column_type: "int".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowCreateRequestModel {
+            data: (0..3u64).map(|i| HashMap::from([
+                (column_name_one.to_string(), Value::String(format!("test_{}", i))),
+                (column_name_two.to_string(), Value::Number(i.into())),
+            ])).collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowUpdateRequestModel {
+            data: HashMap::from([
+                (column_name_one.to_string(), Value::String("test_0_updated".to_string())),
+                (column_name_two.to_string(), Value::Number(10.into())),
+            ])
+        };
+        let response = request_update_row(db_name.to_string(), table_name.to_string(), 0, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = request_delete_row(db_name.to_string(), table_name.to_string(), 1, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        // Restart the engine on the same data directory
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+
+        let response = request_get_database(db_name, &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK, "Failed to get database");
+        let parsed = parse_body::<DatabaseResponseModel>(response).await;
+        assert_eq!(parsed.datastore, db_datastore);
+
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        let parsed = parse_body::<TableResponseModel>(response).await;
+        assert_eq!(parsed.columns.iter().map(|column| column.name.as_str()).collect::<Vec<_>>(), vec![column_name_one, column_name_two]);
+
+        let response = request_get_row(db_name.to_string(), table_name, 0, None, &mut app).await;
+        let parsed = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), "test_0_updated");
+        assert_eq!(parsed.get(column_name_two).unwrap().as_u64().unwrap(), 10);
+
+        let response = request_get_row(db_name.to_string(), table_name, 1, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = request_get_row(db_name.to_string(), table_name, 2, None, &mut app).await;
+        let parsed = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), "test_2");
+    }
+
+    #[tokio::test]
+    async fn file_based_database_recovers_from_crash() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "testTable";
+        let column_name_one = "testColumnOne";
+
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel {
+                    name: column_name_one.to_string(),
+                    column_type: "string".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowCreateRequestModel {
+            data: (0..3).map(|i| HashMap::from([
+                (column_name_one.to_string(), Value::String(format!("test_{}", i))),
+            ])).collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowUpdateRequestModel {
+            data: HashMap::from([(column_name_one.to_string(), Value::String("test_2_updated".to_string()))])
+        };
+        let response = request_update_row(db_name.to_string(), table_name.to_string(), 2, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = request_delete_row(db_name.to_string(), table_name.to_string(), 0, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        // Crash without a checkpoint: the tree file has pages of a commit that never finished and
+        // the log has a torn tail
+        std::mem::forget(app);
+        let database_dir = data_dir.path().join(db_name);
+        let mut tree_file = std::fs::OpenOptions::new().append(true).open(database_dir.join("data.db")).unwrap();
+        tree_file.write_all(&[7; BTREE_PAGE_SIZE * 2]).unwrap();
+        let mut log_file = std::fs::OpenOptions::new().append(true).open(database_dir.join("wal.log")).unwrap();
+        log_file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+
+        let response = request_get_row(db_name.to_string(), table_name, 0, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = request_get_row(db_name.to_string(), table_name, 1, None, &mut app).await;
+        let parsed = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), "test_1");
+
+        let response = request_get_row(db_name.to_string(), table_name, 2, None, &mut app).await;
+        let parsed = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), "test_2_updated");
+    }
+
+    #[tokio::test]
+    async fn file_based_database_works_with_small_cache() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        // A cache of a few pages, far smaller than the table
+        let mut config = EngineConfig::new(data_dir.path().to_path_buf());
+        config.cache_size = 4 * BTREE_PAGE_SIZE;
+        let db_name = "testDB";
+        let table_name = "testTable";
+        let column_name_one = "testColumnOne";
+
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel {
+                    name: column_name_one.to_string(),
+                    column_type: "string".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowCreateRequestModel {
+            data: (0..500).map(|i| HashMap::from([
+                (column_name_one.to_string(), Value::String(format!("test_{}_{}", i, "x".repeat(100)))),
+            ])).collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+
+        for i in [0, 250, 499] {
+            let response = request_get_row(db_name.to_string(), table_name, i, None, &mut app).await;
+            let parsed = parse_body::<HashMap<String, Value>>(response).await;
+            assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), format!("test_{}_{}", i, "x".repeat(100)));
+        }
+    }
+
+    #[tokio::test]
+    async fn file_based_database_stores_large_values() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "testTable";
+        let column_name_one = "testColumnOne";
+        let document = "A document far larger than a page. ".repeat(2000);
+
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel {
+                    name: column_name_one.to_string(),
+                    column_type: "string".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowCreateRequestModel {
+            data: vec![HashMap::from([(column_name_one.to_string(), Value::String(document.clone()))])]
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+
+        let response = request_get_row(db_name.to_string(), table_name, 0, None, &mut app).await;
+        let parsed = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), document);
+    }
+
+    #[tokio::test]
+    async fn catalog_survives_restart() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let table_name = "testTable";
+        let columns = ["testColumnOne", "testColumnTwo", "testColumnThree"];
+
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        for (db_name, datastore, keep_schema) in [
+            ("fileDB", "file_based", false),
+            ("keptDB", "in_memory", true),
+            ("forgottenDB", "in_memory", false),
+        ] {
+            let body = DatabaseCreateRequestModel {
+                name: db_name.to_string(),
+                datastore: datastore.to_string(),
+                keep_schema,
+            };
+            let response = request_create_database(body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            let body = TableCreateRequestModel {
+                name: table_name.to_string(),
+                columns: columns.iter().enumerate().map(|(i, column)| ColumnCreateRequestModel {
+                    name: column.to_string(),
+                    column_type: if i % 2 == 0 { "string" } else { "int" }.to_string(),
+                    ..Default::default()
+                }).collect(),
+                ..Default::default()
+            };
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            let body = RowCreateRequestModel {
+                data: vec![HashMap::from([
+                    (columns[0].to_string(), Value::String("test".to_string())),
+                    (columns[1].to_string(), Value::Number(1.into())),
+                    (columns[2].to_string(), Value::String("test".to_string())),
+                ])]
+            };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        drop(app);
+        // Directories that do not hold a database are not mistaken for one
+        for stray in [".git", "lost+found", "strayDB"] {
+            std::fs::create_dir(data_dir.path().join(stray)).unwrap();
+        }
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        for stray in [".git", "lost+found", "strayDB"] {
+            assert_eq!(std::fs::read_dir(data_dir.path().join(stray)).unwrap().count(), 0);
+        }
+        let response = request_get_database("strayDB", &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        for (db_name, datastore) in [("fileDB", "file_based"), ("keptDB", "in_memory")] {
+            let response = request_get_database(db_name, &mut app).await;
+            assert_eq!(response.status(), StatusCode::OK, "Failed to get database {}", db_name);
+            let parsed = parse_body::<DatabaseResponseModel>(response).await;
+            assert_eq!(parsed.datastore, datastore);
+            assert!(parsed.keep_schema);
+
+            let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+            let parsed = parse_body::<TableResponseModel>(response).await;
+            assert_eq!(parsed.columns.iter().map(|column| column.name.as_str()).collect::<Vec<_>>(), columns);
+            assert_eq!(parsed.columns.iter().map(|column| column.column_type.as_str()).collect::<Vec<_>>(), ["string", "int", "string"]);
+        }
+
+        // In memory databases only keep their schema
+        let response = request_get_row("keptDB".to_string(), table_name, 0, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = request_get_row("fileDB".to_string(), table_name, 0, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = request_get_database("forgottenDB", &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn batch_insert_is_all_or_nothing() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let table_name = "testTable";
+        let column_name_one = "testColumnOne";
+        let column_name_two = "testColumnTwo";
+        let row = |one: Value, two: Value| HashMap::from([(column_name_one.to_string(), one), (column_name_two.to_string(), two)]);
+
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+
+        for (db_name, datastore) in [("memoryDB", "in_memory"), ("fileDB", "file_based")] {
+            let body = DatabaseCreateRequestModel {
+                name: db_name.to_string(),
+                datastore: datastore.to_string(),
+                keep_schema: false,
+            };
+            let response = request_create_database(body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            let body = TableCreateRequestModel {
+                name: table_name.to_string(),
+                columns: vec![
+                    ColumnCreateRequestModel {
+                        name: column_name_one.to_string(),
+                        column_type: "string".to_string(),
+                        ..Default::default()
+                    },
+                    ColumnCreateRequestModel {
+                        name: column_name_two.to_string(),
+                        column_type: "int".to_string(),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            };
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            // The second row has a bad value in its last column
+            let body = RowCreateRequestModel {
+                data: vec![
+                    row(Value::String("test_0".to_string()), Value::Number(0.into())),
+                    row(Value::String("test_1".to_string()), Value::String("not a number".to_string())),
+                    row(Value::String("test_2".to_string()), Value::Number(2.into())),
+                ]
+            };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+            let response = request_get_row(db_name.to_string(), table_name, 0, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "Row of a failed batch was stored in {}", db_name);
+
+            let body = RowCreateRequestModel {
+                data: (0..3u64).map(|i| row(Value::String(format!("test_{}", i)), Value::Number(i.into()))).collect()
+            };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+            assert_eq!(ids.len(), 3);
+
+            for (i, id) in ids.into_iter().enumerate() {
+                let response = request_get_row(db_name.to_string(), table_name, id, None, &mut app).await;
+                let parsed = parse_body::<HashMap<String, Value>>(response).await;
+                assert_eq!(parsed.get(column_name_one).unwrap().as_str().unwrap(), format!("test_{}", i));
+                assert_eq!(parsed.get(column_name_two).unwrap().as_u64().unwrap(), i as u64);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn torn_batch_is_not_replayed() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "testTable";
+        let column_name_one = "testColumnOne";
+
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel {
+                    name: column_name_one.to_string(),
+                    column_type: "string".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowCreateRequestModel {
+            data: (0..3).map(|i| HashMap::from([
+                (column_name_one.to_string(), Value::String(format!("test_{}", i))),
+            ])).collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Crash halfway through writing the batch to the log
+        std::mem::forget(app);
+        let log_path = data_dir.path().join(db_name).join("wal.log");
+        let log_size = std::fs::metadata(&log_path).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&log_path).unwrap().set_len(log_size - 10).unwrap();
+
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        for id in 0..3 {
+            let response = request_get_row(db_name.to_string(), table_name, id, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupt_log_entry_fails_the_start() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "testTable";
+        let column_name_one = "testColumnOne";
+
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel {
+                    name: column_name_one.to_string(),
+                    column_type: "string".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        for i in 0..2 {
+            let body = RowCreateRequestModel {
+                data: vec![HashMap::from([(column_name_one.to_string(), Value::String(format!("test_{}", i)))])]
+            };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        // Crash, and find the first of the two entries damaged, in its records or in its length.
+        // The entry after it was acknowledged, so the log is not cut back to the damage.
+        std::mem::forget(app);
+        let log_path = data_dir.path().join(db_name).join("wal.log");
+        let log = std::fs::read(&log_path).unwrap();
+        for position in [12, 3] {
+            let mut damaged = log.clone();
+            damaged[position] ^= 0xff;
+            std::fs::write(&log_path, &damaged).unwrap();
+
+            assert!(Server::load(config.clone()).is_err());
+            assert_eq!(std::fs::read(&log_path).unwrap(), damaged);
+        }
+    }
+
+    async fn create_accounts(db_name: &str, datastore: &str, app: &mut RouterIntoService<Body>) -> Vec<u64> {
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: datastore.to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: "accounts".to_string(),
+            columns: vec![
+                ColumnCreateRequestModel { name: "owner".to_string(), column_type: "string".to_string(), ..Default::default() },
+                ColumnCreateRequestModel { name: "balance".to_string(), column_type: "int".to_string(), ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = RowCreateRequestModel {
+            data: ["alice", "bob"].iter().map(|owner| HashMap::from([
+                ("owner".to_string(), Value::String(owner.to_string())),
+                ("balance".to_string(), Value::Number(100.into())),
+            ])).collect()
+        };
+        let response = request_create_row(db_name.to_string(), "accounts".to_string(), body, None, app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        parse_body::<RowCreateResponseModel>(response).await.ids
+    }
+
+    async fn balance(db_name: &str, id: u64, transaction: Option<u64>, app: &mut RouterIntoService<Body>) -> u64 {
+        let response = request_get_row(db_name.to_string(), "accounts", id, transaction, app).await;
+        assert_eq!(response.status(), StatusCode::OK, "Failed to get account {}", id);
+        parse_body::<HashMap<String, Value>>(response).await.get("balance").unwrap().as_u64().unwrap()
+    }
+
+    fn set_balance(owner: &str, balance: u64) -> RowUpdateRequestModel {
+        RowUpdateRequestModel {
+            data: HashMap::from([
+                ("owner".to_string(), Value::String(owner.to_string())),
+                ("balance".to_string(), Value::Number(balance.into())),
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn transaction_commits_changes_as_a_unit() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+        let ids = create_accounts(db_name, "file_based", &mut app).await;
+
+        let response = request_begin_transaction(db_name, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+
+        // Move 30 from alice to bob
+        let alice = balance(db_name, ids[0], Some(transaction), &mut app).await;
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", alice - 30), Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let bob = balance(db_name, ids[1], Some(transaction), &mut app).await;
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[1], set_balance("bob", bob + 30), Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = RowCreateRequestModel {
+            data: vec![HashMap::from([
+                ("owner".to_string(), Value::String("carol".to_string())),
+                ("balance".to_string(), Value::Number(0.into())),
+            ])]
+        };
+        let response = request_create_row(db_name.to_string(), "accounts".to_string(), body, Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let carol = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+
+        // The transaction sees its own changes, everyone else only sees committed rows
+        assert_eq!(balance(db_name, ids[0], Some(transaction), &mut app).await, 70);
+        assert_eq!(balance(db_name, ids[0], None, &mut app).await, 100);
+        assert_eq!(balance(db_name, ids[1], None, &mut app).await, 100);
+        let response = request_get_row(db_name.to_string(), "accounts", carol, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = request_end_transaction(db_name, transaction, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = request_end_transaction(db_name, transaction, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        assert_eq!(balance(db_name, ids[0], None, &mut app).await, 70);
+        assert_eq!(balance(db_name, ids[1], None, &mut app).await, 130);
+        assert_eq!(balance(db_name, carol, None, &mut app).await, 0);
+    }
+
+    #[tokio::test]
+    async fn transaction_rolls_back_and_locks_rows() {
+
+        let db_name = "testDB";
+        let mut app = create_router(Server::default()).into_service();
+        let ids = create_accounts(db_name, "in_memory", &mut app).await;
+
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let first = parse_body::<TransactionResponseModel>(response).await.id;
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let second = parse_body::<TransactionResponseModel>(response).await.id;
+        assert_ne!(first, second);
+
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", 0), Some(first), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_delete_row(db_name.to_string(), "accounts".to_string(), ids[1], Some(first), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        // Rows changed by the first transaction are locked for other writers until it ends, readers
+        // still see the committed rows
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", 1), Some(second), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(balance(db_name, ids[1], Some(second), &mut app).await, 100);
+        let response = request_delete_row(db_name.to_string(), "accounts".to_string(), ids[0], None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(balance(db_name, ids[0], None, &mut app).await, 100);
+
+        let response = request_end_transaction(db_name, first, "rollback", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(balance(db_name, ids[0], None, &mut app).await, 100);
+        assert_eq!(balance(db_name, ids[1], None, &mut app).await, 100);
+
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", 1), Some(second), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_end_transaction(db_name, second, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(balance(db_name, ids[0], None, &mut app).await, 1);
+
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", 2), Some(first), &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn transaction_reads_from_its_snapshot() {
+
+        let db_name = "testDB";
+        let mut app = create_router(Server::default()).into_service();
+        let ids = create_accounts(db_name, "in_memory", &mut app).await;
+
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let reader = parse_body::<TransactionResponseModel>(response).await.id;
+        assert_eq!(balance(db_name, ids[0], Some(reader), &mut app).await, 100);
+
+        // Changes committed after the transaction began do not show up in it
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", 50), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_delete_row(db_name.to_string(), "accounts".to_string(), ids[1], None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = RowCreateRequestModel {
+            data: vec![HashMap::from([
+                ("owner".to_string(), Value::String("carol".to_string())),
+                ("balance".to_string(), Value::Number(100.into())),
+            ])]
+        };
+        let response = request_create_row(db_name.to_string(), "accounts".to_string(), body, None, &mut app).await;
+        let carol = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+
+        assert_eq!(balance(db_name, ids[0], Some(reader), &mut app).await, 100);
+        assert_eq!(balance(db_name, ids[1], Some(reader), &mut app).await, 100);
+        let response = request_get_row(db_name.to_string(), "accounts", carol, Some(reader), &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(balance(db_name, ids[0], None, &mut app).await, 50);
+
+        // Writing a row that changed since the snapshot would lose that change
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", 150), Some(reader), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), carol, set_balance("carol", 0), Some(reader), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = request_end_transaction(db_name, reader, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(balance(db_name, ids[0], None, &mut app).await, 50);
+
+        // A new transaction sees everything committed before it
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let writer = parse_body::<TransactionResponseModel>(response).await.id;
+        assert_eq!(balance(db_name, carol, Some(writer), &mut app).await, 100);
+        let response = request_update_row(db_name.to_string(), "accounts".to_string(), ids[0], set_balance("alice", 150), Some(writer), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn requests_on_separate_tables_run_side_by_side() {
+
+        let db_name = "testDB";
+        let router = create_router(Server::default());
+        let mut app = router.clone().into_service();
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let mut tasks = Vec::new();
+        for i in 0..4 {
+            let mut app = router.clone().into_service();
+            tasks.push(tokio::spawn(async move {
+                let table_name = format!("accounts_{}", i);
+                let body = TableCreateRequestModel {
+                    name: table_name.clone(),
+                    columns: vec![
+                        ColumnCreateRequestModel { name: "owner".to_string(), column_type: "string".to_string(), ..Default::default() },
+                        ColumnCreateRequestModel { name: "balance".to_string(), column_type: "int".to_string(), ..Default::default() },
+                    ],
+                    ..Default::default()
+                };
+                let response = request_create_table(db_name.to_string(), body, &mut app).await;
+                assert_eq!(response.status(), StatusCode::CREATED);
+                let body = RowCreateRequestModel { data: vec![set_balance("alice", 0).data] };
+                let response = request_create_row(db_name.to_string(), table_name.clone(), body, None, &mut app).await;
+                let id = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+
+                for n in 1..=50 {
+                    let response = request_begin_transaction(db_name, &mut app).await;
+                    let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+                    let response = request_update_row(db_name.to_string(), table_name.clone(), id, set_balance("alice", n), Some(transaction), &mut app).await;
+                    assert_eq!(response.status(), StatusCode::ACCEPTED);
+                    let response = request_end_transaction(db_name, transaction, "commit", &mut app).await;
+                    assert_eq!(response.status(), StatusCode::OK);
+
+                    let response = request_get_row(db_name.to_string(), &table_name, id, None, &mut app).await;
+                    let row = parse_body::<HashMap<String, Value>>(response).await;
+                    assert_eq!(row.get("balance").unwrap().as_u64().unwrap(), n);
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn every_column_type_round_trips() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "everything";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let columns = [
+            ("signed_int", "signed_int"),
+            ("float", "float"),
+            ("bool", "bool"),
+            ("timestamp", "timestamp"),
+            ("date", "date"),
+            ("uuid", "uuid"),
+            ("bytes", "bytes"),
+        ];
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: columns
+                .iter()
+                .map(|(name, column_type)| ColumnCreateRequestModel { name: name.to_string(), column_type: column_type.to_string(), ..Default::default() })
+                .collect(),
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let row = serde_json::json!({
+            "signed_int": -42,
+            "float": 2.5,
+            "bool": true,
+            "timestamp": "2025-03-01T12:30:00.25+01:00",
+            "date": "2025-03-01",
+            "uuid": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+            "bytes": "AAEC/w==",
+        });
+        let body = RowCreateRequestModel { data: vec![serde_json::from_value(row.clone()).unwrap()] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let id = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+
+        // Timestamps come back in UTC
+        let mut expected = row.clone();
+        expected["timestamp"] = Value::String("2025-03-01T11:30:00.250Z".to_string());
+        let response = request_get_row(db_name.to_string(), table_name, id, None, &mut app).await;
+        assert_eq!(serde_json::to_value(parse_body::<HashMap<String, Value>>(response).await).unwrap(), expected);
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_row(db_name.to_string(), table_name, id, None, &mut app).await;
+        assert_eq!(serde_json::to_value(parse_body::<HashMap<String, Value>>(response).await).unwrap(), expected);
+
+        for (column, value) in [
+            ("signed_int", serde_json::json!(1.5)),
+            ("bool", serde_json::json!("true")),
+            ("timestamp", serde_json::json!("2025-03-01 12:30:00")),
+            ("date", serde_json::json!("2025-02-30")),
+            ("uuid", serde_json::json!("not-a-uuid")),
+            ("bytes", serde_json::json!("not base64!")),
+        ] {
+            let mut invalid = row.clone();
+            invalid[column] = value;
+            let body = RowCreateRequestModel { data: vec![serde_json::from_value(invalid).unwrap()] };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted invalid {}", column);
+        }
+    }
+
+    #[tokio::test]
+    async fn nullable_columns_and_defaults() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "people";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let columns = vec![
+            ColumnCreateRequestModel { name: "name".to_string(), column_type: "string".to_string(), ..Default::default() },
+            ColumnCreateRequestModel { name: "nickname".to_string(), column_type: "string".to_string(), nullable: true, ..Default::default() },
+            ColumnCreateRequestModel { name: "score".to_string(), column_type: "int".to_string(), default: Some(serde_json::json!(10)), ..Default::default() },
+            ColumnCreateRequestModel { name: "active".to_string(), column_type: "bool".to_string(), nullable: true, default: Some(Value::Bool(true)), ..Default::default() },
+        ];
+        let mut invalid = columns.clone();
+        invalid[2].default = Some(Value::String("ten".to_string()));
+        let body = TableCreateRequestModel { name: table_name.to_string(), columns: invalid, ..Default::default() };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = TableCreateRequestModel { name: table_name.to_string(), columns, ..Default::default() };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let rows = [
+            serde_json::json!({ "name": "ann" }),
+            serde_json::json!({ "name": "bob", "nickname": "bobby", "score": 3, "active": null }),
+        ];
+        let body = RowCreateRequestModel { data: rows.iter().map(|row| serde_json::from_value(row.clone()).unwrap()).collect() };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        // Columns that are not nullable need a value or a default
+        for row in [serde_json::json!({ "score": 1 }), serde_json::json!({ "name": null }), serde_json::json!({ "name": "cid", "score": null })] {
+            let body = RowCreateRequestModel { data: vec![serde_json::from_value(row).unwrap()] };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let expected = [
+            serde_json::json!({ "name": "ann", "nickname": null, "score": 10, "active": true }),
+            serde_json::json!({ "name": "bob", "nickname": "bobby", "score": 3, "active": null }),
+        ];
+        for (id, expected) in ids.into_iter().zip(expected) {
+            let response = request_get_row(db_name.to_string(), table_name, id, None, &mut app).await;
+            assert_eq!(serde_json::to_value(parse_body::<HashMap<String, Value>>(response).await).unwrap(), expected);
+        }
+
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        let table = parse_body::<TableResponseModel>(response).await;
+        assert!(!table.columns[0].nullable);
+        assert!(table.columns[1].nullable);
+        assert_eq!(table.columns[2].default, Some(serde_json::json!(10)));
+        assert_eq!(table.columns[3].default, Some(Value::Bool(true)));
+    }
+
+    #[tokio::test]
+    async fn update_keeps_columns_left_out() {
+
+        let db_name = "testDB";
+        let table_name = "people";
+        let mut app = create_router(Server::default()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let columns = vec![
+            ColumnCreateRequestModel { name: "name".to_string(), column_type: "string".to_string(), ..Default::default() },
+            ColumnCreateRequestModel { name: "nickname".to_string(), column_type: "string".to_string(), nullable: true, ..Default::default() },
+            ColumnCreateRequestModel { name: "score".to_string(), column_type: "int".to_string(), default: Some(serde_json::json!(10)), ..Default::default() },
+            ColumnCreateRequestModel { name: "active".to_string(), column_type: "bool".to_string(), nullable: true, default: Some(Value::Bool(true)), ..Default::default() },
+        ];
+        let body = TableCreateRequestModel { name: table_name.to_string(), columns, ..Default::default() };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let row = serde_json::json!({ "name": "bob", "nickname": "bobby", "score": 3, "active": null });
+        let body = RowCreateRequestModel { data: vec![serde_json::from_value(row).unwrap()] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let id = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+
+        // Neither defaults nor nulls are filled in for the columns left out
+        let body = RowUpdateRequestModel { data: serde_json::from_value(serde_json::json!({ "score": 4 })).unwrap() };
+        let response = request_update_row(db_name.to_string(), table_name.to_string(), id, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = RowUpdateRequestModel { data: serde_json::from_value(serde_json::json!({ "nickname": null })).unwrap() };
+        let response = request_update_row(db_name.to_string(), table_name.to_string(), id, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        for row in [serde_json::json!({ "name": null }), serde_json::json!({ "unknown": 1 })] {
+            let body = RowUpdateRequestModel { data: serde_json::from_value(row).unwrap() };
+            let response = request_update_row(db_name.to_string(), table_name.to_string(), id, body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let response = request_get_row(db_name.to_string(), table_name, id, None, &mut app).await;
+        let expected = serde_json::json!({ "name": "bob", "nickname": null, "score": 4, "active": null });
+        assert_eq!(serde_json::to_value(parse_body::<HashMap<String, Value>>(response).await).unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn decimals_are_exact() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "prices";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        for column_type in ["decimal(0,0)", "decimal(29,2)", "decimal(4,5)", "decimal(10)"] {
+            let body = TableCreateRequestModel {
+                name: table_name.to_string(),
+                columns: vec![ColumnCreateRequestModel { name: "price".to_string(), column_type: column_type.to_string(), ..Default::default() }],
+                ..Default::default()
+            };
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", column_type);
+        }
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel { name: "price".to_string(), column_type: "decimal(10,2)".to_string(), nullable: true, ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let prices = [Value::from("19.99"), Value::from("0.1"), Value::from("0.200"), Value::from("-12345678.5"), Value::Null];
+        let body = RowCreateRequestModel {
+            data: prices.iter().map(|price| HashMap::from([("price".to_string(), price.clone())])).collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        // Too many digits after the point, too many before it, or a JSON number that may have
+        // lost precision already
+        for price in [Value::from("1.999"), Value::from("123456789"), Value::from(1.5), Value::from("1e3")] {
+            let body = RowCreateRequestModel { data: vec![HashMap::from([("price".to_string(), price.clone())])] };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", price);
+        }
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_row(db_name.to_string(), table_name, ids[1], None, &mut app).await;
+        assert_eq!(parse_body::<HashMap<String, Value>>(response).await.get("price"), Some(&Value::from("0.10")));
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        assert_eq!(parse_body::<TableResponseModel>(response).await.columns[0].column_type, "decimal(10,2)");
+
+        for (function, expected) in [("sum", "-12345658.21"), ("min", "-12345678.50"), ("max", "19.99")] {
+            let response = request_aggregate(db_name, table_name, "price", function, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(parse_body::<AggregateResponseModel>(response).await.value, Value::from(expected), "Wrong {}", function);
+        }
+        let response = request_aggregate(db_name, table_name, "price", "count", None, &mut app).await;
+        assert_eq!(parse_body::<AggregateResponseModel>(response).await.value, Value::from(4));
+
+        // Aggregates in a transaction see its own changes on top of its snapshot
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+        let response = request_delete_row(db_name.to_string(), table_name.to_string(), ids[3], Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = RowCreateRequestModel { data: vec![HashMap::from([("price".to_string(), Value::from("0.01"))])] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = request_aggregate(db_name, table_name, "price", "sum", Some(transaction), &mut app).await;
+        assert_eq!(parse_body::<AggregateResponseModel>(response).await.value, Value::from("20.29"));
+        let response = request_aggregate(db_name, table_name, "price", "sum", None, &mut app).await;
+        assert_eq!(parse_body::<AggregateResponseModel>(response).await.value, Value::from("-12345658.20"));
+
+        let response = request_aggregate(db_name, table_name, "missing", "sum", None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn json_documents_can_be_queried() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "customers";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel { name: "name".to_string(), column_type: "string".to_string(), ..Default::default() },
+                ColumnCreateRequestModel { name: "payload".to_string(), column_type: "json".to_string(), nullable: true, ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let customers = [
+            ("Anna", json!({ "address": { "city": "Paris", "zip": "75001" }, "orders": 3, "tags": ["vip"] })),
+            ("Bram", json!({ "address": { "city": "Utrecht" }, "orders": 12 })),
+            ("Chen", json!({ "orders": 7.5 })),
+            ("Dana", Value::Null),
+        ];
+        let body = RowCreateRequestModel {
+            data: customers
+                .iter()
+                .map(|(name, payload)| HashMap::from([("name".to_string(), Value::from(*name)), ("payload".to_string(), payload.clone())]))
+                .collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_row(db_name.to_string(), table_name, ids[0], None, &mut app).await;
+        assert_eq!(parse_body::<HashMap<String, Value>>(response).await.get("payload"), Some(&customers[0].1));
+
+        let body = json!({
+            "filters": [{ "column": "payload", "path": "$.address.city", "operator": "eq", "value": "Paris" }],
+            "select": [{ "column": "name" }, { "column": "payload", "path": "$.address.zip", "as": "zip" }, { "column": "payload", "path": "$.tags[0]" }],
+        });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, ids[0]);
+        assert_eq!(rows[0].data, HashMap::from([
+            ("name".to_string(), Value::from("Anna")),
+            ("zip".to_string(), Value::from("75001")),
+            ("$.tags[0]".to_string(), Value::from("vip")),
+        ]));
+
+        // Integers and floats inside documents compare as numbers, documents without the path are
+        // left out
+        let body = json!({
+            "filters": [{ "column": "payload", "path": "$.orders", "operator": "gt", "value": 5 }],
+            "select": [{ "column": "name" }],
+        });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![ids[1], ids[2]]);
+
+        let body = json!({ "filters": [{ "column": "payload", "path": "$.address", "operator": "eq", "value": null }] });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![ids[2], ids[3]]);
+        assert_eq!(rows[1].data.get("payload"), Some(&Value::Null));
+
+        // Queries in a transaction see its own changes
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+        let body = RowUpdateRequestModel {
+            data: HashMap::from([("name".to_string(), Value::from("Bram")), ("payload".to_string(), json!({ "address": { "city": "Paris" } }))]),
+        };
+        let response = request_update_row(db_name.to_string(), table_name.to_string(), ids[1], body, Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = json!({ "filters": [{ "column": "payload", "path": "$.address.city", "operator": "eq", "value": "Paris" }] });
+        let response = request_query(db_name, table_name, body.clone(), Some(transaction), &mut app).await;
+        assert_eq!(parse_body::<QueryResponseModel>(response).await.rows.len(), 2);
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert_eq!(parse_body::<QueryResponseModel>(response).await.rows.len(), 1);
+
+        // Paths only go into JSON columns
+        let body = json!({ "filters": [{ "column": "name", "path": "$.first", "operator": "eq", "value": "Anna" }] });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = json!({ "select": [{ "column": "payload", "path": "address.city" }] });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert!(response.status().is_client_error());
+    }
+
+    #[tokio::test]
+    async fn array_columns() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "articles";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![ColumnCreateRequestModel { name: "tags".to_string(), column_type: "array<strings>".to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel { name: "title".to_string(), column_type: "string".to_string(), ..Default::default() },
+                ColumnCreateRequestModel { name: "tags".to_string(), column_type: "array<string>".to_string(), default: Some(json!([])), ..Default::default() },
+                ColumnCreateRequestModel { name: "scores".to_string(), column_type: "array<array<int>>".to_string(), nullable: true, ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let articles = [
+            json!({ "title": "Rust", "tags": ["code", "systems"], "scores": [[1, 2], [3]] }),
+            json!({ "title": "Bread", "tags": ["food"] }),
+            json!({ "title": "Compilers", "tags": ["code", "theory", "systems"], "scores": [] }),
+        ];
+        let body = RowCreateRequestModel {
+            data: articles.iter().map(|article| serde_json::from_value(article.clone()).unwrap()).collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        // Every element is checked against the element type
+        for tags in [json!(["code", 1]), json!("code"), json!(["code", null])] {
+            let body = RowCreateRequestModel { data: vec![HashMap::from([("title".to_string(), Value::from("Bad")), ("tags".to_string(), tags.clone())])] };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", tags);
+        }
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_row(db_name.to_string(), table_name, ids[0], None, &mut app).await;
+        let row = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(row.get("scores"), Some(&json!([[1, 2], [3]])));
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        assert_eq!(parse_body::<TableResponseModel>(response).await.columns[2].column_type, "array<array<int>>");
+
+        let body = json!({
+            "filters": [{ "column": "tags", "operator": "contains", "value": "systems" }],
+            "select": [{ "column": "title" }, { "column": "tags", "function": "length" }],
+        });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![ids[0], ids[2]]);
+        assert_eq!(rows[1].data.get("length(tags)"), Some(&Value::from(3)));
+
+        let body = json!({ "filters": [{ "column": "scores", "function": "length", "operator": "lt", "value": 1 }] });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![ids[2]]);
+
+        // A row per tag, filtered on the tag itself
+        let body = json!({
+            "unnest": "tags",
+            "filters": [{ "column": "tags", "operator": "ne", "value": "systems" }],
+            "select": [{ "column": "title" }, { "column": "tags", "as": "tag" }],
+        });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        let tags = rows.iter().map(|row| (row.id, row.data.get("tag").unwrap().clone())).collect::<Vec<_>>();
+        assert_eq!(tags, vec![
+            (ids[0], Value::from("code")),
+            (ids[1], Value::from("food")),
+            (ids[2], Value::from("code")),
+            (ids[2], Value::from("theory")),
+        ]);
+
+        for body in [
+            json!({ "unnest": "title" }),
+            json!({ "filters": [{ "column": "title", "operator": "contains", "value": "R" }] }),
+            json!({ "filters": [{ "column": "tags", "operator": "contains", "value": 1 }] }),
+        ] {
+            let response = request_query(db_name, table_name, body.clone(), None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn vector_search() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "documents";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        for (column_type, vector_index) in [("vector<0>", None), ("vector<x>", None), ("array<float>", Some(json!("l2")))] {
+            let body = json!({ "name": table_name, "columns": [{ "name": "embedding", "column_type": column_type, "vector_index": vector_index }] });
+            let response = request_create_table(db_name.to_string(), serde_json::from_value(body).unwrap(), &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", column_type);
+        }
+        let body = json!({
+            "name": table_name,
+            "columns": [
+                { "name": "topic", "column_type": "string" },
+                { "name": "embedding", "column_type": "vector<8>", "nullable": true, "vector_index": "l2" },
+            ],
+        });
+        let response = request_create_table(db_name.to_string(), serde_json::from_value(body).unwrap(), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Points spread over the unit cube, the same ones every run
+        let mut seed: u64 = 42;
+        let mut random = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 40) as f32 / (1u64 << 24) as f32
+        };
+        let vectors: Vec<Vec<f32>> = (0..600).map(|_| (0..8).map(|_| random()).collect()).collect();
+        let body = RowCreateRequestModel {
+            data: vectors
+                .iter()
+                .enumerate()
+                .map(|(i, vector)| HashMap::from([
+                    ("topic".to_string(), Value::from(if i % 2 == 0 { "even" } else { "odd" })),
+                    ("embedding".to_string(), json!(vector)),
+                ]))
+                .collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        for embedding in [json!([1, 2, 3]), json!([0, 0, 0, 0, 0, 0, 0, "x"]), json!([0, 0, 0, 0, 0, 0, 0, 1e39])] {
+            let body = RowCreateRequestModel { data: vec![HashMap::from([("topic".to_string(), Value::from("bad")), ("embedding".to_string(), embedding.clone())])] };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", embedding);
+        }
+        let body = RowCreateRequestModel { data: vec![HashMap::from([("topic".to_string(), Value::from("none"))])] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = request_get_row(db_name.to_string(), table_name, ids[0], None, &mut app).await;
+        let row = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(serde_json::from_value::<Vec<f32>>(row["embedding"].clone()).unwrap(), vectors[0]);
+
+        // Exact searches by every metric
+        let query = vec![0.5f32; 8];
+        let exact = |metric: &str| {
+            let distance = |vector: &Vec<f32>| -> f32 {
+                let dot: f32 = vector.iter().zip(&query).map(|(a, b)| a * b).sum();
+                let norm = |v: &[f32]| v.iter().map(|a| a * a).sum::<f32>().sqrt();
+                match metric {
+                    "cosine" => 1.0 - dot / (norm(vector) * norm(&query)),
+                    "l2" => vector.iter().zip(&query).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt(),
+                    _ => -dot,
+                }
+            };
+            let mut found: Vec<(f32, usize)> = vectors.iter().enumerate().map(|(i, vector)| (distance(vector), i)).collect();
+            found.sort_by(|a, b| a.0.total_cmp(&b.0));
+            found.into_iter().take(10).map(|(_, i)| ids[i]).collect::<Vec<_>>()
+        };
+        for metric in ["cosine", "l2", "dot"] {
+            let body = json!({ "column": "embedding", "vector": query, "k": 10, "metric": metric, "select": [{ "column": "topic" }] });
+            let response = request_nearest(db_name, table_name, body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let rows = parse_body::<NearestResponseModel>(response).await.rows;
+            assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), exact(metric), "Wrong {} neighbours", metric);
+            assert!(rows.windows(2).all(|pair| pair[0].distance <= pair[1].distance));
+            assert_eq!(rows[0].data.len(), 1);
+        }
+
+        // The index is built again on restart, and finds nearly all of the exact neighbours
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let body = json!({ "column": "embedding", "vector": query, "k": 10, "metric": "l2", "approximate": true });
+        let response = request_nearest(db_name, table_name, body.clone(), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let rows = parse_body::<NearestResponseModel>(response).await.rows;
+        let expected = exact("l2");
+        assert!(rows.iter().filter(|row| expected.contains(&row.id)).count() >= 9);
+
+        // Changes show up in the index once committed
+        let nearest = rows[0].id;
+        let response = request_delete_row(db_name.to_string(), table_name.to_string(), nearest, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body_update = RowUpdateRequestModel {
+            data: HashMap::from([("topic".to_string(), Value::from("odd")), ("embedding".to_string(), json!(query))]),
+        };
+        let response = request_update_row(db_name.to_string(), table_name.to_string(), ids[1], body_update, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_nearest(db_name, table_name, body, None, &mut app).await;
+        let rows = parse_body::<NearestResponseModel>(response).await.rows;
+        assert_eq!(rows[0].id, ids[1]);
+        assert_eq!(rows[0].distance, 0.0);
+        assert!(rows.iter().all(|row| row.id != nearest));
+
+        let body = json!({
+            "column": "embedding", "vector": query, "k": 5, "metric": "l2", "approximate": true,
+            "filters": [{ "column": "topic", "operator": "eq", "value": "even" }],
+        });
+        let response = request_nearest(db_name, table_name, body, None, &mut app).await;
+        let rows = parse_body::<NearestResponseModel>(response).await.rows;
+        assert_eq!(rows.len(), 5);
+        assert!(rows.iter().all(|row| row.data.get("topic") == Some(&Value::from("even"))));
+
+        // Only the metric of the index can use it, and the vector has to fit the column
+        for body in [
+            json!({ "column": "embedding", "vector": query, "k": 5, "metric": "cosine", "approximate": true }),
+            json!({ "column": "embedding", "vector": [1, 2], "k": 5, "metric": "l2" }),
+            json!({ "column": "topic", "vector": query, "k": 5, "metric": "l2" }),
+        ] {
+            let response = request_nearest(db_name, table_name, body.clone(), None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn enum_columns() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "tickets";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        for column_type in ["enum()", "enum(open,,closed)", "enum(open,open)", "enum(open"] {
+            let body = TableCreateRequestModel {
+                name: table_name.to_string(),
+                columns: vec![ColumnCreateRequestModel { name: "status".to_string(), column_type: column_type.to_string(), ..Default::default() }],
+                ..Default::default()
+            };
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", column_type);
+        }
+        // Long lists of values are stored too
+        let categories = (0..300).map(|i| format!("category_{}", i)).collect::<Vec<_>>();
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel { name: "status".to_string(), column_type: "enum(open, in_progress, closed)".to_string(), default: Some(Value::from("open")), ..Default::default() },
+                ColumnCreateRequestModel { name: "category".to_string(), column_type: format!("enum({})", categories.join(",")), ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let tickets = [(None, "category_7"), (Some("closed"), "category_299"), (Some("in_progress"), "category_0")];
+        let body = RowCreateRequestModel {
+            data: tickets
+                .iter()
+                .map(|(status, category)| {
+                    let mut row = HashMap::from([("category".to_string(), Value::from(*category))]);
+                    if let Some(status) = status {
+                        row.insert("status".to_string(), Value::from(*status));
+                    }
+                    row
+                })
+                .collect()
+        };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        for status in [Value::from("reopened"), Value::from("Open"), Value::from(0)] {
+            let body = RowCreateRequestModel { data: vec![HashMap::from([("status".to_string(), status.clone()), ("category".to_string(), Value::from("category_1"))])] };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Accepted {}", status);
+        }
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_row(db_name.to_string(), table_name, ids[0], None, &mut app).await;
+        let row = parse_body::<HashMap<String, Value>>(response).await;
+        assert_eq!(row.get("status"), Some(&Value::from("open")));
+        assert_eq!(row.get("category"), Some(&Value::from("category_7")));
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        let columns = parse_body::<TableResponseModel>(response).await.columns;
+        assert_eq!(columns[0].column_type, "enum(open,in_progress,closed)");
+        assert_eq!(columns[1].column_type, format!("enum({})", categories.join(",")));
+
+        // Values order the way the column lists them
+        let body = json!({ "filters": [{ "column": "status", "operator": "lt", "value": "closed" }], "select": [{ "column": "status" }] });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![ids[0], ids[2]]);
+        assert_eq!(rows[1].data.get("status"), Some(&Value::from("in_progress")));
+        let response = request_aggregate(db_name, table_name, "status", "max", None, &mut app).await;
+        assert_eq!(parse_body::<AggregateResponseModel>(response).await.value, Value::from("closed"));
+
+        let body = json!({ "filters": [{ "column": "status", "operator": "eq", "value": "reopened" }] });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn alter_table_columns() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "orders";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = TableCreateRequestModel {
+            name: table_name.to_string(),
+            columns: vec![
+                ColumnCreateRequestModel { name: "item".to_string(), column_type: "string".to_string(), ..Default::default() },
+                ColumnCreateRequestModel { name: "quantity".to_string(), column_type: "int".to_string(), ..Default::default() },
+                ColumnCreateRequestModel { name: "state".to_string(), column_type: "string".to_string(), ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let rows = [json!({ "item": "pen", "quantity": 3, "state": "open" }), json!({ "item": "ink", "quantity": 12, "state": "shipped" })];
+        let body = RowCreateRequestModel { data: rows.iter().map(|row| serde_json::from_value(row.clone()).unwrap()).collect() };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        // Existing rows are backfilled with the default or null, a column without either is refused
+        let column = |name: &str, column_type: &str| ColumnCreateRequestModel { name: name.to_string(), column_type: column_type.to_string(), ..Default::default() };
+        let response = request_add_column(db_name, table_name, column("note", "string"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = request_add_column(db_name, table_name, column("item", "string"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = ColumnCreateRequestModel { default: Some(json!(false)), ..column("paid", "bool") };
+        let response = request_add_column(db_name, table_name, body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = ColumnCreateRequestModel { nullable: true, ..column("note", "string") };
+        let response = request_add_column(db_name, table_name, body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let rename = |name: &str| ColumnUpdateRequestModel { name: Some(name.to_string()), ..Default::default() };
+        let retype = |column_type: &str| ColumnUpdateRequestModel { column_type: Some(column_type.to_string()), ..Default::default() };
+        let response = request_update_column(db_name, table_name, "item", rename("quantity"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = request_update_column(db_name, table_name, "missing", rename("other"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = request_update_column(db_name, table_name, "item", rename("product"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        // Conversions that would lose values are refused and leave the table as it was
+        for (column, column_type) in [("state", "enum(open,closed)"), ("product", "int"), ("quantity", "bool"), ("quantity", "decimal(2,1)")] {
+            let response = request_update_column(db_name, table_name, column, retype(column_type), &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Changed {} to {}", column, column_type);
+        }
+        let response = request_update_column(db_name, table_name, "state", retype("enum(open,shipped,closed)"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_update_column(db_name, table_name, "quantity", retype("decimal(6,2)"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_update_column(db_name, table_name, "paid", retype("string"), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = request_drop_column(db_name, table_name, "note", &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_drop_column(db_name, table_name, "note", &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Tables in use by an open transaction keep their columns until it ends
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+        let response = request_get_row(db_name.to_string(), table_name, ids[0], Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = request_drop_column(db_name, table_name, "paid", &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_end_transaction(db_name, transaction, "rollback", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // New rows take the new columns
+        let body = RowCreateRequestModel { data: vec![serde_json::from_value(json!({ "product": "nib", "quantity": "0.25", "state": "closed" })).unwrap()] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let id = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        let columns = parse_body::<TableResponseModel>(response).await.columns;
+        let columns = columns.iter().map(|column| (column.name.as_str(), column.column_type.as_str())).collect::<Vec<_>>();
+        assert_eq!(columns, vec![("product", "string"), ("quantity", "decimal(6,2)"), ("state", "enum(open,shipped,closed)"), ("paid", "string")]);
+        let expected = [
+            json!({ "product": "pen", "quantity": "3.00", "state": "open", "paid": "false" }),
+            json!({ "product": "ink", "quantity": "12.00", "state": "shipped", "paid": "false" }),
+            json!({ "product": "nib", "quantity": "0.25", "state": "closed", "paid": "false" }),
+        ];
+        for (id, expected) in ids.into_iter().chain([id]).zip(expected) {
+            let response = request_get_row(db_name.to_string(), table_name, id, None, &mut app).await;
+            assert_eq!(serde_json::to_value(parse_body::<HashMap<String, Value>>(response).await).unwrap(), expected);
+        }
+
+        for column in ["product", "quantity", "state"] {
+            let response = request_drop_column(db_name, table_name, column, &mut app).await;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+        }
+        let response = request_drop_column(db_name, table_name, "paid", &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn primary_and_unique_keys() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "users";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let columns = vec![
+            ColumnCreateRequestModel { name: "org".to_string(), column_type: "string".to_string(), ..Default::default() },
+            ColumnCreateRequestModel { name: "name".to_string(), column_type: "string".to_string(), ..Default::default() },
+            ColumnCreateRequestModel { name: "email".to_string(), column_type: "string".to_string(), nullable: true, ..Default::default() },
+        ];
+        let key = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<String>>();
+        // Keys need existing columns, each at most once, and primary key columns can not be null
+        for (primary_key, unique) in [(key(&["email"]), vec![]), (key(&["id"]), vec![]), (key(&["org", "org"]), vec![]), (key(&["org"]), vec![key(&[])])] {
+            let body = TableCreateRequestModel { name: table_name.to_string(), columns: columns.clone(), primary_key, unique };
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        let body = TableCreateRequestModel { name: table_name.to_string(), columns, primary_key: key(&["org", "name"]), unique: vec![key(&["email"])] };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let user = |org: &str, name: &str, email: Value| -> HashMap<String, Value> {
+            serde_json::from_value(json!({ "org": org, "name": name, "email": email })).unwrap()
+        };
+        // Rows with null in a unique column do not collide
+        let body = RowCreateRequestModel { data: vec![user("acme", "ann", json!("ann@acme")), user("acme", "bob", Value::Null), user("beta", "ann", Value::Null)] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        for data in [
+            vec![user("acme", "ann", Value::Null)],
+            vec![user("beta", "bob", json!("ann@acme"))],
+            vec![user("beta", "cid", Value::Null), user("beta", "cid", Value::Null)],
+        ] {
+            let body = RowCreateRequestModel { data };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+        }
+        for data in [user("acme", "ann", Value::Null), user("acme", "bob", json!("ann@acme"))] {
+            let body = RowUpdateRequestModel { data };
+            let response = request_update_row(db_name.to_string(), table_name.to_string(), ids[1], body, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+        }
+        let response = request_get_row_by_key(db_name, table_name, json!({ "org": "beta", "name": "cid" }), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = request_get_row_by_key(db_name, table_name, json!({ "org": "acme" }), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = request_get_row_by_key(db_name, table_name, json!({ "org": "acme", "name": "bob" }), None, &mut app).await;
+        let row = parse_body::<QueryRowModel>(response).await;
+        assert_eq!(row.id, ids[1]);
+        assert_eq!(row.data.get("email"), Some(&Value::Null));
+
+        // A key freed inside a transaction can be taken again, and lookups see the changes of the
+        // transaction
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+        let response = request_delete_row(db_name.to_string(), table_name.to_string(), ids[0], Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let body = RowCreateRequestModel { data: vec![user("acme", "ann", json!("ann@acme"))] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let id = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+        let body = RowCreateRequestModel { data: vec![user("acme", "ann", Value::Null)] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_get_row_by_key(db_name, table_name, json!({ "org": "acme", "name": "ann" }), Some(transaction), &mut app).await;
+        assert_eq!(parse_body::<QueryRowModel>(response).await.id, id);
+        let response = request_get_row_by_key(db_name, table_name, json!({ "org": "acme", "name": "ann" }), None, &mut app).await;
+        assert_eq!(parse_body::<QueryRowModel>(response).await.id, ids[0]);
+        let response = request_end_transaction(db_name, transaction, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Transactions that took the same key side by side: the first to commit wins
+        let mut transactions = Vec::new();
+        for _ in 0..2 {
+            let response = request_begin_transaction(db_name, &mut app).await;
+            let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+            let body = RowCreateRequestModel { data: vec![user("gamma", "dan", Value::Null)] };
+            let response = request_create_row(db_name.to_string(), table_name.to_string(), body, Some(transaction), &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            transactions.push(transaction);
+        }
+        let response = request_end_transaction(db_name, transactions[0], "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = request_end_transaction(db_name, transactions[1], "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = json!({ "filters": [{ "column": "org", "operator": "eq", "value": "gamma" }] });
+        let response = request_query(db_name, table_name, body, None, &mut app).await;
+        assert_eq!(parse_body::<QueryResponseModel>(response).await.rows.len(), 1);
+
+        let rename = ColumnUpdateRequestModel { name: Some("mail".to_string()), ..Default::default() };
+        let response = request_update_column(db_name, table_name, "email", rename, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_drop_column(db_name, table_name, "name", &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        let table = parse_body::<TableResponseModel>(response).await;
+        assert_eq!(table.primary_key, key(&["org", "name"]));
+        assert_eq!(table.unique, vec![key(&["mail"])]);
+        let body = RowCreateRequestModel { data: vec![serde_json::from_value(json!({ "org": "gamma", "name": "eve", "mail": "ann@acme" })).unwrap()] };
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_get_row_by_key(db_name, table_name, json!({ "org": "acme", "name": "ann" }), None, &mut app).await;
+        assert_eq!(parse_body::<QueryRowModel>(response).await.id, id);
+    }
+
+    #[tokio::test]
+    async fn retype_keeps_keys_unique() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let table_name = "prices";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let columns = vec![ColumnCreateRequestModel { name: "amount".to_string(), column_type: "string".to_string(), ..Default::default() }];
+        let body = TableCreateRequestModel { name: table_name.to_string(), columns, unique: vec![vec!["amount".to_string()]], ..Default::default() };
+        let response = request_create_table(db_name.to_string(), body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let data = ["1.50", "1.5"].iter().map(|amount| serde_json::from_value(json!({ "amount": amount })).unwrap()).collect();
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), RowCreateRequestModel { data }, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let ids = parse_body::<RowCreateResponseModel>(response).await.ids;
+
+        // Different strings that become the same decimal would share the key
+        let retype = || ColumnUpdateRequestModel { column_type: Some("decimal(4,2)".to_string()), ..Default::default() };
+        let response = request_update_column(db_name, table_name, "amount", retype(), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_get_table(db_name.to_string(), table_name, &mut app).await;
+        assert_eq!(parse_body::<TableResponseModel>(response).await.columns[0].column_type, "string");
+        for (id, amount) in ids.iter().zip(["1.50", "1.5"]) {
+            let response = request_get_row(db_name.to_string(), table_name, *id, None, &mut app).await;
+            assert_eq!(parse_body::<HashMap<String, Value>>(response).await["amount"], json!(amount));
+        }
+
+        let response = request_delete_row(db_name.to_string(), table_name.to_string(), ids[1], None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_update_column(db_name, table_name, "amount", retype(), &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let data = vec![serde_json::from_value(json!({ "amount": "1.5" })).unwrap()];
+        let response = request_create_row(db_name.to_string(), table_name.to_string(), RowCreateRequestModel { data }, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn foreign_keys() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let column = |name: &str, column_type: &str| ColumnCreateRequestModel { name: name.to_string(), column_type: column_type.to_string(), ..Default::default() };
+        let reference = |name: &str, column_type: &str, table: &str, on_delete: OnDelete| ColumnCreateRequestModel {
+            references: Some(Reference { table: table.to_string(), on_delete }),
+            nullable: on_delete == OnDelete::SetNull,
+            ..column(name, column_type)
+        };
+        let table = |name: &str, columns: Vec<ColumnCreateRequestModel>, primary_key: &[&str]| TableCreateRequestModel {
+            name: name.to_string(),
+            columns,
+            primary_key: primary_key.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        };
+        for body in [
+            table("customers", vec![column("id", "int")], &["id"]),
+            table("reviews", vec![column("text", "string"), reference("customer", "int", "customers", OnDelete::SetNull)], &[]),
+            table("orders", vec![column("id", "int"), reference("customer", "int", "customers", OnDelete::Cascade)], &["id"]),
+            table("invoices", vec![reference("order", "int", "orders", OnDelete::Restrict)], &[]),
+        ] {
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+        // A reference needs a table with a primary key of its type, and set null a nullable column
+        for body in [
+            table("broken", vec![reference("other", "int", "missing", OnDelete::Restrict)], &[]),
+            table("broken", vec![reference("customer", "string", "customers", OnDelete::Restrict)], &[]),
+            table("broken", vec![reference("review", "string", "reviews", OnDelete::Restrict)], &[]),
+            table("broken", vec![ColumnCreateRequestModel { nullable: false, ..reference("customer", "int", "customers", OnDelete::SetNull) }], &[]),
+        ] {
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let rows = |data: Value| RowCreateRequestModel { data: serde_json::from_value(data).unwrap() };
+        let response = request_create_row(db_name.to_string(), "customers".to_string(), rows(json!([{ "id": 1 }, { "id": 2 }, { "id": 3 }])), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = request_create_row(db_name.to_string(), "orders".to_string(), rows(json!([{ "id": 10, "customer": 9 }])), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = rows(json!([{ "id": 10, "customer": 1 }, { "id": 11, "customer": 1 }, { "id": 12, "customer": 2 }]));
+        let response = request_create_row(db_name.to_string(), "orders".to_string(), body, None, &mut app).await;
+        let orders = parse_body::<RowCreateResponseModel>(response).await.ids;
+        let body = RowUpdateRequestModel { data: serde_json::from_value(json!({ "id": 10, "customer": 9 })).unwrap() };
+        let response = request_update_row(db_name.to_string(), "orders".to_string(), orders[0], body, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = rows(json!([{ "text": "good", "customer": 1 }, { "text": "fine", "customer": 2 }]));
+        let response = request_create_row(db_name.to_string(), "reviews".to_string(), body, None, &mut app).await;
+        let reviews = parse_body::<RowCreateResponseModel>(response).await.ids;
+        let response = request_create_row(db_name.to_string(), "invoices".to_string(), rows(json!([{ "order": 12 }])), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // Deleting customer 2 cascades to order 12, which an invoice holds on to
+        let customer = |id: u64| json!({ "id": id });
+        let response = request_get_row_by_key(db_name, "customers", customer(2), None, &mut app).await;
+        let customer_2 = parse_body::<QueryRowModel>(response).await.id;
+        let response = request_delete_row(db_name.to_string(), "customers".to_string(), customer_2, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_get_row(db_name.to_string(), "orders", orders[2], None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = request_get_row(db_name.to_string(), "reviews", reviews[1], None, &mut app).await;
+        assert_eq!(parse_body::<HashMap<String, Value>>(response).await.get("customer"), Some(&json!(2)));
+
+        let response = request_get_row_by_key(db_name, "customers", customer(1), None, &mut app).await;
+        let customer_1 = parse_body::<QueryRowModel>(response).await.id;
+        let response = request_delete_row(db_name.to_string(), "customers".to_string(), customer_1, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_query(db_name, "orders", json!({}), None, &mut app).await;
+        let remaining = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(remaining.iter().map(|row| row.id).collect::<Vec<u64>>(), vec![orders[2]]);
+        let response = request_get_row(db_name.to_string(), "reviews", reviews[0], None, &mut app).await;
+        assert_eq!(parse_body::<HashMap<String, Value>>(response).await.get("customer"), Some(&Value::Null));
+
+        // A customer and its order can go in together, and a delete sees the orders of its
+        // own transaction
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+        let response = request_create_row(db_name.to_string(), "customers".to_string(), rows(json!([{ "id": 4 }])), Some(transaction), &mut app).await;
+        let customer_4 = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+        let response = request_create_row(db_name.to_string(), "orders".to_string(), rows(json!([{ "id": 13, "customer": 4 }])), Some(transaction), &mut app).await;
+        let order = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+        let response = request_create_row(db_name.to_string(), "invoices".to_string(), rows(json!([{ "order": 13 }])), Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = request_delete_row(db_name.to_string(), "customers".to_string(), customer_4, Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_get_row(db_name.to_string(), "orders", order, Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = request_end_transaction(db_name, transaction, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // An order for a customer deleted after it was written can not commit
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+        let response = request_create_row(db_name.to_string(), "orders".to_string(), rows(json!([{ "id": 14, "customer": 3 }])), Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = request_get_row_by_key(db_name, "customers", customer(3), None, &mut app).await;
+        let customer_3 = parse_body::<QueryRowModel>(response).await.id;
+        let response = request_delete_row(db_name.to_string(), "customers".to_string(), customer_3, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = request_end_transaction(db_name, transaction, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        // The type of a referenced key is fixed
+        let change = ColumnUpdateRequestModel { column_type: Some("signed_int".to_string()), ..Default::default() };
+        let response = request_update_column(db_name, "customers", "id", change, &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_get_table(db_name.to_string(), "reviews", &mut app).await;
+        let columns = parse_body::<TableResponseModel>(response).await.columns;
+        assert_eq!(columns[1].references, Some(Reference { table: "customers".to_string(), on_delete: OnDelete::SetNull }));
+        let response = request_create_row(db_name.to_string(), "orders".to_string(), rows(json!([{ "id": 15, "customer": 3 }])), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_delete_row(db_name.to_string(), "orders".to_string(), orders[2], None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn restrict_lets_rows_go_that_the_delete_cascades_to() {
+
+        let db_name = "testDB";
+        let mut app = create_router(Server::default()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "in_memory".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let column = |name: &str| ColumnCreateRequestModel { name: name.to_string(), column_type: "int".to_string(), ..Default::default() };
+        let reference = |name: &str, table: &str, on_delete: OnDelete| ColumnCreateRequestModel {
+            references: Some(Reference { table: table.to_string(), on_delete }),
+            ..column(name)
+        };
+        let table = |name: &str, columns: Vec<ColumnCreateRequestModel>, primary_key: &[&str]| TableCreateRequestModel {
+            name: name.to_string(),
+            columns,
+            primary_key: primary_key.iter().map(|name| name.to_string()).collect(),
+            ..Default::default()
+        };
+        // The orders of a customer are reached before its shipments, which restrict deleting an
+        // order but go along with the customer
+        for body in [
+            table("customers", vec![column("id")], &["id"]),
+            table("orders", vec![column("id"), reference("customer", "customers", OnDelete::Cascade)], &["id"]),
+            table("shipments", vec![reference("order", "orders", OnDelete::Restrict), reference("customer", "customers", OnDelete::Cascade)], &[]),
+        ] {
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let rows = |data: Value| RowCreateRequestModel { data: serde_json::from_value(data).unwrap() };
+        let response = request_create_row(db_name.to_string(), "customers".to_string(), rows(json!([{ "id": 1 }])), None, &mut app).await;
+        let customer = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+        let response = request_create_row(db_name.to_string(), "orders".to_string(), rows(json!([{ "id": 10, "customer": 1 }])), None, &mut app).await;
+        let order = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+        let response = request_create_row(db_name.to_string(), "shipments".to_string(), rows(json!([{ "order": 10, "customer": 1 }])), None, &mut app).await;
+        let shipment = parse_body::<RowCreateResponseModel>(response).await.ids[0];
+
+        let response = request_delete_row(db_name.to_string(), "orders".to_string(), order, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = request_delete_row(db_name.to_string(), "customers".to_string(), customer, None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        for (table, id) in [("orders", order), ("shipments", shipment)] {
+            let response = request_get_row(db_name.to_string(), table, id, None, &mut app).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_commit_is_undone() {
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let config = EngineConfig::new(data_dir.path().to_path_buf());
+        let db_name = "testDB";
+        let mut app = create_router(Server::load(config.clone()).unwrap()).into_service();
+
+        let body = DatabaseCreateRequestModel {
+            name: db_name.to_string(),
+            datastore: "file_based".to_string(),
+            keep_schema: false,
+        };
+        let response = request_create_database(body, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        // The name fits in the key of the table, but not in the keys of its rows, so the tree
+        // fails to store them after the rows of the other table went in
+        let long_name = "b".repeat(BTREE_MAX_KEY_SIZE - 10);
+        for name in ["a", long_name.as_str()] {
+            let columns = vec![ColumnCreateRequestModel { name: "x".to_string(), column_type: "int".to_string(), ..Default::default() }];
+            let body = TableCreateRequestModel { name: name.to_string(), columns, ..Default::default() };
+            let response = request_create_table(db_name.to_string(), body, &mut app).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+        let row = |x: u64| RowCreateRequestModel { data: vec![serde_json::from_value(json!({ "x": x })).unwrap()] };
+        let response = request_create_row(db_name.to_string(), "a".to_string(), row(1), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = request_begin_transaction(db_name, &mut app).await;
+        let transaction = parse_body::<TransactionResponseModel>(response).await.id;
+        let response = request_create_row(db_name.to_string(), "a".to_string(), row(2), Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = request_create_row(db_name.to_string(), long_name.clone(), row(3), Some(transaction), &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = request_end_transaction(db_name, transaction, "commit", &mut app).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = request_query(db_name, "a", json!({}), None, &mut app).await;
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.iter().map(|row| row.data["x"].clone()).collect::<Vec<Value>>(), vec![json!(1)]);
+        let response = request_create_row(db_name.to_string(), "a".to_string(), row(4), None, &mut app).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // The log holds the commits that made it, and nothing of the one that failed
+        drop(app);
+        let mut app = create_router(Server::load(config).unwrap()).into_service();
+        let response = request_query(db_name, "a", json!({}), None, &mut app).await;
+        let rows = parse_body::<QueryResponseModel>(response).await.rows;
+        assert_eq!(rows.iter().map(|row| row.data["x"].clone()).collect::<Vec<Value>>(), vec![json!(1), json!(4)]);
+    }
+
+    #[test]
+    fn commit_does_not_wait_for_a_read() {
+        let database = Database::new("testDB".to_string(), DatastoreVariants::InMemory, false, &EngineConfig::default(), 0).unwrap();
+        let table = Table::with_keys("a".to_string(), vec![Column::new("x".to_string(), ColumnTypes::Int)], Keys::default()).unwrap();
+        database.add_table(table).unwrap();
+        let row_id = database.add_rows("a", vec![HashMap::from([("x".to_string(), json!(1))])], None).unwrap()[0];
+
+        let (started, scanning) = mpsc::channel();
+        let (committed, finished) = mpsc::channel();
+        thread::scope(|scope| {
+            let database = &database;
+            let reader = scope.spawn(move || {
+                database.read("a", None, |table, datastore| {
+                    started.send(()).unwrap();
+                    // The scan is held up until the commit is done, or long enough for it to fail
+                    let waited = finished.recv_timeout(Duration::from_secs(10)).is_ok();
+                    Ok((waited, table.get_row(datastore, row_id)?))
+                })
+            });
+            scanning.recv().unwrap();
+            database.update_row("a", HashMap::from([("x".to_string(), json!(2))]), row_id, None).unwrap();
+            committed.send(()).unwrap();
+            let (waited, row) = reader.join().unwrap().unwrap();
+            assert!(waited);
+            // The read still sees the row as it was when it started
+            assert_eq!(row["x"], json!(1));
+        });
+        assert_eq!(database.get_row("a", row_id, None).unwrap()["x"], json!(2));
     }
-}
\ No newline at end of file
+}
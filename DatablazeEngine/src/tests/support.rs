@@ -1,10 +1,10 @@
-use std::collections::HashMap;
-use axum::{body::Body, http::{Request}, Router};
+use axum::{body::Body, http::{Request}};
 use axum::response::Response;
 use axum::routing::RouterIntoService;
-use serde_json::Value;
 use tower::{Service, ServiceExt};
-use crate::network::model::{DatabaseCreateRequestModel, RowCreateRequestModel, RowUpdateRequestModel, TableCreateRequestModel};
+use crate::database::transaction::TransactionId;
+use crate::datastore::datastore::RowId;
+use crate::network::model::{ColumnCreateRequestModel, ColumnUpdateRequestModel, DatabaseCreateRequestModel, RowCreateRequestModel, RowUpdateRequestModel, TableCreateRequestModel};
 
 pub async fn parse_body<T>(response: Response) -> T where T: serde::de::DeserializeOwned {
     
@@ -75,15 +75,57 @@ pub async fn request_get_table(database_name: String, table_name: &str, app: &mu
         .unwrap()
 }
 
+pub async fn request_add_column(database_name: &str, table_name: &str, body: ColumnCreateRequestModel, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/table/{}/column", database_name, table_name))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap())).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+pub async fn request_update_column(database_name: &str, table_name: &str, column_name: &str, body: ColumnUpdateRequestModel, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/table/{}/column?name={}", database_name, table_name, column_name))
+        .method("PATCH")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_string(&body).unwrap())).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+pub async fn request_drop_column(database_name: &str, table_name: &str, column_name: &str, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/table/{}/column?name={}", database_name, table_name, column_name))
+        .method("DELETE")
+        .body(Body::default()).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
 pub async fn request_create_row(
     
     database_name: String,
     table_name: String,
     body: RowCreateRequestModel,
+    transaction: Option<TransactionId>,
     app: &mut RouterIntoService<Body>) -> Response {
 
     let request = Request::builder()
-        .uri(format!("/api/database/{}/table/{}/row", database_name, table_name))
+        .uri(format!("/api/database/{}/table/{}/row?{}", database_name, table_name, transaction_query(transaction)))
         .method("POST")
         .header("content-type", "application/json")
         .body(Body::from(serde_json::to_string(&body).unwrap())).unwrap();
@@ -99,12 +141,13 @@ pub async fn request_update_row(
 
     database_name: String,
     table_name: String,
-    index: u64,
+    row_id: RowId,
     body: RowUpdateRequestModel,
+    transaction: Option<TransactionId>,
     app: &mut RouterIntoService<Body>) -> Response {
 
     let request = Request::builder()
-        .uri(format!("/api/database/{}/table/{}/row?index={}", database_name, table_name, index))
+        .uri(format!("/api/database/{}/table/{}/row?id={}&{}", database_name, table_name, row_id, transaction_query(transaction)))
         .method("PATCH")
         .header("content-type", "application/json")
         .body(Body::from(serde_json::to_string(&body).unwrap())).unwrap();
@@ -120,11 +163,12 @@ pub async fn request_delete_row(
 
     database_name: String,
     table_name: String,
-    index: u64,
+    row_id: RowId,
+    transaction: Option<TransactionId>,
     app: &mut RouterIntoService<Body>) -> Response {
 
     let request = Request::builder()
-        .uri(format!("/api/database/{}/table/{}/row?index={}", database_name, table_name, index))
+        .uri(format!("/api/database/{}/table/{}/row?id={}&{}", database_name, table_name, row_id, transaction_query(transaction)))
         .method("DELETE")
         .body(Body::default()).unwrap();
     ServiceExt::<Request<Body>>::ready(app)
@@ -136,9 +180,9 @@ pub async fn request_delete_row(
 }
 
 
-pub async fn request_get_row(database_name: String, table_name: &str, row: u64, app: &mut RouterIntoService<Body>) -> Response {
+pub async fn request_get_row(database_name: String, table_name: &str, row_id: RowId, transaction: Option<TransactionId>, app: &mut RouterIntoService<Body>) -> Response {
     let request = Request::builder()
-        .uri(format!("/api/database/{}/table/{}/row?index={}", database_name, table_name, row))
+        .uri(format!("/api/database/{}/table/{}/row?id={}&{}", database_name, table_name, row_id, transaction_query(transaction)))
         .method("GET")
         .body(Body::default()).unwrap();
     ServiceExt::<Request<Body>>::ready(app)
@@ -147,4 +191,94 @@ pub async fn request_get_row(database_name: String, table_name: &str, row: u64,
         .call(request)
         .await
         .unwrap()
+}
+
+/// Get a row by its primary key, the body holding the data of every key column by name
+pub async fn request_get_row_by_key(database_name: &str, table_name: &str, key: serde_json::Value, transaction: Option<TransactionId>, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/table/{}/row/key?{}", database_name, table_name, transaction_query(transaction)))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::json!({ "key": key }).to_string())).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+/// Aggregate a column, the function being count, sum, min or max
+pub async fn request_aggregate(database_name: &str, table_name: &str, column: &str, function: &str, transaction: Option<TransactionId>, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/table/{}/aggregate?column={}&function={}&{}", database_name, table_name, column, function, transaction_query(transaction)))
+        .method("GET")
+        .body(Body::default()).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+/// Query a table, the body holding the filters and the select list
+pub async fn request_query(database_name: &str, table_name: &str, body: serde_json::Value, transaction: Option<TransactionId>, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/table/{}/query?{}", database_name, table_name, transaction_query(transaction)))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string())).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+/// Nearest neighbour search, the body holding the column, vector, k and metric
+pub async fn request_nearest(database_name: &str, table_name: &str, body: serde_json::Value, transaction: Option<TransactionId>, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/table/{}/nearest?{}", database_name, table_name, transaction_query(transaction)))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string())).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+pub async fn request_begin_transaction(database_name: &str, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/transaction", database_name))
+        .method("POST")
+        .body(Body::default()).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+/// End a transaction, the action being either commit or rollback
+pub async fn request_end_transaction(database_name: &str, transaction: TransactionId, action: &str, app: &mut RouterIntoService<Body>) -> Response {
+    let request = Request::builder()
+        .uri(format!("/api/database/{}/transaction/{}/{}", database_name, transaction, action))
+        .method("POST")
+        .body(Body::default()).unwrap();
+    ServiceExt::<Request<Body>>::ready(app)
+        .await
+        .unwrap()
+        .call(request)
+        .await
+        .unwrap()
+}
+
+fn transaction_query(transaction: Option<TransactionId>) -> String {
+    transaction.map(|id| format!("transaction={}", id)).unwrap_or_default()
 }
\ No newline at end of file
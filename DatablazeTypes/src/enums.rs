@@ -1,4 +1,12 @@
-﻿use strum_macros::{Display, EnumString};
+﻿use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use strum::ParseError;
+use strum_macros::{Display, EnumString};
+use uuid::Uuid;
 
 
 #[derive(Copy, Clone, Display, EnumString, Debug)]
@@ -9,24 +17,232 @@ pub enum DatastoreVariants {
     FileBased
 }
 
-#[derive(Copy, Clone, Display, EnumString, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ColumnTypes {
-    #[strum(serialize = "string")]
     String,
-    #[strum(serialize = "int")]
-    Int
+    Int,
+    SignedInt,
+    Float,
+    Bool,
+    /// Point in time, sent with any timezone and kept in UTC
+    Timestamp,
+    Date,
+    Uuid,
+    /// Binary data, sent as base64
+    Bytes,
+    /// Exact number of at most `precision` digits, `scale` of them after the decimal point.
+    /// Sent as a string, for example decimal(10,2).
+    Decimal { precision: u32, scale: u32 },
+    /// Any JSON document
+    Json,
+    /// List of values of one type, for example array<int>. Elements can not be null.
+    Array(Box<ColumnTypes>),
+    /// Embedding of exactly this many 32 bit floats, for example vector<384>
+    Vector(u32),
+    /// One of a fixed list of strings, for example enum(active,blocked). Stored as the position
+    /// of the string in the list, which is also the order of the values.
+    Enum(Vec<String>),
+}
+
+/// Most digits a decimal column can hold
+pub const DECIMAL_MAX_PRECISION: u32 = 28;
+/// Most floats a vector column can hold
+pub const VECTOR_MAX_DIMENSION: u32 = 16384;
+/// Most values an enum column can have, so every value fits a 16 bit code
+pub const ENUM_MAX_VALUES: usize = 1 << 16;
+
+impl fmt::Display for ColumnTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColumnTypes::String => write!(f, "string"),
+            ColumnTypes::Int => write!(f, "int"),
+            ColumnTypes::SignedInt => write!(f, "signed_int"),
+            ColumnTypes::Float => write!(f, "float"),
+            ColumnTypes::Bool => write!(f, "bool"),
+            ColumnTypes::Timestamp => write!(f, "timestamp"),
+            ColumnTypes::Date => write!(f, "date"),
+            ColumnTypes::Uuid => write!(f, "uuid"),
+            ColumnTypes::Bytes => write!(f, "bytes"),
+            ColumnTypes::Decimal { precision, scale } => write!(f, "decimal({},{})", precision, scale),
+            ColumnTypes::Json => write!(f, "json"),
+            ColumnTypes::Array(element_type) => write!(f, "array<{}>", element_type),
+            ColumnTypes::Vector(dimension) => write!(f, "vector<{}>", dimension),
+            ColumnTypes::Enum(values) => write!(f, "enum({})", values.join(",")),
+        }
+    }
+}
+
+impl FromStr for ColumnTypes {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let column_type = match s {
+            "string" => ColumnTypes::String,
+            "int" => ColumnTypes::Int,
+            "signed_int" => ColumnTypes::SignedInt,
+            "float" => ColumnTypes::Float,
+            "bool" => ColumnTypes::Bool,
+            "timestamp" => ColumnTypes::Timestamp,
+            "date" => ColumnTypes::Date,
+            "uuid" => ColumnTypes::Uuid,
+            "bytes" => ColumnTypes::Bytes,
+            "json" => ColumnTypes::Json,
+            _ if s.starts_with("array<") => {
+                let element_type = s
+                    .strip_prefix("array<")
+                    .and_then(|rest| rest.strip_suffix('>'))
+                    .ok_or(ParseError::VariantNotFound)?;
+                ColumnTypes::Array(Box::new(ColumnTypes::from_str(element_type)?))
+            }
+            // Values can not be empty, repeat or hold a comma or parenthesis
+            _ if s.starts_with("enum(") => {
+                let values: Vec<String> = s
+                    .strip_prefix("enum(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .ok_or(ParseError::VariantNotFound)?
+                    .split(',')
+                    .map(|value| value.trim().to_string())
+                    .collect();
+                let invalid = |value: &String| value.is_empty() || value.contains(['(', ')']);
+                if values.len() > ENUM_MAX_VALUES || values.iter().any(invalid) {
+                    return Err(ParseError::VariantNotFound);
+                }
+                if values.iter().enumerate().any(|(i, value)| values[..i].contains(value)) {
+                    return Err(ParseError::VariantNotFound);
+                }
+                ColumnTypes::Enum(values)
+            }
+            _ if s.starts_with("vector<") => {
+                let dimension = s
+                    .strip_prefix("vector<")
+                    .and_then(|rest| rest.strip_suffix('>'))
+                    .and_then(|dimension| dimension.parse().ok())
+                    .ok_or(ParseError::VariantNotFound)?;
+                if dimension == 0 || dimension > VECTOR_MAX_DIMENSION {
+                    return Err(ParseError::VariantNotFound);
+                }
+                ColumnTypes::Vector(dimension)
+            }
+            _ => {
+                let parameters = s
+                    .strip_prefix("decimal(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .and_then(|parameters| parameters.split_once(','))
+                    .ok_or(ParseError::VariantNotFound)?;
+                let precision = parameters.0.trim().parse().map_err(|_| ParseError::VariantNotFound)?;
+                let scale = parameters.1.trim().parse().map_err(|_| ParseError::VariantNotFound)?;
+                if precision == 0 || precision > DECIMAL_MAX_PRECISION || scale > precision {
+                    return Err(ParseError::VariantNotFound);
+                }
+                ColumnTypes::Decimal { precision, scale }
+            }
+        };
+        Ok(column_type)
+    }
 }
 
 #[derive(Clone, Display, EnumString, Debug)]
 pub enum ColumnData {
     String(String),
-    Int(u64)
+    Int(u64),
+    SignedInt(i64),
+    Float(f64),
+    Bool(bool),
+    Timestamp(DateTime<Utc>),
+    Date(NaiveDate),
+    Uuid(Uuid),
+    Bytes(Vec<u8>),
+    Decimal(Decimal),
+    Json(Value),
+    Array(Vec<ColumnData>),
+    Vector(Vec<f32>),
+    /// Position of the value in the list of an enum column
+    Enum(u16),
+    /// No data, only valid in nullable columns
+    Null
 }
 impl ColumnData {
-    pub fn validate(&self, data: ColumnTypes) -> bool {
+    pub fn validate(&self, data: &ColumnTypes) -> bool {
         match data {
             ColumnTypes::String => matches!(self, ColumnData::String(_)),
             ColumnTypes::Int => matches!(self, ColumnData::Int(_)),
+            ColumnTypes::SignedInt => matches!(self, ColumnData::SignedInt(_)),
+            ColumnTypes::Float => matches!(self, ColumnData::Float(_)),
+            ColumnTypes::Bool => matches!(self, ColumnData::Bool(_)),
+            ColumnTypes::Timestamp => matches!(self, ColumnData::Timestamp(_)),
+            ColumnTypes::Date => matches!(self, ColumnData::Date(_)),
+            ColumnTypes::Uuid => matches!(self, ColumnData::Uuid(_)),
+            ColumnTypes::Bytes => matches!(self, ColumnData::Bytes(_)),
+            ColumnTypes::Decimal { precision, scale } => match self {
+                ColumnData::Decimal(decimal) => fits_decimal(decimal, *precision, *scale),
+                _ => false,
+            },
+            ColumnTypes::Json => matches!(self, ColumnData::Json(_)),
+            ColumnTypes::Array(element_type) => match self {
+                ColumnData::Array(elements) => elements.iter().all(|element| element.validate(element_type)),
+                _ => false,
+            },
+            ColumnTypes::Enum(values) => matches!(self, ColumnData::Enum(code) if (*code as usize) < values.len()),
+            ColumnTypes::Vector(dimension) => match self {
+                ColumnData::Vector(vector) => vector.len() == *dimension as usize && vector.iter().all(|value| value.is_finite()),
+                _ => false,
+            },
         }
     }
+
+    /// Order data of the same kind, None for data of different kinds, nulls and NaN. Parts of
+    /// JSON documents order when they are numbers, strings or booleans, and are otherwise only
+    /// equal to themselves.
+    pub fn compare(&self, other: &ColumnData) -> Option<Ordering> {
+        match (self, other) {
+            (ColumnData::String(a), ColumnData::String(b)) => Some(a.cmp(b)),
+            (ColumnData::Int(a), ColumnData::Int(b)) => Some(a.cmp(b)),
+            (ColumnData::SignedInt(a), ColumnData::SignedInt(b)) => Some(a.cmp(b)),
+            (ColumnData::Float(a), ColumnData::Float(b)) => a.partial_cmp(b),
+            (ColumnData::Bool(a), ColumnData::Bool(b)) => Some(a.cmp(b)),
+            (ColumnData::Timestamp(a), ColumnData::Timestamp(b)) => Some(a.cmp(b)),
+            (ColumnData::Date(a), ColumnData::Date(b)) => Some(a.cmp(b)),
+            (ColumnData::Uuid(a), ColumnData::Uuid(b)) => Some(a.cmp(b)),
+            (ColumnData::Bytes(a), ColumnData::Bytes(b)) => Some(a.cmp(b)),
+            (ColumnData::Decimal(a), ColumnData::Decimal(b)) => Some(a.cmp(b)),
+            (ColumnData::Json(a), ColumnData::Json(b)) => compare_json(a, b),
+            // Element by element, a shorter array orders before a longer one it starts
+            (ColumnData::Array(a), ColumnData::Array(b)) => {
+                for (a, b) in a.iter().zip(b) {
+                    match a.compare(b)? {
+                        Ordering::Equal => {}
+                        ordering => return Some(ordering),
+                    }
+                }
+                Some(a.len().cmp(&b.len()))
+            }
+            (ColumnData::Vector(a), ColumnData::Vector(b)) => a.partial_cmp(b),
+            (ColumnData::Enum(a), ColumnData::Enum(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+// At most `scale` digits after the decimal point and `precision` digits in total
+fn fits_decimal(decimal: &Decimal, precision: u32, scale: u32) -> bool {
+    if decimal.scale() > scale {
+        return false;
+    }
+    let mut rescaled = *decimal;
+    rescaled.rescale(scale);
+    rescaled.mantissa().unsigned_abs() < 10u128.pow(precision)
+}
+
+fn compare_json(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
 }
\ No newline at end of file
@@ -1,2 +0,0 @@
-﻿pub mod node;
-mod btree;
\ No newline at end of file
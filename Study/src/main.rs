@@ -1,9 +1,7 @@
-﻿use crate::btree::node::*;
-
-mod btree;
+﻿use datablaze_btree::node::*;
 
 fn main() {
-    let node_1_max = 4 + 1 * 8 + 1 * 2 + 4 + BTREE_MAX_KEY_SIZE + BTREE_MAX_VAL_SIZE;
+    let node_1_max = BNODE_HEADER + 8 + 2 + 4 + BTREE_MAX_KEY_SIZE + BTREE_MAX_LEAF_VAL_SIZE;
     assert!(node_1_max <= BTREE_PAGE_SIZE);
 
     let mut node = BNode::new(vec![0; BTREE_PAGE_SIZE]);